@@ -12,6 +12,7 @@ pub enum Id {
     ErrorPopup,
     LoginPopup,
     DownloadPopup,
+    LogSearchPopup,
     SystemSelectPopup,
     JobFilterPopup,
     FileView,
@@ -32,6 +32,7 @@ pub enum LoginPopupMsg {
     Opened,
     Closed,
     LoginDone(String, String),
+    DeviceLogin,
 }
 #[derive(Debug, PartialEq)]
 pub enum DownloadPopupMsg {
@@ -40,6 +41,12 @@ pub enum DownloadPopupMsg {
     Closed,
 }
 #[derive(Debug, PartialEq)]
+pub enum LogSearchPopupMsg {
+    Opened,
+    Closed,
+    Search(String),
+}
+#[derive(Debug, PartialEq)]
 pub enum SystemSelectMsg {
     Opened(Vec<System>),
     Closed,
@@ -56,6 +63,7 @@ pub enum JobFilterPopupMsg {
 #[derive(Debug, PartialEq)]
 pub enum CscsMsg {
     Login(String, String),
+    DeviceLogin,
     SelectSystem,
     SystemSelected(String),
 }
@@ -66,6 +74,7 @@ pub enum JobMsg {
     Details(JobDetail),
     GetDetails(JobId),
     Cancel(JobId),
+    DownloadLog(PathBuf),
     Switch,
     Close,
 }
@@ -81,7 +90,6 @@ pub enum StatusMsg {
     #[allow(dead_code)]
     Progress(String, usize),
     Info(String),
-    #[allow(dead_code)]
     Warning(String),
 }
 
@@ -93,6 +101,7 @@ pub enum Msg {
     ErrorPopup(ErrorPopupMsg),
     LoginPopup(LoginPopupMsg),
     DownloadPopup(DownloadPopupMsg),
+    LogSearchPopup(LogSearchPopupMsg),
     SystemSelectPopup(SystemSelectMsg),
     JobFilterPopup(JobFilterPopupMsg),
     Error(String),
@@ -1,4 +1,5 @@
 use eyre::{Context, Report};
+use openidconnect::core::CoreDeviceAuthorizationResponse;
 use tokio::sync::mpsc;
 use tuirealm::{
     application::Application,
@@ -15,21 +16,22 @@ use crate::{
     app::{
         ids::Id,
         messages::{
-            CscsMsg, DownloadPopupMsg, ErrorPopupMsg, InfoPopupMsg, JobFilterPopupMsg, JobMsg, LoginPopupMsg, MenuMsg,
-            Msg, StatusMsg, SystemSelectMsg, View,
+            CscsMsg, DownloadPopupMsg, ErrorPopupMsg, InfoPopupMsg, JobFilterPopupMsg, JobMsg, LogSearchPopupMsg,
+            LoginPopupMsg, MenuMsg, Msg, StatusMsg, SystemSelectMsg, View,
         },
-        user_events::{CscsEvent, StatusEvent, UserEvent},
+        user_events::{CscsEvent, JobEvent, StatusEvent, UserEvent},
     },
     components::{
         context_menu::ContextMenu, download_popup::DownloadTargetInput, error_popup::ErrorPopup, info_popup::InfoPopup,
-        job_status_filter_popup::JobStatusFilterPopup, login_popup::LoginPopup, resource_usage::ResourceUsage,
-        system_select_popup::SystemSelectPopup, workload_details::WorkloadDetails, workload_list::WorkloadList,
-        workload_log::WorkloadLog,
+        job_status_filter_popup::JobStatusFilterPopup, log_search_popup::LogSearchInput, login_popup::LoginPopup,
+        resource_usage::ResourceUsage, system_select_popup::SystemSelectPopup, workload_details::WorkloadDetails,
+        workload_list::WorkloadList, workload_log::WorkloadLog,
     },
     config::Config,
     cscs::{
         api_client::types::JobStatus,
-        handlers::{cscs_login, cscs_system_set, get_available_compute_platforms},
+        handlers::{cscs_login, cscs_set_default_platform, cscs_system_set},
+        oauth2::start_cscs_device_login,
         ports::{BackgroundTask, JobLogAction, JobResourceUsageAction},
     },
     trace_dbg,
@@ -58,6 +60,9 @@ where
     /// Triggers async request to select current system
     pub select_system_tx: mpsc::Sender<()>,
 
+    /// Hands a started device login over to be finished in the background
+    pub device_login_tx: mpsc::Sender<(CoreDeviceAuthorizationResponse, String)>,
+
     /// Triggers watching job logs
     /// sending None stops watching
     pub job_log_tx: mpsc::Sender<JobLogAction>,
@@ -86,6 +91,7 @@ where
         terminal: T,
         error_tx: mpsc::Sender<String>,
         select_system_tx: mpsc::Sender<()>,
+        device_login_tx: mpsc::Sender<(CoreDeviceAuthorizationResponse, String)>,
         job_log_tx: mpsc::Sender<JobLogAction>,
         job_filter_tx: mpsc::Sender<Vec<JobStatus>>,
         job_resource_usage_tx: mpsc::Sender<JobResourceUsageAction>,
@@ -100,6 +106,7 @@ where
             current_view: View::default(),
             error_tx,
             select_system_tx,
+            device_login_tx,
             job_log_tx,
             job_filter_tx,
             job_resource_usage_tx,
@@ -161,6 +168,10 @@ where
                         let popup = draw_area_in_absolute_fixed_height(f.area(), 10, 3);
                         f.render_widget(Clear, popup);
                         app.view(&Id::JobFilterPopup, f, popup);
+                    } else if app.mounted(&Id::LogSearchPopup) {
+                        let popup = draw_area_in_absolute_fixed_height(f.area(), 10, 3);
+                        f.render_widget(Clear, popup);
+                        app.view(&Id::LogSearchPopup, f, popup);
                     }
                 })
                 .is_ok()
@@ -197,6 +208,10 @@ where
                 assert!(self.app.umount(&Id::LoginPopup).is_ok());
                 Some(Msg::Cscs(CscsMsg::Login(client_id, client_secret)))
             }
+            LoginPopupMsg::DeviceLogin => {
+                assert!(self.app.umount(&Id::LoginPopup).is_ok());
+                Some(Msg::Cscs(CscsMsg::DeviceLogin))
+            }
         }
     }
     fn handle_system_select_popup_msg(&mut self, msg: SystemSelectMsg) -> Option<Msg> {
@@ -293,6 +308,32 @@ where
             }
         }
     }
+    fn handle_log_search_popup_msg(&mut self, msg: LogSearchPopupMsg) -> Option<Msg> {
+        match msg {
+            LogSearchPopupMsg::Opened => {
+                if self.app.mounted(&Id::LogSearchPopup) {
+                    assert!(self.app.umount(&Id::LogSearchPopup).is_ok());
+                }
+                assert!(
+                    self.app
+                        .mount(Id::LogSearchPopup, Box::new(LogSearchInput::new()), vec![])
+                        .is_ok()
+                );
+                assert!(self.app.active(&Id::LogSearchPopup).is_ok());
+                None
+            }
+            LogSearchPopupMsg::Search(query) => {
+                assert!(self.app.umount(&Id::LogSearchPopup).is_ok());
+                assert!(self.app.active(&Id::WorkloadLogs).is_ok());
+                Some(Msg::CreateEvent(UserEvent::Job(JobEvent::SearchLog(query))))
+            }
+            LogSearchPopupMsg::Closed => {
+                assert!(self.app.umount(&Id::LogSearchPopup).is_ok());
+                assert!(self.app.active(&Id::WorkloadLogs).is_ok());
+                None
+            }
+        }
+    }
     fn handle_job_filter_popup_msg(&mut self, msg: JobFilterPopupMsg) -> Option<Msg> {
         match msg {
             JobFilterPopupMsg::Opened => {
@@ -465,6 +506,28 @@ where
                 });
                 None
             }
+            JobMsg::DownloadLog(remote) => {
+                let local = match (std::env::current_dir(), remote.file_name()) {
+                    (Ok(dir), Some(name)) => dir.join(name),
+                    _ => return Some(Msg::Error("couldn't determine download path for log".to_owned())),
+                };
+                let background_tx = self.background_task_tx.clone();
+                let event_tx = self.user_event_tx.clone();
+                tokio::spawn(async move {
+                    event_tx
+                        .send(UserEvent::Status(StatusEvent::Info(format!(
+                            "downloading log to {}...",
+                            local.display()
+                        ))))
+                        .await
+                        .unwrap();
+                    background_tx
+                        .send(BackgroundTask::DownloadFile(remote, local))
+                        .await
+                        .unwrap();
+                });
+                None
+            }
             JobMsg::Switch => {
                 let job_log_tx = self.job_log_tx.clone();
                 tokio::spawn(async move {
@@ -550,45 +613,21 @@ where
                 Msg::InfoPopup(popup_msg) => self.handle_info_popup_msg(popup_msg),
                 Msg::DownloadPopup(popup_msg) => self.handle_download_popup_msg(popup_msg),
                 Msg::JobFilterPopup(popup_msg) => self.handle_job_filter_popup_msg(popup_msg),
+                Msg::LogSearchPopup(popup_msg) => self.handle_log_search_popup_msg(popup_msg),
                 Msg::Cscs(CscsMsg::Login(client_id, client_secret)) => {
                     let event_tx = self.user_event_tx.clone();
                     let error_tx = self.error_tx.clone();
                     tokio::spawn(async move {
-                        match cscs_login(client_id, client_secret).await {
+                        let result = match Config::new() {
+                            Ok(config) => cscs_login(&config, client_id, client_secret).await,
+                            Err(e) => Err(e),
+                        };
+                        match result {
                             Ok(_) => {
-                                let mut config = match Config::new() {
-                                    Ok(config) => config,
-                                    Err(e) => {
-                                        error_tx
-                                            .send(format!(
-                                                "{:?}",
-                                                Err::<(), Report>(e).wrap_err("Couldn't create config object")
-                                            ))
-                                            .await
-                                            .unwrap();
-                                        return;
-                                    }
-                                };
-                                let source = config.value_source("cscs.current_platform");
-                                if !source.1 && !source.2 {
-                                    // don't override platform if it's already set
-                                    if let Ok(available_platforms) = get_available_compute_platforms().await
-                                        && !available_platforms.is_empty()
-                                        && let Err(e) = config.set(
-                                            "cscs.current_platform",
-                                            available_platforms[0].to_string(),
-                                            true,
-                                        )
-                                    {
-                                        error_tx
-                                            .send(format!(
-                                                "{:?}",
-                                                Err::<(), Report>(e).wrap_err("Couldn't set currnt platform")
-                                            ))
-                                            .await
-                                            .unwrap();
-                                        return;
-                                    }
+                                // don't override platform if it's already set
+                                if let Err(e) = cscs_set_default_platform().await {
+                                    error_tx.send(format!("{:?}", Err::<(), Report>(e))).await.unwrap();
+                                    return;
                                 }
                                 event_tx.send(UserEvent::Cscs(CscsEvent::LoggedIn)).await.unwrap()
                             }
@@ -603,6 +642,27 @@ where
                     });
                     None
                 }
+                Msg::Cscs(CscsMsg::DeviceLogin) => {
+                    let device_login_tx = self.device_login_tx.clone();
+                    let error_tx = self.error_tx.clone();
+                    tokio::spawn(async move {
+                        let result = match Config::new().and_then(|config| config.values.current_site()) {
+                            Ok(site) => start_cscs_device_login(&site).await,
+                            Err(e) => Err(e),
+                        };
+                        match result {
+                            Ok(details) => device_login_tx.send(details).await.unwrap(),
+                            Err(e) => error_tx
+                                .send(format!(
+                                    "{:?}",
+                                    Err::<(), Report>(e).wrap_err("couldn't start device login")
+                                ))
+                                .await
+                                .unwrap(),
+                        }
+                    });
+                    None
+                }
                 Msg::Cscs(CscsMsg::SelectSystem) => {
                     let system_select_tx = self.select_system_tx.clone();
                     tokio::spawn(async move {
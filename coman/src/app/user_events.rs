@@ -1,14 +1,16 @@
+use std::path::PathBuf;
+
 use crate::{
     app::messages::View,
     cli::rpc::ResourceUsage,
-    cscs::api_client::types::{Job, JobDetail, PathEntry, System},
+    cscs::api_client::types::{Job, JobDetail, LogStream, PathEntry, System},
 };
 
 #[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord)]
 pub enum CscsEvent {
     LoggedIn,
     GotWorkloadData(Vec<Job>),
-    GotJobLog(String),
+    GotJobLog(PathBuf, LogStream, String), // log file, its stream, newly written content
     GotJobDetails(JobDetail),
     GotJobResourceUsage(ResourceUsage),
     SelectSystemList(Vec<System>),
@@ -33,6 +35,7 @@ pub enum StatusEvent {
 #[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord)]
 pub enum JobEvent {
     Cancel,
+    SearchLog(String),
 }
 
 #[derive(Debug, Eq, Clone, PartialOrd, Ord)]
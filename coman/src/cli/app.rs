@@ -11,15 +11,16 @@ use strum::VariantNames;
 use tokio::sync::mpsc;
 
 use crate::{
+    cli::{log::parse_since, output::OutputFormat},
     config::{ComputePlatform, Config, get_config_dir, get_data_dir, get_project_local_config_file},
     cscs::{
         api_client::{
-            client::{EdfSpec as EdfSpecEnum, ScriptSpec as ScriptSpecEnum},
+            client::{EdfSpec as EdfSpecEnum, ScriptSpec as ScriptSpecEnum, SlurmOptions},
             types::{JobStatus, PathType},
         },
         handlers::{cscs_file_list, cscs_job_list, file_system_roots},
     },
-    util::types::DockerImageUrl,
+    util::{secrets::SecretBackend, types::DockerImageUrl},
 };
 
 #[derive(Parser, Debug)]
@@ -29,6 +30,10 @@ pub struct Cli {
     #[arg(short, long, value_name = "FLOAT", default_value_t = 4.0)]
     pub tick_rate: f64,
 
+    /// Output format of listing commands
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
     #[command(subcommand)]
     pub command: Option<CliCommands>,
 }
@@ -57,6 +62,25 @@ pub enum CliCommands {
         platform: Option<ComputePlatform>,
         #[clap(short, long, help = "override compute account to use (project or user)",value_hint=ValueHint::Other)]
         account: Option<String>,
+        #[clap(long, help = "override FirecREST site to use ('cscs' or one of the [sites] in coman.toml)", value_hint=ValueHint::Other)]
+        site: Option<String>,
+    },
+    #[clap(about = "Run multi-step pipelines of dependent jobs")]
+    Pipeline {
+        #[command(subcommand)]
+        command: PipelineCommands,
+    },
+    #[clap(about = "Browse the local history of submitted jobs")]
+    History {
+        #[command(subcommand)]
+        command: HistoryCommands,
+    },
+    #[clap(
+        about = "Watch all jobs and send notifications on status changes and log matches, as configured in coman.toml"
+    )]
+    Watch {
+        #[clap(short, long, default_value_t = 30, help = "seconds between checking the jobs")]
+        interval: u64,
     },
     #[clap(about = "Create a new project configuration file")]
     Init {
@@ -70,6 +94,11 @@ pub enum CliCommands {
         #[command(subcommand)]
         command: ConfigCommands,
     },
+    #[clap(about = "Manage where credentials and tokens are stored")]
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommands,
+    },
     #[clap(about = "Generate shell completions")]
     Completions {
         /// generate shell completions
@@ -82,7 +111,59 @@ pub enum CliCommands {
         command: Vec<String>,
     },
     #[clap(hide = true)]
-    Proxy { system: String, job_id: String },
+    Proxy {
+        system: String,
+        job_id: String,
+        #[clap(long, default_value_t = 0)]
+        node: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PipelineCommands {
+    #[clap(about = "Submit all steps of a pipeline, each step waits for the steps it depends on")]
+    Run {
+        #[clap(help = "pipeline toml file", value_hint=ValueHint::FilePath)]
+        file: PathBuf,
+    },
+    #[clap(about = "Show the status of the steps of the last run of a pipeline")]
+    Status {
+        #[clap(help = "name of the pipeline (default: the last pipeline that was run)", value_hint=ValueHint::Other)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommands {
+    #[clap(alias("ls"), about = "List submitted jobs, newest first [aliases: ls]")]
+    List {
+        #[clap(short = 'n', long, help = "only show the newest N jobs")]
+        limit: Option<usize>,
+    },
+    #[clap(about = "Show how a job was submitted: image, command, environment, mounts, script and git commit")]
+    Show {
+        #[clap(help = "id or name of the job (name uses newest job of that name)", value_hint=ValueHint::Other)]
+        job: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SecretsCommands {
+    #[clap(about = "Show which secret backend is in use")]
+    Status,
+    #[clap(about = "Move all stored credentials and tokens to another secret backend and switch to it")]
+    Migrate {
+        #[clap(value_enum, help = "Backend to move the secrets to")]
+        to: SecretBackend,
+        #[clap(
+            long,
+            value_enum,
+            help = "Backend to move the secrets from (default: the configured one)"
+        )]
+        from: Option<SecretBackend>,
+        #[clap(long, action, help = "Keep the secrets in the old backend instead of deleting them")]
+        keep: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -109,13 +190,34 @@ pub enum ConfigCommands {
 
     #[clap(about = "Show whole currently active config")]
     Show,
+    #[clap(about = "List the submission profiles that can be selected with `job submit --profile`")]
+    Profiles,
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 pub enum CscsCommands {
     #[clap(about = "Log in to CSCS")]
-    Login,
+    Login {
+        #[clap(
+            long,
+            help = "Log in with your own CSCS identity in the browser instead of with the client id and secret of a service account"
+        )]
+        device: bool,
+        #[clap(
+            long,
+            help = "Name of the identity to store the credentials under, to keep several accounts apart (see `coman cscs identity`)",
+            value_hint=ValueHint::Other
+        )]
+        profile: Option<String>,
+    },
+    #[clap(about = "Show the identity in use and the CSCS user it logs in as")]
+    Whoami,
+    #[clap(about = "Manage named identities (sets of CSCS credentials)")]
+    Identity {
+        #[command(subcommand)]
+        command: CscsIdentityCommands,
+    },
     #[clap(alias("j"), about = "Job subcommands [aliases: j]")]
     Job {
         #[command(subcommand)]
@@ -134,6 +236,11 @@ pub enum CscsCommands {
         #[command(subcommand)]
         command: CscsSystemCommands,
     },
+    #[clap(about = "Subcommands for environment definition (edf) files")]
+    Edf {
+        #[command(subcommand)]
+        command: CscsEdfCommands,
+    },
     #[clap(
         alias("pf"),
         about = "Forward a local port to a remote port for a job. Note that the port needs to have been exposed with the -P flag on job submission [aliases: pf]"
@@ -145,6 +252,12 @@ pub enum CscsCommands {
         destination_port: u16,
         #[arg(help="id or name of the job (name uses newest job of that name)", add = ArgValueCompleter::new(job_id_or_name_completer))]
         job: JobIdOrName,
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "index of the node to forward to, for multi-node jobs"
+        )]
+        node: usize,
     },
 }
 
@@ -183,6 +296,38 @@ impl From<ScriptSpec> for ScriptSpecEnum {
     }
 }
 
+#[derive(Args, Clone, Debug, Default)]
+pub struct SlurmSpec {
+    #[arg(long, help = "slurm partition to run the job in", value_hint=ValueHint::Other)]
+    partition: Option<String>,
+    #[arg(long, help = "slurm reservation to run the job in", value_hint=ValueHint::Other)]
+    reservation: Option<String>,
+    #[arg(long, value_name = "CONSTRAINTS", help = "slurm node constraints (features) the job needs", value_hint=ValueHint::Other)]
+    constraint: Option<String>,
+    #[arg(long, help = "number of nodes to allocate", value_hint=ValueHint::Other)]
+    nodes: Option<u32>,
+    #[arg(long, help = "number of tasks to run (defaults to one per node)", value_hint=ValueHint::Other)]
+    ntasks: Option<u32>,
+    #[arg(long, help = "number of gpus to allocate on each node", value_hint=ValueHint::Other)]
+    gpus_per_node: Option<u32>,
+    #[arg(long, value_name = "WALLTIME", help = "maximum run time of the job, in slurm format (e.g. '1:00:00' or '2-00:00:00')", value_hint=ValueHint::Other)]
+    time: Option<String>,
+}
+
+impl From<SlurmSpec> for SlurmOptions {
+    fn from(val: SlurmSpec) -> Self {
+        SlurmOptions {
+            partition: val.partition,
+            reservation: val.reservation,
+            constraints: val.constraint,
+            nodes: val.nodes,
+            ntasks: val.ntasks,
+            gpus_per_node: val.gpus_per_node,
+            walltime: val.time,
+        }
+    }
+}
+
 #[derive(Args, Clone, Debug)]
 #[group(multiple = false)]
 pub struct EdfSpec {
@@ -250,12 +395,48 @@ pub enum CscsJobCommands {
     },
     #[clap(about = "Get the stdout of a job")]
     Log {
-        #[clap(short, long, action, help = "whether to get stderr instead of stdout")]
+        #[clap(
+            short,
+            long,
+            action,
+            conflicts_with = "all",
+            help = "whether to get stderr instead of stdout"
+        )]
         stderr: bool,
+        #[clap(
+            short,
+            long,
+            action,
+            help = "show stdout and stderr interleaved, each line prefixed with its stream (by line timestamps unless following)"
+        )]
+        all: bool,
+        #[clap(short, long, action, help = "keep printing new output until the job is done")]
+        follow: bool,
+        #[clap(
+            short = 'n',
+            long,
+            default_value_t = 100,
+            help = "number of lines to show from the end of the log"
+        )]
+        lines: usize,
+        #[clap(long, value_parser = parse_since, help = "only show lines logged after this time, either a duration like 10m, 2h, 1d or a timestamp like '2025-01-31 12:00:00'. Lines need to start with a timestamp, lines before the first one are always shown")]
+        since: Option<DateTime<Local>>,
         #[arg(help="id or name of the job (name uses newest job of that name)", add = ArgValueCompleter::new(job_id_or_name_completer))]
         job: JobIdOrName,
     },
 
+    #[clap(
+        about = "Wait until a job is done. Exits with the job's exit code, 124 if it timed out and 125 if it was cancelled"
+    )]
+    Wait {
+        #[arg(help="id or name of the job (name uses newest job of that name)", add = ArgValueCompleter::new(job_id_or_name_completer))]
+        job: JobIdOrName,
+    },
+    #[clap(about = "Like `wait`, but shows a live status line while waiting")]
+    Watch {
+        #[arg(help="id or name of the job (name uses newest job of that name)", add = ArgValueCompleter::new(job_id_or_name_completer))]
+        job: JobIdOrName,
+    },
     #[clap(alias("s"), about = "Submit a new compute job [aliases: s]")]
     Submit {
         #[clap(short, long, help = "name of the job", value_hint=ValueHint::Other)]
@@ -294,15 +475,92 @@ pub enum CscsJobCommands {
         edf_spec: Option<EdfSpec>,
         #[command(flatten)]
         script_spec: Option<ScriptSpec>,
+        #[command(flatten)]
+        slurm: SlurmSpec,
+        #[clap(long,
+            value_name="KEY=V1,V2",
+            value_parser=parse_sweep,
+            help="Run a sweep over these values, exposed as environment variable KEY in the job. Can be repeated to sweep over all combinations",
+            value_hint=ValueHint::Other)]
+        sweep: Vec<(String, Vec<String>)>,
+        #[clap(long, value_name="PATH", help = "toml file with parameters to sweep over, e.g. `lr = [0.1, 0.01]`", value_hint=ValueHint::FilePath)]
+        sweep_file: Option<PathBuf>,
+        #[clap(long, value_name="JOB", help="only start once this job (id or name) has started, can be repeated", add = ArgValueCompleter::new(job_id_or_name_completer))]
+        after: Vec<JobIdOrName>,
+        #[clap(long, value_name="JOB", help="only start once this job (id or name) has finished successfully, can be repeated", add = ArgValueCompleter::new(job_id_or_name_completer))]
+        afterok: Vec<JobIdOrName>,
         #[clap(long, action, help = "don't set up ssh integration")]
         no_ssh: bool,
         #[clap(short, long, help="ssh public key to use", value_hint=ValueHint::FilePath)]
         ssh_key: Option<PathBuf>,
         #[clap(long, action, help = "don't upload and inject coman into the container")]
         no_coman: bool,
+        #[clap(long, help = "apply the settings of this profile from the `[profiles]` config", value_hint=ValueHint::Other)]
+        profile: Option<String>,
+        #[clap(
+            long,
+            action,
+            help = "only show the rendered script, environment file and resolved settings, without creating or submitting anything"
+        )]
+        dry_run: bool,
+        #[clap(long, value_name = "DIR", requires = "dry_run", help = "write the files rendered in a dry run to this directory instead of printing them", value_hint=ValueHint::DirPath)]
+        render_dir: Option<PathBuf>,
         #[clap(trailing_var_arg = true, help = "The command to run in the container", value_hint=ValueHint::Other)]
         command: Option<Vec<String>>,
     },
+    #[clap(
+        about = "Submit a job again with the same settings, optionally overriding some of them. Uses the local job history, or the script and environment file left on the remote for jobs not in the history"
+    )]
+    Resubmit {
+        #[clap(help="id or name of the job (name uses newest job of that name)", add = ArgValueCompleter::new(job_id_or_name_completer))]
+        job: JobIdOrName,
+        #[clap(short, long, help = "name of the new job (default: name of the original job)", value_hint=ValueHint::Other)]
+        name: Option<String>,
+        #[clap(
+            short,
+            long,
+            help = "the working directory path inside the container",
+            value_hint=ValueHint::Other
+        )]
+        workdir: Option<String>,
+        #[clap(short='E',
+            value_name="KEY=VALUE",
+            value_parser=parse_key_val::<String, String>,
+            help="Environment variables to set in the container, in addition to those of the original job",
+            value_hint=ValueHint::Other)]
+        env: Vec<(String, String)>,
+        #[clap(short='P',
+            value_name="TARGET",
+            help="Ports to forward from the container, instead of those of the original job",
+            value_hint=ValueHint::Other)]
+        port_forward: Vec<u16>,
+        #[clap(short='M',
+            value_name="PATH:CONTAINER_PATH",
+            value_parser=parse_key_val_colon::<String,String>,
+            help="Paths to mount inside container, in addition to those of the original job",
+            value_hint=ValueHint::Other)]
+        mount: Vec<(String, String)>,
+        #[clap(short, long, help = "The docker image to use", value_hint=ValueHint::Other)]
+        image: Option<DockerImageUrl>,
+        #[clap(long, help = "Path where stdout of the job gets written to", value_hint=ValueHint::Other)]
+        stdout: Option<PathBuf>,
+        #[clap(long, help = "Path where stderr of the job gets written to", value_hint=ValueHint::Other)]
+        stderr: Option<PathBuf>,
+        #[command(flatten)]
+        edf_spec: Option<EdfSpec>,
+        #[command(flatten)]
+        script_spec: Option<ScriptSpec>,
+        #[command(flatten)]
+        slurm: SlurmSpec,
+        #[clap(long, action, help = "don't set up ssh integration")]
+        no_ssh: bool,
+        #[clap(short, long, help="ssh public key to use", value_hint=ValueHint::FilePath)]
+        ssh_key: Option<PathBuf>,
+        #[clap(long, action, help = "don't upload and inject coman into the container")]
+        no_coman: bool,
+        #[clap(trailing_var_arg = true, help = "The command to run in the container (default: command of the original job)", value_hint=ValueHint::Other)]
+        command: Option<Vec<String>>,
+    },
     #[clap(
         alias("c"),
         about = "Cancel a running job, fails if the job isn't running [aliases: c]"
@@ -318,6 +576,11 @@ pub enum CscsJobCommands {
     ResourceUsage {
         #[clap(help="id or name of the job (name uses newest job of that name)",  add = ArgValueCompleter::new(job_id_or_name_completer))]
         job: JobIdOrName,
+        #[clap(
+            long,
+            help = "only show the usage of this node, for multi-node jobs (default: aggregated over all nodes)"
+        )]
+        node: Option<usize>,
     },
 }
 fn job_id_or_name_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
@@ -333,7 +596,8 @@ fn job_id_or_name_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidat
     match jn {
         JobIdOrName::Id(id) => {
             tokio::spawn(async move {
-                let jobs = cscs_job_list(None, None, None).await.unwrap();
+                let config = Config::new().unwrap();
+                let jobs = cscs_job_list(&config, None, None, None).await.unwrap();
                 let partial_id = id.to_string();
                 let ids: Vec<_> = jobs
                     .iter()
@@ -348,7 +612,8 @@ fn job_id_or_name_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidat
         }
         JobIdOrName::Name(name) => {
             tokio::spawn(async move {
-                let jobs = cscs_job_list(None, None, None).await.unwrap();
+                let config = Config::new().unwrap();
+                let jobs = cscs_job_list(&config, None, None, None).await.unwrap();
                 let names: Vec<_> = jobs
                     .into_iter()
                     .map(|j| j.name)
@@ -384,24 +649,143 @@ pub enum CscsFileCommands {
     },
     #[clap(alias("rm"), about = "Remove remote files or folders [aliases: rm]")]
     Remove {
+        #[clap(short, long, action, help = "Remove a folder and all its contents")]
+        recursive: bool,
         #[arg(help ="remote path to remove", add = ArgValueCompleter::new(remote_path_completer))]
         path: PathBuf,
     },
-    #[clap(alias("dl"), about = "Download a remote file [aliases: dl]")]
+    #[clap(alias("dl"), about = "Download a remote file or folder [aliases: dl]")]
     Download {
+        #[clap(short, long, action, help = "Download a folder and all its contents")]
+        recursive: bool,
+        #[clap(
+            short,
+            long,
+            action,
+            conflicts_with = "recursive",
+            help = "Download a folder as a single archive that is compressed on the cluster"
+        )]
+        archive: bool,
+        #[clap(
+            short = 'x',
+            long,
+            action,
+            requires = "archive",
+            help = "Extract the downloaded archive locally"
+        )]
+        extract: bool,
         #[clap(help = "The path in the cluster to download", add = ArgValueCompleter::new(remote_path_completer))]
         remote: PathBuf,
         #[clap(help = "The local path to download the file to", value_hint=ValueHint::AnyPath)]
         local: PathBuf,
     },
-    #[clap(alias("ul"), about = "Upload a file to remote storage [aliases: ul]")]
+    #[clap(alias("ul"), about = "Upload a file or folder to remote storage [aliases: ul]")]
     Upload {
+        #[clap(short, long, action, help = "Upload a folder and all its contents")]
+        recursive: bool,
+        #[clap(
+            long,
+            action,
+            conflicts_with = "recursive",
+            help = "Resume an interrupted upload of a large file"
+        )]
+        resume: bool,
+        #[clap(
+            short,
+            long,
+            help = "How many parts of a large file to upload in parallel [default: cscs.transfer_parallelism]"
+        )]
+        parallel: Option<usize>,
         #[clap(help = "The local path to upload to the cluster", value_hint=ValueHint::AnyPath)]
         local: PathBuf,
 
         #[clap(help = "the path in the cluster to upload to", add = ArgValueCompleter::new(remote_path_completer))]
         remote: PathBuf,
     },
+    #[clap(about = "Show the first lines of a remote file")]
+    Head {
+        #[clap(short = 'n', long, help = "Number of lines to show", conflicts_with = "bytes")]
+        lines: Option<usize>,
+        #[clap(short = 'c', long, help = "Number of bytes to show")]
+        bytes: Option<usize>,
+        #[arg(help ="remote file to show", add = ArgValueCompleter::new(remote_path_completer))]
+        path: PathBuf,
+    },
+    #[clap(about = "Print the contents of a remote file")]
+    Cat {
+        #[arg(help ="remote file to print", add = ArgValueCompleter::new(remote_path_completer))]
+        path: PathBuf,
+    },
+    #[clap(about = "Show the type of a remote file")]
+    Type {
+        #[arg(help ="remote file to inspect", add = ArgValueCompleter::new(remote_path_completer))]
+        path: PathBuf,
+    },
+    #[clap(about = "Copy remote files or folders")]
+    Cp {
+        #[arg(help ="remote path to copy", add = ArgValueCompleter::new(remote_path_completer))]
+        source: PathBuf,
+        #[arg(help ="remote path to copy to", add = ArgValueCompleter::new(remote_path_completer))]
+        target: PathBuf,
+    },
+    #[clap(about = "Move or rename remote files or folders")]
+    Mv {
+        #[arg(help ="remote path to move", add = ArgValueCompleter::new(remote_path_completer))]
+        source: PathBuf,
+        #[arg(help ="remote path to move to", add = ArgValueCompleter::new(remote_path_completer))]
+        target: PathBuf,
+    },
+    #[clap(about = "Create a symbolic link on the cluster")]
+    Ln {
+        #[arg(help ="remote path the link points to", add = ArgValueCompleter::new(remote_path_completer))]
+        target: PathBuf,
+        #[arg(help ="remote path of the link", add = ArgValueCompleter::new(remote_path_completer))]
+        link: PathBuf,
+    },
+    #[clap(about = "Change the owner and/or group of a remote path")]
+    Chown {
+        #[arg(help = "new owner and group, as <owner>, <owner>:<group> or :<group>")]
+        owner: String,
+        #[arg(help ="remote path to change", add = ArgValueCompleter::new(remote_path_completer))]
+        path: PathBuf,
+    },
+    #[clap(
+        about = "Create a tar archive of a remote path, compression is picked from the archive extension (.tar, .tar.gz, .tar.bz2, .tar.xz)"
+    )]
+    Tar {
+        #[clap(
+            long,
+            action,
+            help = "Create the archive directly instead of in a transfer job, faster for small paths but limited by the api request timeout"
+        )]
+        direct: bool,
+        #[arg(help ="remote path to archive", add = ArgValueCompleter::new(remote_path_completer))]
+        source: PathBuf,
+        #[arg(help ="remote path of the archive to create", add = ArgValueCompleter::new(remote_path_completer))]
+        archive: PathBuf,
+    },
+    #[clap(about = "Extract a remote tar archive into a remote folder")]
+    Untar {
+        #[clap(
+            long,
+            action,
+            help = "Extract the archive directly instead of in a transfer job, faster for small archives but limited by the api request timeout"
+        )]
+        direct: bool,
+        #[arg(help ="remote archive to extract", add = ArgValueCompleter::new(remote_path_completer))]
+        archive: PathBuf,
+        #[arg(help ="remote folder to extract to", add = ArgValueCompleter::new(remote_path_completer))]
+        target: PathBuf,
+    },
+    #[clap(about = "Upload new or changed files of a local folder to the cluster, skipping paths in .comanignore")]
+    Sync {
+        #[clap(short, long, action, help = "Delete remote files that don't exist locally")]
+        delete: bool,
+        #[clap(help = "The local folder to sync", value_hint=ValueHint::DirPath)]
+        local: PathBuf,
+        #[clap(help = "the path in the cluster to sync to", add = ArgValueCompleter::new(remote_path_completer))]
+        remote: PathBuf,
+    },
 }
 
 fn remote_path_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
@@ -416,7 +800,10 @@ fn remote_path_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate>
     let (send, mut recv) = mpsc::unbounded_channel();
     if current.is_empty() || current == "/" {
         tokio::spawn(async move {
-            let roots = file_system_roots(None).await;
+            let Ok(config) = Config::new() else {
+                return;
+            };
+            let roots = file_system_roots(&config, None).await;
             if let Ok(roots) = roots {
                 for root in roots {
                     send.send(CompletionCandidate::new(root.name.clone())).unwrap();
@@ -426,8 +813,11 @@ fn remote_path_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate>
     } else {
         let current = PathBuf::from(current);
         tokio::spawn(async move {
+            let Ok(config) = Config::new() else {
+                return;
+            };
             let parent = current.parent().unwrap();
-            let roots = cscs_file_list(current.clone(), None, None).await;
+            let roots = cscs_file_list(&config, current.clone(), None, None).await;
             if let Ok(roots) = roots {
                 for root in roots {
                     if root.path_type == PathType::Directory {
@@ -442,7 +832,7 @@ fn remote_path_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate>
             } else {
                 // file listing only work for full paths, so if we want to complet a partial result, we need
                 // to list the parent folder and take it from there
-                if let Ok(roots) = cscs_file_list(parent.to_path_buf(), None, None).await {
+                if let Ok(roots) = cscs_file_list(&config, parent.to_path_buf(), None, None).await {
                     let partial = current.file_name().unwrap().to_string_lossy().into_owned();
                     for root in roots {
                         if root.name.starts_with(&partial) {
@@ -487,6 +877,33 @@ pub enum CscsSystemCommands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum CscsIdentityCommands {
+    #[clap(alias("ls"), about = "List identities logged in on this machine [aliases: ls]")]
+    List,
+    #[clap(about = "Use an identity for the current project, or globally")]
+    Use {
+        #[clap(short, long, action, help = "set in global config instead of project-local one")]
+        global: bool,
+        #[clap(help = "Identity name", value_hint=ValueHint::Other)]
+        name: String,
+    },
+    #[clap(alias("rm"), about = "Delete the credentials of an identity [aliases: rm]")]
+    Remove {
+        #[clap(help = "Identity name", value_hint=ValueHint::Other)]
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CscsEdfCommands {
+    #[clap(about = "Check an environment definition toml file for mistakes, e.g. unknown keys or invalid mounts")]
+    Validate {
+        #[clap(help = "edf toml file to check", value_hint=ValueHint::FilePath)]
+        file: PathBuf,
+    },
+}
+
 pub const COMAN_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const VERSION_MESSAGE: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("VERGEN_BUILD_DATE"), ")");
@@ -532,6 +949,10 @@ where
         .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{s}`"))?;
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
+fn parse_sweep(s: &str) -> Result<(String, Vec<String>), Box<dyn Error + Send + Sync + 'static>> {
+    let (key, values): (String, String) = parse_key_val(s)?;
+    Ok((key, values.split(',').map(|v| v.to_owned()).collect()))
+}
 fn parse_key_val_colon<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
 where
     T: std::str::FromStr,
@@ -9,14 +9,51 @@ use iroh::{
 };
 use pid1::Pid1Settings;
 use rust_supervisor::{ChildType, Supervisor, SupervisorConfig};
+use sha2::{Digest, Sha256};
 use tokio::{io::AsyncWriteExt, net::TcpStream};
 
-use crate::cli::rpc::{COMAN_RPC_ALPN, RpcHandler};
+use crate::{
+    cli::rpc::{COMAN_RPC_ALPN, RpcHandler},
+    config::EndpointMode,
+};
 
 const SECRET_KEY_ENV: &str = "COMAN_IROH_SECRET";
 const PORT_FORWARD_ENV: &str = "COMAN_FORWARDED_PORTS";
+const ENDPOINT_MODE_ENV: &str = "COMAN_ENDPOINT_MODE";
 const SSH_PORT: u16 = 15263;
 
+/// Derives the iroh secret key of a node of a multi-node job from the secret key of the job.
+///
+/// Node 0 uses the job key itself, so single node jobs keep working with older coman versions.
+pub(crate) fn node_secret_key(secret_key: &SecretKey, node: usize) -> SecretKey {
+    if node == 0 {
+        return secret_key.clone();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(secret_key.to_bytes());
+    hasher.update(b"coman-node");
+    hasher.update((node as u64).to_le_bytes());
+    SecretKey::from_bytes(&hasher.finalize().into())
+}
+
+fn slurm_env(name: &str) -> usize {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
+/// Whether this task should run the iroh endpoint.
+///
+/// Srun starts coman once per task, but only one task per node (or only rank 0) can bind the endpoint of a node.
+fn runs_endpoint() -> bool {
+    let mode = std::env::var(ENDPOINT_MODE_ENV)
+        .ok()
+        .and_then(|m| m.parse().ok())
+        .unwrap_or_default();
+    match mode {
+        EndpointMode::PerNode => slurm_env("SLURM_LOCALID") == 0,
+        EndpointMode::RankZero => slurm_env("SLURM_PROCID") == 0,
+    }
+}
+
 fn get_secret_key() -> Option<Vec<u8>> {
     if let Ok(secret) = std::env::var(SECRET_KEY_ENV) {
         let secret_key = BASE64_STANDARD.decode(secret).unwrap();
@@ -98,7 +135,7 @@ async fn port_forward() -> Result<()> {
         return Ok(());
     };
     let secret_key: &[u8; 32] = secret_key[0..32].try_into().unwrap();
-    let secret_key = SecretKey::from_bytes(secret_key);
+    let secret_key = node_secret_key(&SecretKey::from_bytes(secret_key), slurm_env("SLURM_NODEID"));
     let mut forwarded_ports = vec!["ssh".to_owned()];
     if let Ok(env_ports) = std::env::var(PORT_FORWARD_ENV) {
         forwarded_ports.extend(env_ports.split(',').map(|p| p.to_owned()).collect::<Vec<String>>());
@@ -145,11 +182,13 @@ pub(crate) async fn cli_exec_command(command: Vec<String>) -> Result<()> {
         .expect("Launch failed");
 
     let mut supervisor = Supervisor::new(SupervisorConfig::default());
-    supervisor.add_process("port-forward", ChildType::Permanent, || {
-        thread::spawn(|| {
-            let _ = port_forward();
-        })
-    });
+    if runs_endpoint() {
+        supervisor.add_process("port-forward", ChildType::Permanent, || {
+            thread::spawn(|| {
+                let _ = port_forward();
+            })
+        });
+    }
     supervisor.add_process("main-process", ChildType::Temporary, move || {
         let command = command.clone();
         thread::spawn(move || {
@@ -173,3 +212,18 @@ pub(crate) async fn cli_exec_command(command: Vec<String>) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_secret_key() {
+        let secret_key = SecretKey::from_bytes(&[7u8; 32]);
+        assert_eq!(node_secret_key(&secret_key, 0).public(), secret_key.public());
+        let node_1 = node_secret_key(&secret_key, 1).public();
+        assert_ne!(node_1, secret_key.public());
+        assert_ne!(node_1, node_secret_key(&secret_key, 2).public());
+        assert_eq!(node_1, node_secret_key(&secret_key, 1).public());
+    }
+}
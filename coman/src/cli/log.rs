@@ -0,0 +1,227 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, TimeZone};
+
+use crate::cscs::api_client::types::LogStream;
+
+const TIMESTAMP_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Parses a `--since` value, either a duration relative to now (e.g. `30s`, `10m`, `2h`, `1d`) or a timestamp
+pub fn parse_since(s: &str) -> Result<DateTime<Local>, Box<dyn Error + Send + Sync + 'static>> {
+    if let Some(unit) = s.chars().last().filter(|c| c.is_ascii_alphabetic())
+        && let Ok(amount) = s[..s.len() - 1].parse::<i64>()
+    {
+        let delta = match unit {
+            's' => TimeDelta::seconds(amount),
+            'm' => TimeDelta::minutes(amount),
+            'h' => TimeDelta::hours(amount),
+            'd' => TimeDelta::days(amount),
+            _ => return Err(format!("invalid duration unit `{unit}`, use one of s, m, h, d").into()),
+        };
+        return Ok(Local::now() - delta);
+    }
+    parse_timestamp(s).ok_or_else(|| format!("invalid time `{s}`, use e.g. `10m` or `2025-01-31 12:00:00`").into())
+}
+
+/// Parses a timestamp at the start of `s`, optionally in brackets, as written by most logging libraries
+fn parse_timestamp(s: &str) -> Option<DateTime<Local>> {
+    let s = s.trim_start().trim_start_matches('[');
+    // rfc3339 timestamps contain no spaces, so the first token can be tried as is
+    let first_token = s.split([' ', ']']).next().unwrap_or_default();
+    if let Ok(t) = DateTime::parse_from_rfc3339(first_token) {
+        return Some(t.with_timezone(&Local));
+    }
+    TIMESTAMP_FORMATS.iter().find_map(|format| {
+        NaiveDateTime::parse_and_remainder(s, format)
+            .ok()
+            .and_then(|(t, _)| Local.from_local_datetime(&t).earliest())
+    })
+}
+
+/// A timestamped line and the untimestamped lines following it
+type LogBlock<'a> = (Option<DateTime<Local>>, Vec<&'a str>);
+
+/// Merges complete logs of several streams into one, ordered by the timestamps at the start of their lines.
+///
+/// Lines without a timestamp stay with the line before them. Each stream keeps its own order, and a stream without
+/// any timestamps ends up before the others, so its lines are shown one after the other.
+pub fn interleave(streams: Vec<(LogStream, String)>) -> Vec<(LogStream, String)> {
+    let mut blocks: Vec<VecDeque<LogBlock>> = streams
+        .iter()
+        .map(|(_, content)| {
+            let mut blocks: VecDeque<LogBlock> = VecDeque::new();
+            for line in content.lines() {
+                match (parse_timestamp(line), blocks.back_mut()) {
+                    (None, Some((_, lines))) => lines.push(line),
+                    (timestamp, _) => blocks.push_back((timestamp, vec![line])),
+                }
+            }
+            blocks
+        })
+        .collect();
+    let mut merged = vec![];
+    loop {
+        // earliest next block, the first stream wins ties
+        let next = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.front().map(|(timestamp, _)| (*timestamp, i)))
+            .min();
+        let Some((_, index)) = next else {
+            break;
+        };
+        let (_, lines) = blocks[index].pop_front().expect("block exists");
+        let stream = streams[index].0;
+        merged.extend(lines.into_iter().map(|line| (stream, line.to_owned())));
+    }
+    merged
+}
+
+/// Splits chunks of log output into lines, optionally prefixing them with their stream and filtering by time
+pub struct LogPrinter {
+    prefix: bool,
+    since: Option<DateTime<Local>>,
+    pending: HashMap<LogStream, String>,
+    /// whether the last timestamped line of a stream was recent enough, untimestamped lines follow it.
+    ///
+    /// Lines are shown until the first timestamp, so output without any timestamps isn't filtered away entirely.
+    include: HashMap<LogStream, bool>,
+}
+
+impl LogPrinter {
+    pub fn new(prefix: bool, since: Option<DateTime<Local>>) -> Self {
+        Self {
+            prefix,
+            since,
+            pending: HashMap::new(),
+            include: HashMap::new(),
+        }
+    }
+
+    /// Adds a chunk of output, returning the complete lines that should be shown
+    pub fn push(&mut self, stream: LogStream, chunk: &str) -> Vec<String> {
+        let pending = self.pending.entry(stream).or_default();
+        pending.push_str(chunk);
+        let Some(last_newline) = pending.rfind('\n') else {
+            return vec![];
+        };
+        let complete: String = pending.drain(..=last_newline).collect();
+        complete.lines().filter_map(|line| self.format(stream, line)).collect()
+    }
+
+    /// Returns whatever is left of incomplete lines
+    pub fn flush(&mut self) -> Vec<String> {
+        let pending: Vec<_> = self.pending.drain().filter(|(_, rest)| !rest.is_empty()).collect();
+        pending
+            .into_iter()
+            .filter_map(|(stream, rest)| self.format(stream, &rest))
+            .collect()
+    }
+
+    fn format(&mut self, stream: LogStream, line: &str) -> Option<String> {
+        if let Some(since) = self.since {
+            let include = self.include.entry(stream).or_insert(true);
+            if let Some(timestamp) = parse_timestamp(line) {
+                *include = timestamp >= since;
+            }
+            if !*include {
+                return None;
+            }
+        }
+        if self.prefix {
+            Some(format!("[{stream}] {line}"))
+        } else {
+            Some(line.to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_printer_since_and_prefix() {
+        let since = parse_since("2025-01-31 12:00:00").unwrap();
+        let mut printer = LogPrinter::new(true, Some(since));
+        assert!(
+            printer
+                .push(LogStream::Stdout, "2025-01-31 11:59:00 old\ncontinued\n2025-01-31T12:")
+                .is_empty()
+        );
+        assert_eq!(
+            printer.push(LogStream::Stdout, "00:01 new\ntraceback\n[2025-01-31 12:05:00] partial"),
+            vec![
+                "[stdout] 2025-01-31T12:00:01 new".to_owned(),
+                "[stdout] traceback".to_owned()
+            ]
+        );
+        assert!(
+            printer
+                .push(LogStream::Stderr, "2025-01-31 11:00:00 old\nno timestamp\n")
+                .is_empty()
+        );
+        assert_eq!(
+            printer.flush(),
+            vec!["[stdout] [2025-01-31 12:05:00] partial".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_log_printer_since_without_timestamps() {
+        let since = parse_since("2025-01-31 12:00:00").unwrap();
+        let mut printer = LogPrinter::new(false, Some(since));
+        // plain program output can't be filtered by time, so it's shown
+        assert_eq!(
+            printer.push(LogStream::Stdout, "epoch 1\nepoch 2\n"),
+            vec!["epoch 1".to_owned(), "epoch 2".to_owned()]
+        );
+        // until a timestamp says it's too old
+        assert!(
+            printer
+                .push(LogStream::Stdout, "2025-01-31 11:00:00 old\nepoch 3\n")
+                .is_empty()
+        );
+        assert_eq!(
+            printer.push(LogStream::Stdout, "2025-01-31 12:00:00 new\n"),
+            vec!["2025-01-31 12:00:00 new".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_interleave() {
+        let stdout = "2025-01-31 12:00:00 start\n2025-01-31 12:00:02 step\n2025-01-31 12:00:04 done\n".to_owned();
+        let stderr = "2025-01-31 12:00:03 warning\ntraceback\n".to_owned();
+        let merged: Vec<_> = interleave(vec![(LogStream::Stdout, stdout), (LogStream::Stderr, stderr)])
+            .into_iter()
+            .map(|(stream, line)| format!("[{stream}] {line}"))
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                "[stdout] 2025-01-31 12:00:00 start",
+                "[stdout] 2025-01-31 12:00:02 step",
+                "[stderr] 2025-01-31 12:00:03 warning",
+                "[stderr] traceback",
+                "[stdout] 2025-01-31 12:00:04 done",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_since_duration() {
+        let since = parse_since("10m").unwrap();
+        let expected = Local::now() - TimeDelta::minutes(10);
+        assert!((expected - since).num_seconds().abs() < 5);
+        assert!(parse_since("10x").is_err());
+        assert!(parse_since("yesterday").is_err());
+    }
+}
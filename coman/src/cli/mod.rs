@@ -1,4 +1,6 @@
 pub mod app;
 pub mod exec;
+pub mod log;
+pub mod output;
 pub mod proxy;
 pub mod rpc;
@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+use color_eyre::Result;
+use eyre::{Context, eyre};
+use serde::Serialize;
+use serde_json::Value;
+use strum::Display;
+
+/// How listing commands print their results
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human readable table
+    #[default]
+    Table,
+    Json,
+    Yaml,
+    /// One row per entry, nested values are encoded as json
+    Csv,
+}
+
+impl OutputFormat {
+    /// Serializes `items` in a machine readable format, returns `None` for the table format
+    pub fn render<T: Serialize>(&self, items: &[T]) -> Result<Option<String>> {
+        match self {
+            OutputFormat::Table => Ok(None),
+            OutputFormat::Json => Ok(Some(serde_json::to_string_pretty(items)?)),
+            OutputFormat::Yaml => Ok(Some(serde_yaml::to_string(items)?)),
+            OutputFormat::Csv => to_csv(items).map(Some),
+        }
+    }
+
+    /// Like `render`, but for a single item, which isn't wrapped in a list for json and yaml
+    pub fn render_one<T: Serialize>(&self, item: &T) -> Result<Option<String>> {
+        match self {
+            OutputFormat::Table => Ok(None),
+            OutputFormat::Json => Ok(Some(serde_json::to_string_pretty(item)?)),
+            OutputFormat::Yaml => Ok(Some(serde_yaml::to_string(item)?)),
+            OutputFormat::Csv => to_csv(std::slice::from_ref(item)).map(Some),
+        }
+    }
+}
+
+fn to_csv<T: Serialize>(items: &[T]) -> Result<String> {
+    let rows = items
+        .iter()
+        .map(|item| match serde_json::to_value(item)? {
+            Value::Object(fields) => Ok(fields),
+            _ => Err(eyre!("only structs can be written as csv")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut writer = csv::Writer::from_writer(vec![]);
+    if let Some(first) = rows.first() {
+        writer.write_record(first.keys())?;
+    }
+    for row in rows {
+        writer.write_record(row.values().map(csv_cell))?;
+    }
+    String::from_utf8(writer.into_inner().wrap_err("couldn't write csv")?).wrap_err("csv output isn't valid utf-8")
+}
+
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_owned(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Entry {
+        name: String,
+        size: Option<usize>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let entries = vec![
+            Entry {
+                name: "a, b".to_owned(),
+                size: Some(3),
+                tags: vec!["x".to_owned()],
+            },
+            Entry {
+                name: "c".to_owned(),
+                size: None,
+                tags: vec![],
+            },
+        ];
+        let csv = OutputFormat::Csv.render(&entries).unwrap().unwrap();
+        assert_eq!(csv, "name,size,tags\n\"a, b\",3,\"[\"\"x\"\"]\"\nc,,[]\n");
+        assert!(OutputFormat::Table.render(&entries).unwrap().is_none());
+    }
+}
@@ -31,6 +31,26 @@ impl Ord for ResourceUsage {
         )
     }
 }
+impl ResourceUsage {
+    /// Combines the usage of several nodes: cpu is averaged, memory is summed up and all gpus are listed
+    pub fn aggregate(usages: Vec<ResourceUsage>) -> ResourceUsage {
+        let count = usages.len().max(1) as f32;
+        usages
+            .into_iter()
+            .fold(ResourceUsage::default(), |acc, usage| ResourceUsage {
+                cpu: acc.cpu + usage.cpu / count,
+                rss: acc.rss + usage.rss,
+                vsz: acc.vsz + usage.vsz,
+                gpu: match (acc.gpu, usage.gpu) {
+                    (Some(mut gpus), Some(other)) => {
+                        gpus.extend(other);
+                        Some(gpus)
+                    }
+                    (gpus, other) => gpus.or(other),
+                },
+            })
+    }
+}
 impl PartialOrd for ResourceUsage {
     fn partial_cmp(&self, other: &ResourceUsage) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -123,3 +143,35 @@ impl ProtocolHandler for RpcHandler {
 async fn spawn(fut: impl Future<Output = ()> + Send + 'static) {
     tokio::spawn(fut);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_resource_usage() {
+        let usage = ResourceUsage::aggregate(vec![
+            ResourceUsage {
+                cpu: 10.0,
+                rss: 100,
+                vsz: 1000,
+                gpu: Some(vec![(80, 10)]),
+            },
+            ResourceUsage {
+                cpu: 30.0,
+                rss: 200,
+                vsz: 2000,
+                gpu: Some(vec![(80, 20)]),
+            },
+        ]);
+        assert_eq!(
+            usage,
+            ResourceUsage {
+                cpu: 20.0,
+                rss: 300,
+                vsz: 3000,
+                gpu: Some(vec![(80, 10), (80, 20)]),
+            }
+        );
+    }
+}
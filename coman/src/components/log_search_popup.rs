@@ -0,0 +1,64 @@
+use tui_realm_stdlib::components::Input;
+use tuirealm::{
+    command::{Cmd, CmdResult, Direction, Position},
+    component::{AppComponent, Component},
+    event::{Event, Key, KeyEvent, KeyModifiers},
+    props::{BorderType, Borders, Color, InputType},
+    state::{State, StateValue},
+};
+
+use crate::app::{
+    messages::{LogSearchPopupMsg, Msg},
+    user_events::UserEvent,
+};
+
+#[derive(Component)]
+pub struct LogSearchInput {
+    component: Input,
+}
+
+impl LogSearchInput {
+    pub fn new() -> Self {
+        Self {
+            component: Input::default()
+                .borders(Borders::default().modifiers(BorderType::Thick).color(Color::Yellow))
+                .input_type(InputType::Text)
+                .title("Search Log"),
+        }
+    }
+}
+
+impl AppComponent<Msg, UserEvent> for LogSearchInput {
+    fn on(&mut self, ev: &Event<UserEvent>) -> Option<Msg> {
+        let _ = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Left, .. }) => self.perform(Cmd::Move(Direction::Left)),
+            Event::Keyboard(KeyEvent { code: Key::Right, .. }) => self.perform(Cmd::Move(Direction::Right)),
+            Event::Keyboard(KeyEvent { code: Key::Home, .. }) => self.perform(Cmd::GoTo(Position::Begin)),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => self.perform(Cmd::GoTo(Position::End)),
+            Event::Keyboard(KeyEvent { code: Key::Delete, .. }) => self.perform(Cmd::Cancel),
+            Event::Keyboard(KeyEvent {
+                code: Key::Backspace, ..
+            }) => self.perform(Cmd::Delete),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            }) => self.perform(Cmd::Type(ch.to_owned())),
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter,
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if let State::Single(StateValue::String(query)) = self.state()
+                    && !query.is_empty()
+                {
+                    return Some(Msg::LogSearchPopup(LogSearchPopupMsg::Search(query)));
+                }
+                return Some(Msg::LogSearchPopup(LogSearchPopupMsg::Closed));
+            }
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => {
+                return Some(Msg::LogSearchPopup(LogSearchPopupMsg::Closed));
+            }
+            _ => CmdResult::NoChange,
+        };
+        Some(Msg::None)
+    }
+}
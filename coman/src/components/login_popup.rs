@@ -2,7 +2,7 @@ use tui_realm_stdlib::components::Input;
 use tuirealm::{
     command::{Cmd, CmdResult, Direction, Position},
     component::{AppComponent, Component},
-    event::{Event, Key, KeyEvent},
+    event::{Event, Key, KeyEvent, KeyModifiers},
     props::{AttrValue, Attribute, BorderType, Borders, Color, InputType, Layout, Props, QueryResult, Style, Title},
     ratatui::{
         Frame,
@@ -41,7 +41,7 @@ impl LoginPopup {
         popup.client_id_input.attr(Attribute::Focus, AttrValue::Flag(true));
         popup
             .borders(Borders::default().modifiers(BorderType::Thick).color(Color::Green))
-            .title("Login")
+            .title("Login (Ctrl+D to log in with your CSCS account in the browser)")
             .layout(
                 Layout::default()
                     .constraints(&[Constraint::Length(3), Constraint::Length(3)])
@@ -163,6 +163,12 @@ impl AppComponent<Msg, UserEvent> for LoginPopup {
             Event::Keyboard(KeyEvent {
                 code: Key::Backspace, ..
             }) => self.perform(Cmd::Delete),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => {
+                return Some(Msg::LoginPopup(LoginPopupMsg::DeviceLogin));
+            }
             Event::Keyboard(KeyEvent {
                 code: Key::Char(ch), ..
             }) => self.perform(Cmd::Type(ch.to_owned())),
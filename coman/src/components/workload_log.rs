@@ -1,21 +1,39 @@
+use std::{collections::VecDeque, path::PathBuf};
+
 use tui_realm_stdlib::components::Textarea;
 use tuirealm::{
     command::{Cmd, CmdResult, Direction, Position},
     component::{AppComponent, Component},
     event::{Event, Key, KeyEvent},
-    props::{AttrValue, Attribute, BorderType, Borders, Color, PropPayload, PropValue, Title},
-    ratatui::text::Span,
+    props::{AttrValue, Attribute, BorderType, Borders, Color, Style, Title},
+    ratatui::text::{Line, Span, Text},
 };
 
-use crate::app::{
-    messages::{JobMsg, Msg},
-    user_events::{CscsEvent, UserEvent},
+use crate::{
+    app::{
+        messages::{JobMsg, LogSearchPopupMsg, Msg, StatusMsg},
+        user_events::{CscsEvent, JobEvent, UserEvent},
+    },
+    cscs::api_client::types::LogStream,
 };
 
+const LOG_HELP: &str = "/: search, n/N: next/prev match, g/G: top/bottom, tab: stdout/stderr, d: download";
+/// Lines kept in the pager, older lines are dropped and can be seen by downloading the log
+const MAX_LOG_LINES: usize = 10_000;
+
+/// Pager for the log of a job, which is appended to as the job writes output
 #[derive(Component)]
 pub struct WorkloadLog {
     component: Textarea,
-    stderr: bool,
+    stream: LogStream,
+    lines: VecDeque<String>,
+    /// the rendered lines, kept so that new output only renders the new lines
+    text: VecDeque<Line<'static>>,
+    /// whether the last line is still being written
+    partial_line: bool,
+    /// remote log file, used for downloading
+    path: Option<PathBuf>,
+    query: Option<String>,
 }
 
 impl WorkloadLog {
@@ -23,23 +41,151 @@ impl WorkloadLog {
         Self {
             component: Textarea::default()
                 .borders(Borders::default().modifiers(BorderType::Rounded).color(Color::Yellow))
-                .title("Workload Log (stdout)")
+                .title(Self::title(LogStream::Stdout, None))
+                .highlight_str("> ")
                 .step(4),
-            stderr: false,
+            stream: LogStream::Stdout,
+            lines: VecDeque::new(),
+            text: VecDeque::new(),
+            partial_line: false,
+            path: None,
+            query: None,
+        }
+    }
+
+    fn title(stream: LogStream, query: Option<&str>) -> String {
+        match query {
+            Some(query) => format!("Workload Log ({stream}) [/{query}] | {LOG_HELP}"),
+            None => format!("Workload Log ({stream}) | {LOG_HELP}"),
+        }
+    }
+
+    fn update_title(&mut self) {
+        let title = Self::title(self.stream, self.query.as_deref());
+        self.attr(Attribute::Title, AttrValue::Title(Title::from(title)));
+    }
+
+    fn render_line(&self, line: &str) -> Line<'static> {
+        let highlight = Style::default().fg(Color::Black).bg(Color::Yellow);
+        match self.query.as_deref() {
+            Some(query) if line.contains(query) => {
+                let mut spans = vec![];
+                let mut rest = line;
+                while let Some(start) = rest.find(query) {
+                    spans.push(Span::from(rest[..start].to_owned()));
+                    spans.push(Span::styled(query.to_owned(), highlight));
+                    rest = &rest[start + query.len()..];
+                }
+                spans.push(Span::from(rest.to_owned()));
+                Line::from(spans)
+            }
+            _ => Line::from(line.to_owned()),
+        }
+    }
+
+    fn show_text(&mut self) {
+        let text = Text::from(Vec::from(self.text.clone()));
+        self.attr(Attribute::Text, AttrValue::Text(text));
+    }
+
+    /// Renders the whole log again, highlighting matches of the current search
+    fn update_text(&mut self) {
+        self.text = self.lines.iter().map(|line| self.render_line(line)).collect();
+        self.show_text();
+    }
+
+    /// Appends new output to the log, dropping the oldest lines beyond [`MAX_LOG_LINES`]
+    fn append(&mut self, content: &str) {
+        let mut content = content.to_owned();
+        if self.partial_line
+            && let Some(last) = self.lines.pop_back()
+        {
+            self.text.pop_back();
+            content.insert_str(0, &last);
+        }
+        self.partial_line = !content.ends_with('\n');
+        for line in content.lines() {
+            self.text.push_back(self.render_line(line));
+            self.lines.push_back(line.to_owned());
+        }
+        let dropped = self.lines.len().saturating_sub(MAX_LOG_LINES);
+        self.lines.drain(..dropped);
+        self.text.drain(..dropped);
+        // stay on the same line while the old ones are dropped
+        let current = self.current_line().saturating_sub(dropped);
+        self.show_text();
+        self.component.states.list_index = current;
+    }
+
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.text.clear();
+        self.partial_line = false;
+        self.path = None;
+        self.show_text();
+    }
+
+    fn current_line(&self) -> usize {
+        self.component.states.list_index
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Moves to the next (or previous) line matching the search, wrapping around at the end of the log
+    fn goto_match(&mut self, forward: bool, include_current: bool) -> Option<Msg> {
+        let query = self.query.clone()?;
+        let matches: Vec<usize> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.contains(query.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+        if matches.is_empty() {
+            return Some(Msg::Status(StatusMsg::Warning(format!("no match for '{query}'"))));
         }
+        let current = self.current_line();
+        let target = if forward {
+            matches
+                .iter()
+                .find(|&&i| i > current || (include_current && i == current))
+                .unwrap_or(&matches[0])
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|&&i| i < current)
+                .unwrap_or(&matches[matches.len() - 1])
+        };
+        self.component.states.list_index = *target;
+        None
     }
 }
+
 impl AppComponent<Msg, UserEvent> for WorkloadLog {
     fn on(&mut self, ev: &Event<UserEvent>) -> Option<Msg> {
         let _ = match ev {
-            Event::User(UserEvent::Cscs(CscsEvent::GotJobLog(log))) => {
-                let l = log.clone();
-                let spans: Vec<PropValue> = l
-                    .lines()
-                    .map(|l| PropValue::TextSpan(Span::from(l.to_owned())))
-                    .collect();
-                self.attr(Attribute::Text, AttrValue::Payload(PropPayload::Vec(spans.clone())));
-                self.perform(Cmd::Change)
+            Event::User(UserEvent::Cscs(CscsEvent::GotJobLog(path, stream, content))) => {
+                if *stream != self.stream {
+                    // output of the previous log that was still on its way when switching
+                    return Some(Msg::None);
+                }
+                // keep following the end of the log if we're already there
+                let at_end = self.current_line() + 1 >= self.line_count();
+                self.path = Some(path.clone());
+                self.append(content);
+                if at_end {
+                    self.perform(Cmd::GoTo(Position::End));
+                }
+                CmdResult::Changed(self.state())
+            }
+            Event::User(UserEvent::Job(JobEvent::SearchLog(query))) => {
+                self.query = Some(query.clone());
+                self.update_title();
+                self.update_text();
+                return self.goto_match(true, true).or(Some(Msg::None));
             }
             Event::Keyboard(KeyEvent { code: Key::Down, .. }) => self.perform(Cmd::Move(Direction::Down)),
             Event::Keyboard(KeyEvent { code: Key::Up, .. }) => self.perform(Cmd::Move(Direction::Up)),
@@ -47,26 +193,49 @@ impl AppComponent<Msg, UserEvent> for WorkloadLog {
                 code: Key::PageDown, ..
             }) => self.perform(Cmd::Scroll(Direction::Down)),
             Event::Keyboard(KeyEvent { code: Key::PageUp, .. }) => self.perform(Cmd::Scroll(Direction::Up)),
-            Event::Keyboard(KeyEvent { code: Key::Home, .. }) => self.perform(Cmd::GoTo(Position::Begin)),
-            Event::Keyboard(KeyEvent { code: Key::End, .. }) => self.perform(Cmd::GoTo(Position::End)),
+            Event::Keyboard(KeyEvent { code: Key::Home, .. })
+            | Event::Keyboard(KeyEvent {
+                code: Key::Char('g'), ..
+            }) => self.perform(Cmd::GoTo(Position::Begin)),
+            Event::Keyboard(KeyEvent { code: Key::End, .. })
+            | Event::Keyboard(KeyEvent {
+                code: Key::Char('G'), ..
+            }) => self.perform(Cmd::GoTo(Position::End)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('/'), ..
+            }) => return Some(Msg::LogSearchPopup(LogSearchPopupMsg::Opened)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('n'), ..
+            }) => return self.goto_match(true, false).or(Some(Msg::None)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('N'), ..
+            }) => return self.goto_match(false, false).or(Some(Msg::None)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('d'), ..
+            }) => {
+                return match &self.path {
+                    Some(path) => Some(Msg::Job(JobMsg::DownloadLog(path.clone()))),
+                    None => Some(Msg::Status(StatusMsg::Warning("log doesn't exist yet".to_owned()))),
+                };
+            }
             Event::Keyboard(KeyEvent { code: Key::Tab, .. }) => {
-                self.stderr = !self.stderr;
-                if self.stderr {
-                    self.attr(
-                        Attribute::Title,
-                        AttrValue::Title(Title::from("Workload Log (stderr)".to_owned())),
-                    );
-                } else {
-                    self.attr(
-                        Attribute::Title,
-                        AttrValue::Title(Title::from("Workload Log (stdout)".to_owned())),
-                    );
-                }
-                // empty log view
-                self.attr(Attribute::Text, AttrValue::Payload(PropPayload::Vec(vec![])));
+                self.stream = match self.stream {
+                    LogStream::Stdout => LogStream::Stderr,
+                    LogStream::Stderr => LogStream::Stdout,
+                };
+                self.update_title();
+                // empty log view, the other log is loaded from its end
+                self.clear();
                 return Some(Msg::Job(JobMsg::Switch));
             }
             Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => {
+                if self.query.is_some() {
+                    // first clear the search, then close the log
+                    self.query = None;
+                    self.update_title();
+                    self.update_text();
+                    return Some(Msg::None);
+                }
                 return Some(Msg::Job(JobMsg::Close));
             }
             _ => CmdResult::NoChange,
@@ -74,3 +243,32 @@ impl AppComponent<Msg, UserEvent> for WorkloadLog {
         Some(Msg::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_event(stream: LogStream, content: &str) -> Event<UserEvent> {
+        Event::User(UserEvent::Cscs(CscsEvent::GotJobLog(
+            PathBuf::from("/scratch/job.out"),
+            stream,
+            content.to_owned(),
+        )))
+    }
+
+    #[test]
+    fn test_append_log() {
+        let mut log = WorkloadLog::new();
+        log.on(&log_event(LogStream::Stdout, "first\nsec"));
+        log.on(&log_event(LogStream::Stdout, "ond\n"));
+        // output of stderr still arriving after switching back to stdout
+        log.on(&log_event(LogStream::Stderr, "error\n"));
+        assert_eq!(log.lines, ["first", "second"]);
+
+        let output: String = (0..MAX_LOG_LINES).map(|i| format!("line {i}\n")).collect();
+        log.on(&log_event(LogStream::Stdout, &output));
+        assert_eq!(log.line_count(), MAX_LOG_LINES);
+        assert_eq!(log.lines.front().map(String::as_str), Some("line 0"));
+        assert_eq!(log.current_line(), MAX_LOG_LINES - 1);
+    }
+}
@@ -1,9 +1,9 @@
 #![allow(dead_code)] // Remove this once you start using the code
 
-use std::{collections::HashMap, env, path::PathBuf};
+use std::{collections::HashMap, env, path::PathBuf, sync::OnceLock};
 
 use color_eyre::{
-    Result,
+    Result, Section,
     eyre::{Context, ContextCompat, eyre},
 };
 use directories::ProjectDirs;
@@ -12,10 +12,26 @@ use serde::{Deserialize, Serialize};
 use strum_macros::{EnumIter, EnumString, VariantArray, VariantNames};
 use toml_edit::DocumentMut;
 
+use crate::{
+    cscs::{
+        api_client::{
+            client::CSCS_API_URL,
+            types::{JobStatus, LogStream},
+        },
+        oauth2::{CSCS_CLIENT_ID, CSCS_SCOPES, CSCS_URL},
+    },
+    util::secrets::SecretBackend,
+};
+
 const DEFAULT_CONFIG_TOML: &str = include_str!("../.config/config.toml");
 
 const DEFAULT_KEYS: &[&str] = &["name", "cscs.account"];
 
+/// The FirecREST installation used when no other site is configured
+pub const DEFAULT_SITE: &str = "cscs";
+
+static ACTIVE_SITE: OnceLock<String> = OnceLock::new();
+
 lazy_static! {
     pub static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_uppercase().to_string();
     pub static ref DATA_FOLDER: Option<PathBuf> = env::var(format!("{}_DATA", PROJECT_NAME.clone()))
@@ -34,7 +50,19 @@ pub struct SystemDescription {
 }
 
 #[derive(
-    Clone, Debug, Serialize, Deserialize, Default, strum::Display, EnumString, VariantNames, VariantArray, EnumIter,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    Default,
+    strum::Display,
+    EnumString,
+    VariantNames,
+    VariantArray,
+    EnumIter,
 )]
 #[strum(serialize_all = "lowercase")]
 #[allow(clippy::upper_case_acronyms)]
@@ -48,8 +76,102 @@ pub enum ComputePlatform {
     CW,
 }
 
+/// Which tasks of a multi-node job run an iroh endpoint for ssh, port forwarding and resource usage
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq, strum::Display, EnumString, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointMode {
+    /// the first task on every node
+    #[default]
+    PerNode,
+    /// only the task with rank 0
+    RankZero,
+}
+
+/// Where notifications about jobs are sent
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationSink {
+    Desktop,
+    Bell,
+    Webhook,
+}
+
+/// A regex that triggers a notification when a line of a job log matches it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogPattern {
+    pub pattern: String,
+    /// which log to search, both if not set
+    #[serde(default)]
+    pub stream: Option<LogStream>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct SecretsConfig {
+    /// where credentials and tokens are stored
+    #[serde(default)]
+    pub backend: SecretBackend,
+}
+
+/// A FirecREST installation coman can run jobs on
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SiteConfig {
+    /// base url of the FirecREST v2 api, `{platform}` is replaced with the current platform
+    pub api_url: String,
+    /// url of the OpenID Connect issuer handing out tokens for the api
+    pub auth_url: String,
+    /// public client to use for `coman cscs login --device`, if the site supports it
+    #[serde(default)]
+    pub device_client_id: Option<String>,
+    /// OAuth2 scopes to request when logging in, none if empty
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            api_url: CSCS_API_URL.to_owned(),
+            auth_url: CSCS_URL.to_owned(),
+            device_client_id: Some(CSCS_CLIENT_ID.to_owned()),
+            scopes: CSCS_SCOPES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl SiteConfig {
+    /// Whether the site has a separate api per compute platform, like CSCS does
+    pub fn has_platforms(&self) -> bool {
+        self.api_url.contains("{platform}")
+    }
+
+    pub fn api_url(&self, platform: &ComputePlatform) -> String {
+        self.api_url.replace("{platform}", &platform.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct NotificationConfig {
+    #[serde(default)]
+    pub sinks: Vec<NotificationSink>,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// statuses to notify about when a job changes to them, all if empty
+    #[serde(default)]
+    pub statuses: Vec<JobStatus>,
+    #[serde(default)]
+    pub log_patterns: Vec<LogPattern>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct CscsConfig {
+    /// FirecREST installation to talk to, one of `[sites]` or the builtin `cscs`
+    #[serde(default)]
+    pub site: Option<String>,
+    /// named identity whose credentials to use, see `coman cscs identity`
+    #[serde(default)]
+    pub identity: Option<String>,
     #[serde(default)]
     pub client_id: Option<String>,
     #[serde(default)]
@@ -66,6 +188,9 @@ pub struct CscsConfig {
     pub workdir: Option<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// paths on the cluster to mount into the container, keyed by the path on the cluster
+    #[serde(default)]
+    pub mounts: HashMap<String, String>,
     #[serde(default)]
     pub port_forward: Vec<u16>,
     #[serde(default)]
@@ -76,11 +201,105 @@ pub struct CscsConfig {
     pub ssh_key: Option<PathBuf>,
     #[serde(default)]
     pub command: Vec<String>,
+    /// how many parts of large files to transfer in parallel, use [`CscsConfig::transfer_parallelism`] to read it
+    #[serde(default = "default_transfer_parallelism")]
+    pub transfer_parallelism: usize,
+    #[serde(default)]
+    pub partition: Option<String>,
+    #[serde(default)]
+    pub reservation: Option<String>,
+    #[serde(default)]
+    pub constraints: Option<String>,
+    #[serde(default)]
+    pub nodes: Option<u32>,
+    #[serde(default)]
+    pub ntasks: Option<u32>,
+    #[serde(default)]
+    pub gpus_per_node: Option<u32>,
+    #[serde(default)]
+    pub walltime: Option<String>,
+    #[serde(default)]
+    pub endpoint_mode: EndpointMode,
 
     #[serde(default)]
     pub systems: HashMap<String, SystemDescription>,
 }
 
+/// Named set of job settings that overrides the `[cscs]` config when selected with `--profile`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub current_system: Option<String>,
+    #[serde(default)]
+    pub account: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    #[serde(default)]
+    pub workdir: Option<String>,
+    /// added to (or replacing) the environment variables of the `[cscs]` config
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// added to (or replacing) the mounts of the `[cscs]` config
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mounts: HashMap<String, String>,
+    #[serde(default)]
+    pub port_forward: Option<Vec<u16>>,
+    #[serde(default)]
+    pub partition: Option<String>,
+    #[serde(default)]
+    pub reservation: Option<String>,
+    #[serde(default)]
+    pub constraints: Option<String>,
+    #[serde(default)]
+    pub nodes: Option<u32>,
+    #[serde(default)]
+    pub ntasks: Option<u32>,
+    #[serde(default)]
+    pub gpus_per_node: Option<u32>,
+    #[serde(default)]
+    pub walltime: Option<String>,
+}
+
+impl Profile {
+    fn apply(&self, cscs: &mut CscsConfig) {
+        let profile = self.clone();
+        if let Some(system) = profile.current_system {
+            cscs.current_system = system;
+        }
+        if let Some(command) = profile.command {
+            cscs.command = command;
+        }
+        if let Some(port_forward) = profile.port_forward {
+            cscs.port_forward = port_forward;
+        }
+        cscs.account = profile.account.or(cscs.account.take());
+        cscs.image = profile.image.or(cscs.image.take());
+        cscs.workdir = profile.workdir.or(cscs.workdir.take());
+        cscs.partition = profile.partition.or(cscs.partition.take());
+        cscs.reservation = profile.reservation.or(cscs.reservation.take());
+        cscs.constraints = profile.constraints.or(cscs.constraints.take());
+        cscs.nodes = profile.nodes.or(cscs.nodes);
+        cscs.ntasks = profile.ntasks.or(cscs.ntasks);
+        cscs.gpus_per_node = profile.gpus_per_node.or(cscs.gpus_per_node);
+        cscs.walltime = profile.walltime.or(cscs.walltime.take());
+        cscs.env.extend(profile.env);
+        cscs.mounts.extend(profile.mounts);
+    }
+}
+
+fn default_transfer_parallelism() -> usize {
+    4
+}
+
+impl CscsConfig {
+    /// The configured transfer parallelism, at least one so transfers always make progress
+    pub fn transfer_parallelism(&self) -> usize {
+        self.transfer_parallelism.max(1)
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ComanConfig {
     #[serde(default)]
@@ -91,6 +310,69 @@ pub struct ComanConfig {
     pub update_check_interval_hours: u64,
     #[serde(default)]
     pub cscs: CscsConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    #[serde(default)]
+    pub secrets: SecretsConfig,
+    #[serde(default)]
+    pub sites: HashMap<String, SiteConfig>,
+}
+
+impl ComanConfig {
+    /// The site called `name`, sites defined in the config take precedence over the builtin `cscs` one
+    pub fn site(&self, name: &str) -> Result<SiteConfig> {
+        check_site_name(name)?;
+        if let Some(site) = self.sites.get(name) {
+            return Ok(site.clone());
+        }
+        if name == DEFAULT_SITE {
+            return Ok(SiteConfig::default());
+        }
+        let mut available: Vec<_> = self.sites.keys().cloned().collect();
+        available.sort();
+        available.insert(0, DEFAULT_SITE.to_owned());
+        Err(eyre!("unknown site {}", name)).with_suggestion(|| {
+            format!(
+                "define it as `[sites.{}]` in your coman.toml, available sites: {}",
+                name,
+                available.join(", ")
+            )
+        })
+    }
+
+    /// The site selected for this run, see [`active_site`]
+    pub fn current_site(&self) -> Result<SiteConfig> {
+        self.site(&active_site())
+    }
+}
+
+/// Site names end up in secret names, so we keep them simple
+fn check_site_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(eyre!("invalid site name `{}`", name))
+            .suggestion("site names can only contain letters, digits, `-` and `_`");
+    }
+    Ok(())
+}
+
+/// Selects the site to use for the rest of this run, instead of the configured one
+pub fn set_active_site(name: String) -> Result<()> {
+    Config::new()?.values.site(&name)?;
+    ACTIVE_SITE.set(name).map_err(|_| eyre!("a site was already selected"))
+}
+
+/// The FirecREST installation coman talks to, from `cscs.site` in the (project local) config
+pub fn active_site() -> String {
+    ACTIVE_SITE
+        .get_or_init(|| {
+            Config::new()
+                .ok()
+                .and_then(|c| c.values.cscs.site)
+                .unwrap_or(DEFAULT_SITE.to_owned())
+        })
+        .clone()
 }
 
 #[derive(Clone, Debug)]
@@ -197,12 +479,35 @@ fn implicit_table() -> toml_edit::Item {
     toml_edit::Item::Table(table)
 }
 
+/// Merges the default, global and project config, and applies the given profile on top
+fn build_values(global_layer: &Layer, project_layer: &Option<Layer>, profile: Option<&str>) -> Result<ComanConfig> {
+    let mut builder =
+        config::Config::builder().add_source(config::File::from_str(DEFAULT_CONFIG_TOML, config::FileFormat::Toml));
+    builder = builder.add_source(config::File::from_str(
+        &global_layer.data.to_string(),
+        config::FileFormat::Toml,
+    ));
+    if let Some(project_layer) = project_layer {
+        builder = builder.add_source(config::File::from_str(
+            &project_layer.data.to_string(),
+            config::FileFormat::Toml,
+        ));
+    }
+    let mut values: ComanConfig = builder.build()?.try_deserialize()?;
+    if let Some(profile) = profile.and_then(|name| values.profiles.get(name)).cloned() {
+        profile.apply(&mut values.cscs);
+    }
+    Ok(values)
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub values: ComanConfig,
     default_layer: toml_edit::DocumentMut,
     global_layer: Layer,
     project_layer: Option<Layer>,
+    /// profile applied on top of the `[cscs]` config
+    profile: Option<String>,
 }
 
 impl Config {
@@ -210,28 +515,46 @@ impl Config {
         let default_layer: DocumentMut = DEFAULT_CONFIG_TOML.parse()?;
         let global_layer = global_config_layer()?;
         let project_layer = project_local_config_layer()?;
-        let mut builder =
-            config::Config::builder().add_source(config::File::from_str(DEFAULT_CONFIG_TOML, config::FileFormat::Toml));
-        builder = builder.add_source(config::File::from_str(
-            &global_layer.data.to_string(),
-            config::FileFormat::Toml,
-        ));
-        if let Some(project_layer) = project_layer.clone() {
-            builder = builder.add_source(config::File::from_str(
-                &project_layer.data.to_string(),
-                config::FileFormat::Toml,
-            ));
-        }
-
-        let cfg: ComanConfig = builder.build()?.try_deserialize()?;
+        let cfg = build_values(&global_layer, &project_layer, None)?;
 
         Ok(Self {
             values: cfg,
             default_layer,
             global_layer,
             project_layer,
+            profile: None,
+        })
+    }
+
+    /// Only the builtin defaults, without reading any config files
+    #[cfg(test)]
+    pub fn defaults() -> Result<Self> {
+        let global_layer = Layer {
+            source: PathBuf::new(),
+            data: DocumentMut::new(),
+        };
+        Ok(Self {
+            values: build_values(&global_layer, &None, None)?,
+            default_layer: DEFAULT_CONFIG_TOML.parse()?,
+            global_layer,
+            project_layer: None,
+            profile: None,
         })
     }
+
+    /// Applies a profile on top of the `[cscs]` config, e.g. the one selected with `job submit --profile`
+    pub fn with_profile(mut self, name: &str) -> Result<Self> {
+        if !self.values.profiles.contains_key(name) {
+            let available = self.values.profiles.keys().cloned().collect::<Vec<_>>().join(", ");
+            return Err(eyre!("unknown profile {}", name)).with_suggestion(|| match available.is_empty() {
+                true => "no profiles are defined, add them as `[profiles.<name>]` to your coman.toml".to_owned(),
+                false => format!("available profiles: {available}"),
+            });
+        }
+        self.profile = Some(name.to_owned());
+        self.values = build_values(&self.global_layer, &self.project_layer, self.profile.as_deref())?;
+        Ok(self)
+    }
     pub fn create_project_config(destination: Option<PathBuf>, name: Option<String>) -> Result<()> {
         let project_dir = destination
             .unwrap_or(std::env::current_dir().expect("current directory does not exist"))
@@ -283,19 +606,7 @@ impl Config {
         }
 
         // reload config
-        let mut builder =
-            config::Config::builder().add_source(config::File::from_str(DEFAULT_CONFIG_TOML, config::FileFormat::Toml));
-        builder = builder.add_source(config::File::from_str(
-            &self.global_layer.data.to_string(),
-            config::FileFormat::Toml,
-        ));
-        if let Some(project_layer) = self.project_layer.clone() {
-            builder = builder.add_source(config::File::from_str(
-                &project_layer.data.to_string(),
-                config::FileFormat::Toml,
-            ));
-        }
-        self.values = builder.build()?.try_deserialize()?;
+        self.values = build_values(&self.global_layer, &self.project_layer, self.profile.as_deref())?;
         Ok(())
     }
 
@@ -320,20 +631,7 @@ impl Config {
     }
 
     pub fn validate(&mut self) -> Result<()> {
-        let mut builder =
-            config::Config::builder().add_source(config::File::from_str(DEFAULT_CONFIG_TOML, config::FileFormat::Toml));
-        builder = builder.add_source(config::File::from_str(
-            &self.global_layer.data.to_string(),
-            config::FileFormat::Toml,
-        ));
-        if let Some(project_layer) = self.project_layer.clone() {
-            builder = builder.add_source(config::File::from_str(
-                &project_layer.data.to_string(),
-                config::FileFormat::Toml,
-            ));
-        }
-
-        let _cfg: ComanConfig = builder.build()?.try_deserialize().wrap_err("invalid config")?;
+        build_values(&self.global_layer, &self.project_layer, self.profile.as_deref()).wrap_err("invalid config")?;
         Ok(())
     }
 
@@ -431,6 +729,158 @@ mod tests {
     use tempfile::tempdir;
 
     use super::*;
+    use crate::util::shell::shell_quote_filter;
+
+    #[test]
+    fn test_default_sbatch_template_slurm_options() {
+        let config: ComanConfig = toml::from_str(DEFAULT_CONFIG_TOML).expect("couldn't parse default config");
+        let mut tera = tera::Tera::default();
+        tera.register_filter("shell_quote", shell_quote_filter);
+        tera.add_raw_template("script.sh", &config.cscs.sbatch_script_template)
+            .expect("couldn't parse template");
+        let mut context = tera::Context::new();
+        context.insert("name", "test");
+        context.insert("command", "hostname");
+        context.insert("walltime", &config.cscs.walltime);
+        context.insert("nodes", &None::<u32>);
+        context.insert("ntasks", &None::<u32>);
+        context.insert("gpus_per_node", &None::<u32>);
+        context.insert("sweep", &HashMap::<String, String>::new());
+        let script = tera.render("script.sh", &context).expect("couldn't render template");
+        assert!(script.contains("#SBATCH --ntasks=1\n#SBATCH --time=10:00:00\nsrun"));
+
+        context.insert("nodes", &Some(2));
+        context.insert("gpus_per_node", &Some(4));
+        let script = tera.render("script.sh", &context).expect("couldn't render template");
+        assert!(script.contains("#SBATCH --nodes=2\n#SBATCH --ntasks=2\n#SBATCH --gpus-per-node=4\n"));
+
+        context.insert("sweep", &HashMap::from([("LR", "0.1")]));
+        let script = tera.render("script.sh", &context).expect("couldn't render template");
+        assert!(script.contains("export LR='0.1'\nsrun"));
+
+        context.insert("sweep", &HashMap::from([("CMD", "\"$(id)\" 'x'")]));
+        let script = tera.render("script.sh", &context).expect("couldn't render template");
+        assert!(script.contains(r#"export CMD='"$(id)" '\''x'\'''"#));
+    }
+
+    #[test]
+    fn test_default_edf_template_env() {
+        let config: ComanConfig = toml::from_str(DEFAULT_CONFIG_TOML).expect("couldn't parse default config");
+        let mut tera = tera::Tera::default();
+        tera.add_raw_template("environment.toml", &config.cscs.edf_file_template)
+            .expect("couldn't parse template");
+        let mut context = tera::Context::new();
+        context.insert("container_workdir", "/workdir");
+        context.insert("mount", &HashMap::<String, String>::new());
+        context.insert("env", &HashMap::from([("GREETING", "say \"hi\" to $USER")]));
+        let edf = tera
+            .render("environment.toml", &context)
+            .expect("couldn't render template");
+        let edf: toml::Table = toml::from_str(&edf).expect("rendered edf isn't valid toml");
+        assert_eq!(edf["env"]["GREETING"].as_str(), Some("say \"hi\" to $USER"));
+    }
+
+    #[test]
+    fn test_notification_config() {
+        let config: ComanConfig = toml::from_str(
+            r#"
+[notifications]
+sinks = ["desktop", "webhook"]
+webhook_url = "https://example.com/hook"
+statuses = ["running", "failed"]
+
+[[notifications.log_patterns]]
+pattern = "out of memory"
+stream = "stderr"
+"#,
+        )
+        .expect("couldn't parse notification config");
+        assert_eq!(
+            config.notifications.sinks,
+            vec![NotificationSink::Desktop, NotificationSink::Webhook]
+        );
+        assert_eq!(
+            config.notifications.statuses,
+            vec![JobStatus::Running, JobStatus::Failed]
+        );
+        assert_eq!(config.notifications.log_patterns[0].stream, Some(LogStream::Stderr));
+        // the config is written back the way it was read
+        let written = toml::to_string(&config.notifications).expect("couldn't write notification config");
+        assert!(written.contains(r#"statuses = ["running", "failed"]"#));
+        let reread: NotificationConfig = toml::from_str(&written).expect("couldn't parse written config");
+        assert_eq!(reread.statuses, config.notifications.statuses);
+    }
+
+    #[test]
+    fn test_transfer_parallelism() {
+        let config: CscsConfig = toml::from_str("").expect("couldn't parse config");
+        assert_eq!(config.transfer_parallelism(), 4);
+        let config: CscsConfig = toml::from_str("transfer_parallelism = 0").expect("couldn't parse config");
+        assert_eq!(config.transfer_parallelism(), 1);
+    }
+
+    #[test]
+    fn test_profiles_layering() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let global_config = temp_dir.path().join("global.toml");
+        let project_config = temp_dir.path().join("project.toml");
+        std::fs::write(
+            &global_config,
+            "[cscs.env]\nA = \"1\"\n\n[profiles.train]\nnodes = 4\nimage = \"pytorch\"\nenv = { B = \"2\" }\n",
+        )
+        .expect("couldn't write config file");
+        std::fs::write(&project_config, "[profiles.train]\nimage = \"project/pytorch\"\n")
+            .expect("couldn't write config file");
+        let global_layer = Layer::from_path(global_config).expect("couldn't create global layer");
+        let project_layer = Layer::from_path(project_config).expect("couldn't create project layer");
+
+        let project_layer = Some(project_layer);
+        let values = build_values(&global_layer, &project_layer, None).expect("couldn't build config");
+        let profile = values.profiles["train"].clone();
+        assert_eq!(profile.nodes, Some(4));
+        assert_eq!(profile.image.as_deref(), Some("project/pytorch"));
+        assert_eq!(values.cscs.nodes, None);
+
+        let values = build_values(&global_layer, &project_layer, Some("train")).expect("couldn't build config");
+        assert_eq!(values.cscs.nodes, Some(4));
+        assert_eq!(values.cscs.image.as_deref(), Some("project/pytorch"));
+        assert_eq!(values.cscs.env["A"], "1");
+        assert_eq!(values.cscs.env["B"], "2");
+        assert_eq!(values.cscs.walltime.as_deref(), Some("10:00:00"));
+    }
+
+    #[test]
+    fn test_sites() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let global_config = temp_dir.path().join("global.toml");
+        std::fs::write(
+            &global_config,
+            "[sites.other]\napi_url = \"https://firecrest.example.com/v2/\"\nauth_url = \"https://auth.example.com/realms/hpc\"\n",
+        )
+        .expect("couldn't write config file");
+        let global_layer = Layer::from_path(global_config).expect("couldn't create global layer");
+        let values = build_values(&global_layer, &None, None).expect("couldn't build config");
+
+        let cscs = values.site(DEFAULT_SITE).expect("builtin site missing");
+        assert!(cscs.has_platforms());
+        assert_eq!(
+            cscs.api_url(&ComputePlatform::ML),
+            "https://api.cscs.ch/ml/firecrest/v2/"
+        );
+
+        let other = values.site("other").expect("configured site missing");
+        assert!(!other.has_platforms());
+        assert_eq!(
+            other.api_url(&ComputePlatform::HPC),
+            "https://firecrest.example.com/v2/"
+        );
+        assert_eq!(other.device_client_id, None);
+        assert!(other.scopes.is_empty());
+        assert_eq!(cscs.scopes, ["profile", "firecrest", "firecrest-v2"]);
+
+        assert!(values.site("missing").is_err());
+        assert!(values.site("no spaces").is_err());
+    }
 
     #[test]
     fn test_get_project_local_config() {
@@ -439,7 +889,9 @@ mod tests {
         let pwd = temp_dir.path().join("sub").join("folder");
         std::fs::create_dir_all(pwd.clone()).expect("couldn't create dir");
         let mut locked_cwd = Cwd::mutex().lock().expect("couldn't get cwd lock");
-        locked_cwd.set(&pwd).expect("couldn't set current dir");
+        // resets the current dir when the test ends, before the temp dir is deleted
+        let mut cwd = CwdGuard::try_from(&mut *locked_cwd).expect("couldn't get current dir");
+        cwd.set(&pwd).expect("couldn't set current dir");
         assert_eq!(pwd, std::env::current_dir().expect("couldn't get current dir"));
 
         let config = temp_dir.path().join("coman.toml");
@@ -480,7 +932,9 @@ mod tests {
         let project_dir = tempdir().expect("couldn't create temp dir");
 
         let mut locked_cwd = Cwd::mutex().lock().expect("couldn't get cwd lock");
-        locked_cwd.set(&project_dir).expect("couldn't set current dir");
+        // resets the current dir when the test ends, before the temp dir is deleted
+        let mut cwd = CwdGuard::try_from(&mut *locked_cwd).expect("couldn't get current dir");
+        cwd.set(&project_dir).expect("couldn't set current dir");
         assert_eq!(
             project_dir.path(),
             std::env::current_dir().expect("couldn't get current dir")
@@ -524,6 +978,7 @@ mod tests {
             default_layer,
             global_layer,
             project_layer: Some(project_layer),
+            profile: None,
         };
 
         assert_eq!(conf.values.cscs.current_system, "project");
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use color_eyre::eyre::Result;
+use reqwest::Url;
+
+use crate::cscs::api_client::{
+    client::JobStartOptions,
+    types::{ArchiveCompression, FileStat, Job, JobDetail, JobId, JobStatus, PathEntry, S3Upload, System, UserInfo},
+};
+
+/// An HPC installation coman can run jobs on.
+///
+/// Handlers only talk to the cluster through this trait, [`CscsApi`](super::client::CscsApi) implements it for
+/// FirecREST, wherever that runs (see `[sites]` in the config). Tests use the in memory `FakeBackend` instead.
+#[tuirealm::async_trait]
+pub trait ComputeBackend: Send + Sync {
+    // systems
+    async fn list_systems(&self) -> Result<Vec<System>>;
+    async fn get_system(&self, system: &str) -> Result<Option<System>> {
+        let systems = self.list_systems().await?;
+        Ok(systems.into_iter().find(|s| s.name == system))
+    }
+
+    // jobs
+    async fn start_job(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        name: &str,
+        script_path: PathBuf,
+        envvars: HashMap<String, String>,
+        options: JobStartOptions,
+    ) -> Result<Option<JobId>>;
+    async fn list_jobs(
+        &self,
+        status: Option<Vec<JobStatus>>,
+        system_name: &str,
+        all_users: Option<bool>,
+    ) -> Result<Vec<Job>>;
+    async fn get_job(&self, system_name: &str, job_id: JobId) -> Result<Option<JobDetail>>;
+    async fn cancel_job(&self, system_name: &str, job_id: JobId) -> Result<()>;
+
+    // files, on the file system of a system
+    async fn mkdir(&self, system_name: &str, path: PathBuf) -> Result<()>;
+    async fn chmod(&self, system_name: &str, path: PathBuf, mode: &str) -> Result<()>;
+    async fn chown(&self, system_name: &str, path: PathBuf, owner: Option<String>, group: Option<String>)
+    -> Result<()>;
+    async fn rm_path(&self, system_name: &str, path: PathBuf) -> Result<()>;
+    async fn symlink(&self, system_name: &str, path: PathBuf, link_path: PathBuf) -> Result<()>;
+    async fn upload(&self, system_name: &str, target: PathBuf, file: Vec<u8>) -> Result<()>;
+    async fn download(&self, system_name: &str, path: PathBuf) -> Result<Vec<u8>>;
+    async fn tail(&self, system_name: &str, path: PathBuf, lines: usize) -> Result<String>;
+    async fn head(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        lines: Option<usize>,
+        bytes: Option<usize>,
+    ) -> Result<String>;
+    async fn view(&self, system_name: &str, path: PathBuf, size: usize, offset: usize) -> Result<String>;
+    async fn list_path(&self, system_name: &str, path: PathBuf, show_hidden: bool) -> Result<Vec<PathEntry>>;
+    async fn checksum(&self, system_name: &str, path: PathBuf) -> Result<Option<String>>;
+    async fn stat_path(&self, system_name: &str, path: PathBuf) -> Result<Option<FileStat>>;
+    async fn file_type(&self, system_name: &str, path: PathBuf) -> Result<Option<String>>;
+    async fn compress(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<()>;
+    async fn extract(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<()>;
+
+    // transfers of large files and long running operations, these run as a job on the system
+    async fn transfer_upload(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        target: PathBuf,
+        size: i64,
+    ) -> Result<(JobId, S3Upload)>;
+    async fn transfer_download(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+    ) -> Result<(JobId, Url)>;
+    async fn transfer_copy(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+    ) -> Result<JobId>;
+    async fn transfer_move(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+    ) -> Result<JobId>;
+    async fn transfer_rm(&self, system_name: &str, account: Option<String>, path: PathBuf) -> Result<JobId>;
+    async fn transfer_compress(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<JobId>;
+    async fn transfer_extract(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<JobId>;
+
+    // user info
+    async fn get_userinfo(&self, system_name: &str) -> Result<UserInfo>;
+}
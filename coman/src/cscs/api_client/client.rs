@@ -9,29 +9,45 @@ use firecrest_client::{
         get_compute_system_jobs, post_compute_system_job,
     },
     filesystem_api::{
-        delete_filesystem_ops_rm, get_filesystem_ops_checksum, get_filesystem_ops_download, get_filesystem_ops_ls,
-        get_filesystem_ops_stat, get_filesystem_ops_tail, post_filesystem_ops_mkdir, post_filesystem_ops_upload,
-        post_filesystem_transfer_download, post_filesystem_transfer_upload, put_filesystem_ops_chmod,
+        delete_filesystem_ops_rm, delete_filesystem_transfer_rm, get_filesystem_ops_checksum,
+        get_filesystem_ops_download, get_filesystem_ops_file, get_filesystem_ops_head, get_filesystem_ops_ls,
+        get_filesystem_ops_stat, get_filesystem_ops_tail, get_filesystem_ops_view, post_filesystem_ops_compress,
+        post_filesystem_ops_extract, post_filesystem_ops_mkdir, post_filesystem_ops_symlink,
+        post_filesystem_ops_upload, post_filesystem_transfer_compress, post_filesystem_transfer_cp,
+        post_filesystem_transfer_download, post_filesystem_transfer_extract, post_filesystem_transfer_mv,
+        post_filesystem_transfer_upload, put_filesystem_ops_chmod, put_filesystem_ops_chown,
     },
     status_api::{get_status_systems, get_status_userinfo},
     types::DownloadFileResponseTransferDirectives,
 };
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::{ComputePlatform, Config},
-    cscs::api_client::types::{FileStat, Job, JobDetail, JobId, JobStatus, PathEntry, S3Upload, System, UserInfo},
+    config::{ComputePlatform, CscsConfig, SiteConfig},
+    cscs::{
+        api_client::{
+            backend::ComputeBackend,
+            types::{
+                ArchiveCompression, FileStat, Job, JobDependency, JobDetail, JobId, JobStatus, PathEntry, S3Upload,
+                System, UserInfo,
+            },
+        },
+        sweep::SweepGrid,
+    },
     trace_dbg,
     util::types::DockerImageUrl,
 };
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ScriptSpec {
     #[default]
     Generate,
     Local(PathBuf),
     Remote(PathBuf),
 }
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum EdfSpec {
     #[default]
     Generate,
@@ -39,6 +55,47 @@ pub enum EdfSpec {
     Remote(PathBuf),
 }
 
+/// Slurm resources to request for a job
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SlurmOptions {
+    pub partition: Option<String>,
+    pub reservation: Option<String>,
+    pub constraints: Option<String>,
+    pub nodes: Option<u32>,
+    pub ntasks: Option<u32>,
+    pub gpus_per_node: Option<u32>,
+    pub walltime: Option<String>,
+}
+
+impl SlurmOptions {
+    /// Uses the values of `other` for everything that isn't set in `self`
+    pub fn or(self, other: SlurmOptions) -> Self {
+        Self {
+            partition: self.partition.or(other.partition),
+            reservation: self.reservation.or(other.reservation),
+            constraints: self.constraints.or(other.constraints),
+            nodes: self.nodes.or(other.nodes),
+            ntasks: self.ntasks.or(other.ntasks),
+            gpus_per_node: self.gpus_per_node.or(other.gpus_per_node),
+            walltime: self.walltime.or(other.walltime),
+        }
+    }
+}
+
+impl From<&CscsConfig> for SlurmOptions {
+    fn from(value: &CscsConfig) -> Self {
+        Self {
+            partition: value.partition.clone(),
+            reservation: value.reservation.clone(),
+            constraints: value.constraints.clone(),
+            nodes: value.nodes,
+            ntasks: value.ntasks,
+            gpus_per_node: value.gpus_per_node,
+            walltime: value.walltime.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct JobStartOptions {
     pub image: Option<DockerImageUrl>,
@@ -54,24 +111,30 @@ pub struct JobStartOptions {
     pub no_ssh: bool,
     pub ssh_key: Option<PathBuf>,
     pub no_coman: bool,
+    pub slurm: SlurmOptions,
+    pub sweep: SweepGrid,
+    pub dependencies: Vec<JobDependency>,
 }
 
+/// FirecREST api of CSCS, `{platform}` is replaced with the compute platform
+pub(crate) const CSCS_API_URL: &str = "https://api.cscs.ch/{platform}/firecrest/v2/";
+
 pub struct CscsApi {
     client: FirecrestClient,
 }
 
 impl CscsApi {
-    pub fn new(token: String, platform: Option<ComputePlatform>) -> Result<Self> {
-        let config = Config::new()?;
+    pub fn new(token: String, site: &SiteConfig, platform: &ComputePlatform) -> Result<Self> {
         let client = FirecrestClient::default()
-            .base_path(format!(
-                "https://api.cscs.ch/{}/firecrest/v2/",
-                platform.unwrap_or(config.values.cscs.current_platform)
-            ))?
+            .base_path(site.api_url(platform))?
             .token(token);
         Ok(Self { client })
     }
-    pub async fn start_job(
+}
+
+#[tuirealm::async_trait]
+impl ComputeBackend for CscsApi {
+    async fn start_job(
         &self,
         system_name: &str,
         account: Option<String>,
@@ -94,23 +157,22 @@ impl CscsApi {
                 envvars,
                 stdout: options.stdout,
                 stderr: options.stderr,
+                partition: options.slurm.partition,
+                reservation: options.slurm.reservation,
+                constraints: options.slurm.constraints,
             },
         )
         .await?;
 
         Ok(result.job_id.map(|i| i.into()))
     }
-    pub async fn get_system(&self, system: &str) -> Result<Option<System>> {
-        let systems = self.list_systems().await?;
-        Ok(systems.into_iter().find(|s| s.name == system))
-    }
-    pub async fn list_systems(&self) -> Result<Vec<System>> {
+    async fn list_systems(&self) -> Result<Vec<System>> {
         let result = get_status_systems(&self.client)
             .await
             .wrap_err("couldn't list CSCS systems")?;
         Ok(result.systems.into_iter().map(|s| s.into()).collect())
     }
-    pub async fn list_jobs(
+    async fn list_jobs(
         &self,
         status: Option<Vec<JobStatus>>,
         system_name: &str,
@@ -128,7 +190,7 @@ impl CscsApi {
         }
         Ok(result)
     }
-    pub async fn get_job(&self, system_name: &str, job_id: JobId) -> Result<Option<JobDetail>> {
+    async fn get_job(&self, system_name: &str, job_id: JobId) -> Result<Option<JobDetail>> {
         let jobs = get_compute_system_job(&self.client, system_name, job_id.clone().into_string())
             .await
             .wrap_err("couldn't fetch job info")?;
@@ -152,38 +214,38 @@ impl CscsApi {
         Ok(Some((job, job_metadata).into()))
     }
 
-    pub async fn cancel_job(&self, system_name: &str, job_id: JobId) -> Result<()> {
+    async fn cancel_job(&self, system_name: &str, job_id: JobId) -> Result<()> {
         cancel_compute_system_job(&self.client, system_name, job_id.into_string())
             .await
             .wrap_err("couldn't delete job")?;
         Ok(())
     }
 
-    pub async fn mkdir(&self, system_name: &str, path: PathBuf) -> Result<()> {
+    async fn mkdir(&self, system_name: &str, path: PathBuf) -> Result<()> {
         let _ = post_filesystem_ops_mkdir(&self.client, system_name, path)
             .await
             .wrap_err("couldn't create directory")?;
         Ok(())
     }
-    pub async fn chmod(&self, system_name: &str, path: PathBuf, mode: &str) -> Result<()> {
+    async fn chmod(&self, system_name: &str, path: PathBuf, mode: &str) -> Result<()> {
         let _ = put_filesystem_ops_chmod(&self.client, system_name, path, mode)
             .await
             .wrap_err("couldn't change directory permission")?;
         Ok(())
     }
-    pub async fn rm_path(&self, system_name: &str, path: PathBuf) -> Result<()> {
+    async fn rm_path(&self, system_name: &str, path: PathBuf) -> Result<()> {
         delete_filesystem_ops_rm(&self.client, system_name, path)
             .await
             .wrap_err("couldn't remove remote file")?;
         Ok(())
     }
-    pub async fn upload(&self, system_name: &str, target: PathBuf, file: Vec<u8>) -> Result<()> {
+    async fn upload(&self, system_name: &str, target: PathBuf, file: Vec<u8>) -> Result<()> {
         post_filesystem_ops_upload(&self.client, system_name, target, file)
             .await
             .wrap_err("couldn't upload file")?;
         Ok(())
     }
-    pub async fn transfer_upload(
+    async fn transfer_upload(
         &self,
         system_name: &str,
         account: Option<String>,
@@ -203,13 +265,13 @@ impl CscsApi {
             Err(eyre!("didn't get S3 transfer directive"))
         }
     }
-    pub async fn download(&self, system_name: &str, path: PathBuf) -> Result<String> {
+    async fn download(&self, system_name: &str, path: PathBuf) -> Result<Vec<u8>> {
         let content = get_filesystem_ops_download(&self.client, system_name, path)
             .await
             .wrap_err("couldn't download file")?;
         Ok(content)
     }
-    pub async fn transfer_download(
+    async fn transfer_download(
         &self,
         system_name: &str,
         account: Option<String>,
@@ -225,7 +287,7 @@ impl CscsApi {
             Err(eyre!("didn't get S3 transfer directive"))
         }
     }
-    pub async fn tail(&self, system_name: &str, path: PathBuf, lines: usize) -> Result<String> {
+    async fn tail(&self, system_name: &str, path: PathBuf, lines: usize) -> Result<String> {
         let result = get_filesystem_ops_tail(&self.client, system_name, path, lines)
             .await
             .wrap_err("couldn't tail file")?;
@@ -234,7 +296,7 @@ impl CscsApi {
             None => Ok("".to_string()),
         }
     }
-    pub async fn list_path(&self, system_name: &str, path: PathBuf, show_hidden: bool) -> Result<Vec<PathEntry>> {
+    async fn list_path(&self, system_name: &str, path: PathBuf, show_hidden: bool) -> Result<Vec<PathEntry>> {
         let result = get_filesystem_ops_ls(&self.client, system_name, path, show_hidden)
             .await
             .wrap_err("couldn't list path")?;
@@ -243,18 +305,155 @@ impl CscsApi {
             None => Ok(vec![]),
         }
     }
-    pub async fn checksum(&self, system_name: &str, path: PathBuf) -> Result<Option<String>> {
+    async fn checksum(&self, system_name: &str, path: PathBuf) -> Result<Option<String>> {
         get_filesystem_ops_checksum(&self.client, system_name, path)
             .await
             .wrap_err("couldn't stat file")
     }
-    pub async fn stat_path(&self, system_name: &str, path: PathBuf) -> Result<Option<FileStat>> {
+    async fn stat_path(&self, system_name: &str, path: PathBuf) -> Result<Option<FileStat>> {
         let result = get_filesystem_ops_stat(&self.client, system_name, path)
             .await
             .wrap_err("couldn't stat file")?;
         Ok(result.output.map(|f| f.into()))
     }
-    pub async fn get_userinfo(&self, system_name: &str) -> Result<UserInfo> {
+    async fn head(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        lines: Option<usize>,
+        bytes: Option<usize>,
+    ) -> Result<String> {
+        let result = get_filesystem_ops_head(&self.client, system_name, path, lines, bytes)
+            .await
+            .wrap_err("couldn't read head of file")?;
+        match result.output {
+            Some(output) => Ok(output.content),
+            None => Ok("".to_string()),
+        }
+    }
+    async fn view(&self, system_name: &str, path: PathBuf, size: usize, offset: usize) -> Result<String> {
+        let result = get_filesystem_ops_view(&self.client, system_name, path, size, offset)
+            .await
+            .wrap_err("couldn't view file")?;
+        Ok(result.output.unwrap_or_default())
+    }
+    async fn file_type(&self, system_name: &str, path: PathBuf) -> Result<Option<String>> {
+        let result = get_filesystem_ops_file(&self.client, system_name, path)
+            .await
+            .wrap_err("couldn't get file type")?;
+        Ok(result.output)
+    }
+    async fn symlink(&self, system_name: &str, path: PathBuf, link_path: PathBuf) -> Result<()> {
+        let _ = post_filesystem_ops_symlink(&self.client, system_name, path, link_path)
+            .await
+            .wrap_err("couldn't create symbolic link")?;
+        Ok(())
+    }
+    async fn chown(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        owner: Option<String>,
+        group: Option<String>,
+    ) -> Result<()> {
+        let _ = put_filesystem_ops_chown(&self.client, system_name, path, owner, group)
+            .await
+            .wrap_err("couldn't change owner")?;
+        Ok(())
+    }
+    async fn transfer_copy(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+    ) -> Result<JobId> {
+        let result = post_filesystem_transfer_cp(&self.client, system_name, account, path, target)
+            .await
+            .wrap_err("couldn't copy path")?;
+        Ok(result.transfer_job.job_id.into())
+    }
+    async fn transfer_move(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+    ) -> Result<JobId> {
+        let result = post_filesystem_transfer_mv(&self.client, system_name, account, path, target)
+            .await
+            .wrap_err("couldn't move path")?;
+        Ok(result.transfer_job.job_id.into())
+    }
+    async fn transfer_rm(&self, system_name: &str, account: Option<String>, path: PathBuf) -> Result<JobId> {
+        let result = delete_filesystem_transfer_rm(&self.client, system_name, account, path)
+            .await
+            .wrap_err("couldn't remove remote path")?;
+        Ok(result.transfer_job.job_id.into())
+    }
+    async fn transfer_compress(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<JobId> {
+        let result = post_filesystem_transfer_compress(
+            &self.client,
+            system_name,
+            account,
+            path,
+            target,
+            Some(compression.into()),
+        )
+        .await
+        .wrap_err("couldn't compress path")?;
+        Ok(result.transfer_job.job_id.into())
+    }
+    async fn compress(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<()> {
+        post_filesystem_ops_compress(&self.client, system_name, path, target, Some(compression.into()))
+            .await
+            .wrap_err("couldn't compress path")
+    }
+    async fn extract(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<()> {
+        post_filesystem_ops_extract(&self.client, system_name, path, target, Some(compression.into()))
+            .await
+            .wrap_err("couldn't extract archive")
+    }
+    async fn transfer_extract(
+        &self,
+        system_name: &str,
+        account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+        compression: ArchiveCompression,
+    ) -> Result<JobId> {
+        let result = post_filesystem_transfer_extract(
+            &self.client,
+            system_name,
+            account,
+            path,
+            target,
+            Some(compression.into()),
+        )
+        .await
+        .wrap_err("couldn't extract archive")?;
+        Ok(result.transfer_job.job_id.into())
+    }
+    async fn get_userinfo(&self, system_name: &str) -> Result<UserInfo> {
         let result = get_status_userinfo(&self.client, system_name)
             .await
             .wrap_err("couldn't load user info")?;
@@ -266,8 +465,9 @@ impl CscsApi {
 mod tests {
     use claim::*;
     use firecrest_client::types::{
-        DownloadFileResponse, GetJobMetadataResponse, GetJobResponse, GetSystemsResponse, HPCCluster, JobMetadataModel,
-        JobModel, JobStatus, PostJobSubmissionResponse, S3TransferResponse, TransferJob, UploadFileResponse,
+        CompressResponse, DownloadFileResponse, FileContent, GetFileHeadResponse, GetJobMetadataResponse,
+        GetJobResponse, GetSystemsResponse, HPCCluster, JobMetadataModel, JobModel, JobStatus,
+        PostJobSubmissionResponse, S3TransferResponse, TransferJob, UploadFileResponse,
     };
     use injectorpp::interface::injector::*;
 
@@ -297,7 +497,10 @@ mod tests {
                         working_dir: None,
                         envvars: HashMap::new(),
                         stdout: None,
-                        stderr: None
+                        stderr: None,
+                        partition: None,
+                        reservation: None,
+                        constraints: None,
                     }
                 ),
                 Result<PostJobSubmissionResponse>
@@ -551,4 +754,83 @@ mod tests {
         assert_eq!(result.0.as_ref(), "1");
         assert_eq!(result.1, Url::parse("http://download").unwrap())
     }
+
+    #[tokio::test]
+    async fn test_head() {
+        let client = get_client();
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(
+                firecrest_client::filesystem_api::get_filesystem_ops_head(
+                    &client.client,
+                    "",
+                    PathBuf::from(""),
+                    None,
+                    None,
+                ),
+                Result<GetFileHeadResponse>
+            ))
+            .will_return_async(injectorpp::async_return!(
+                Ok(GetFileHeadResponse {
+                    output: Some(FileContent {
+                        content: "first line\n".to_owned(),
+                        ..Default::default()
+                    })
+                }),
+                Result<GetFileHeadResponse>
+            ));
+        let result = client.head("test", PathBuf::from("/test"), Some(1), None).await;
+        assert_eq!(result.unwrap(), "first line\n");
+    }
+
+    #[tokio::test]
+    async fn test_download_binary() {
+        let client = get_client();
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(
+                firecrest_client::filesystem_api::get_filesystem_ops_download(&client.client, "", PathBuf::from("")),
+                Result<Vec<u8>>
+            ))
+            .will_return_async(injectorpp::async_return!(Ok(vec![0xff, 0x00, 0xfe]), Result<Vec<u8>>));
+        let result = client.download("test", PathBuf::from("/test.bin")).await;
+        assert_eq!(result.unwrap(), vec![0xff, 0x00, 0xfe]);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_compress() {
+        let client = get_client();
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(
+                firecrest_client::filesystem_api::post_filesystem_transfer_compress(
+                    &client.client,
+                    "",
+                    None,
+                    PathBuf::from(""),
+                    PathBuf::from(""),
+                    None,
+                ),
+                Result<CompressResponse>
+            ))
+            .will_return_async(injectorpp::async_return!(
+                Ok(CompressResponse {
+                    transfer_job: TransferJob {
+                        job_id: "1".to_owned(),
+                        ..Default::default()
+                    },
+                }),
+                Result<CompressResponse>
+            ));
+        let result = client
+            .transfer_compress(
+                "test",
+                None,
+                PathBuf::from("/test"),
+                PathBuf::from("/test.tar.gz"),
+                ArchiveCompression::Gzip,
+            )
+            .await;
+        assert_eq!(result.unwrap().as_ref(), "1");
+    }
 }
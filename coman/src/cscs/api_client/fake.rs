@@ -0,0 +1,449 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use color_eyre::eyre::Result;
+use eyre::eyre;
+use reqwest::Url;
+
+use crate::cscs::api_client::{
+    backend::ComputeBackend,
+    client::JobStartOptions,
+    types::{
+        ArchiveCompression, FileStat, FileSystem, FileSystemType, Job, JobDetail, JobId, JobStatus, PathEntry,
+        PathType, S3Upload, System, UserInfo,
+    },
+};
+
+#[derive(Default)]
+struct State {
+    dirs: BTreeSet<PathBuf>,
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    jobs: Vec<JobDetail>,
+}
+
+/// An in memory cluster with a single file system shared by all systems, jobs never run unless a test changes them
+#[derive(Default)]
+pub(crate) struct FakeBackend {
+    systems: Vec<System>,
+    state: Mutex<State>,
+}
+
+impl FakeBackend {
+    pub fn new(systems: &[&str]) -> Self {
+        let backend = Self {
+            systems: systems
+                .iter()
+                .map(|name| System {
+                    name: name.to_string(),
+                    file_systems: vec![FileSystem {
+                        data_type: FileSystemType::Scratch,
+                        default_work_dir: true,
+                        path: "/scratch".to_owned(),
+                    }],
+                    services_health: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        backend.state().dirs.insert(PathBuf::from("/"));
+        backend
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("fake backend state is poisoned")
+    }
+
+    pub fn add_dir(&self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let mut state = self.state();
+        state.dirs.extend(path.ancestors().map(Path::to_path_buf));
+    }
+
+    pub fn add_file(&self, path: impl Into<PathBuf>, content: &str) {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            self.add_dir(parent);
+        }
+        self.state().files.insert(path, content.as_bytes().to_vec());
+    }
+
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        let state = self.state();
+        state.dirs.contains(path.as_ref()) || state.files.contains_key(path.as_ref())
+    }
+
+    pub fn add_job(&self, id: &str, name: &str, status: JobStatus) -> JobId {
+        let id = JobId::new(id.to_owned());
+        self.state().jobs.push(JobDetail {
+            id: id.clone(),
+            name: name.to_owned(),
+            start_date: None,
+            end_date: None,
+            status,
+            status_reason: String::new(),
+            exit_code: 0,
+            user: "user".to_owned(),
+            stdout: String::new(),
+            stderr: String::new(),
+            stdin: String::new(),
+        });
+        id
+    }
+
+    pub fn job(&self, id: &JobId) -> Option<JobDetail> {
+        self.state().jobs.iter().find(|j| &j.id == id).cloned()
+    }
+
+    fn check_system(&self, system_name: &str) -> Result<()> {
+        if self.systems.iter().any(|s| s.name == system_name) {
+            Ok(())
+        } else {
+            Err(eyre!("unknown system {}", system_name))
+        }
+    }
+
+    fn read(&self, system_name: &str, path: &Path) -> Result<Vec<u8>> {
+        self.check_system(system_name)?;
+        self.state()
+            .files
+            .get(path)
+            .cloned()
+            .ok_or(eyre!("{} is not a file", path.display()))
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        let mut state = self.state();
+        if state.files.remove(path).is_some() {
+            return Ok(());
+        }
+        if !state.dirs.remove(path) {
+            return Err(eyre!("{} doesn't exist", path.display()));
+        }
+        state.dirs.retain(|d| !d.starts_with(path));
+        state.files.retain(|f, _| !f.starts_with(path));
+        Ok(())
+    }
+
+    /// Transfers finish immediately, but still report a job like on a real cluster
+    fn transfer_job(&self, result: Result<()>) -> Result<JobId> {
+        let id = format!("transfer-{}", self.state().jobs.len());
+        let status = if result.is_ok() {
+            JobStatus::Finished
+        } else {
+            JobStatus::Failed
+        };
+        Ok(self.add_job(&id, "transfer", status))
+    }
+
+    fn copy(&self, path: &Path, target: &Path) -> Result<()> {
+        let mut state = self.state();
+        if let Some(content) = state.files.get(path).cloned() {
+            state.files.insert(target.to_path_buf(), content);
+            return Ok(());
+        }
+        if !state.dirs.contains(path) {
+            return Err(eyre!("{} doesn't exist", path.display()));
+        }
+        let dirs: Vec<_> = state
+            .dirs
+            .iter()
+            .filter_map(|d| d.strip_prefix(path).ok().map(|rest| target.join(rest)))
+            .collect();
+        let files: Vec<_> = state
+            .files
+            .iter()
+            .filter_map(|(f, content)| {
+                f.strip_prefix(path)
+                    .ok()
+                    .map(|rest| (target.join(rest), content.clone()))
+            })
+            .collect();
+        state.dirs.extend(dirs);
+        state.files.extend(files);
+        Ok(())
+    }
+}
+
+#[tuirealm::async_trait]
+impl ComputeBackend for FakeBackend {
+    async fn list_systems(&self) -> Result<Vec<System>> {
+        Ok(self.systems.clone())
+    }
+
+    async fn start_job(
+        &self,
+        system_name: &str,
+        _account: Option<String>,
+        name: &str,
+        script_path: PathBuf,
+        _envvars: HashMap<String, String>,
+        _options: JobStartOptions,
+    ) -> Result<Option<JobId>> {
+        self.read(system_name, &script_path)?;
+        let id = (self.state().jobs.len() + 1).to_string();
+        Ok(Some(self.add_job(&id, name, JobStatus::Pending)))
+    }
+
+    async fn list_jobs(
+        &self,
+        status: Option<Vec<JobStatus>>,
+        system_name: &str,
+        _all_users: Option<bool>,
+    ) -> Result<Vec<Job>> {
+        self.check_system(system_name)?;
+        Ok(self
+            .state()
+            .jobs
+            .iter()
+            .filter(|j| status.as_ref().is_none_or(|s| s.contains(&j.status)))
+            .map(|j| Job {
+                id: j.id.clone(),
+                name: j.name.clone(),
+                status: j.status.clone(),
+                user: j.user.clone(),
+                start_date: j.start_date,
+                end_date: j.end_date,
+            })
+            .collect())
+    }
+
+    async fn get_job(&self, system_name: &str, job_id: JobId) -> Result<Option<JobDetail>> {
+        self.check_system(system_name)?;
+        Ok(self.job(&job_id))
+    }
+
+    async fn cancel_job(&self, system_name: &str, job_id: JobId) -> Result<()> {
+        self.check_system(system_name)?;
+        let mut state = self.state();
+        let job = state
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == job_id)
+            .ok_or(eyre!("unknown job {}", job_id))?;
+        job.status = JobStatus::Cancelled;
+        Ok(())
+    }
+
+    async fn mkdir(&self, system_name: &str, path: PathBuf) -> Result<()> {
+        self.check_system(system_name)?;
+        self.add_dir(path);
+        Ok(())
+    }
+
+    async fn chmod(&self, system_name: &str, _path: PathBuf, _mode: &str) -> Result<()> {
+        self.check_system(system_name)
+    }
+
+    async fn chown(
+        &self,
+        system_name: &str,
+        _path: PathBuf,
+        _owner: Option<String>,
+        _group: Option<String>,
+    ) -> Result<()> {
+        self.check_system(system_name)
+    }
+
+    async fn rm_path(&self, system_name: &str, path: PathBuf) -> Result<()> {
+        self.check_system(system_name)?;
+        self.remove(&path)
+    }
+
+    async fn symlink(&self, _system_name: &str, _path: PathBuf, _link_path: PathBuf) -> Result<()> {
+        Err(eyre!("the fake backend doesn't support symlinks"))
+    }
+
+    async fn upload(&self, system_name: &str, target: PathBuf, file: Vec<u8>) -> Result<()> {
+        self.check_system(system_name)?;
+        let mut state = self.state();
+        if !target.parent().is_some_and(|p| state.dirs.contains(p)) {
+            return Err(eyre!("parent of {} doesn't exist", target.display()));
+        }
+        state.files.insert(target, file);
+        Ok(())
+    }
+
+    async fn download(&self, system_name: &str, path: PathBuf) -> Result<Vec<u8>> {
+        self.read(system_name, &path)
+    }
+
+    async fn tail(&self, system_name: &str, path: PathBuf, lines: usize) -> Result<String> {
+        let content = String::from_utf8(self.read(system_name, &path)?)?;
+        let all: Vec<_> = content.lines().collect();
+        Ok(all[all.len().saturating_sub(lines)..].join("\n"))
+    }
+
+    async fn head(
+        &self,
+        system_name: &str,
+        path: PathBuf,
+        lines: Option<usize>,
+        bytes: Option<usize>,
+    ) -> Result<String> {
+        let content = String::from_utf8(self.read(system_name, &path)?)?;
+        Ok(match (lines, bytes) {
+            (_, Some(bytes)) => content.chars().take(bytes).collect(),
+            (Some(lines), None) => content.lines().take(lines).collect::<Vec<_>>().join("\n"),
+            (None, None) => content,
+        })
+    }
+
+    async fn view(&self, system_name: &str, path: PathBuf, size: usize, offset: usize) -> Result<String> {
+        let content = self.read(system_name, &path)?;
+        let start = offset.min(content.len());
+        let end = (offset + size).min(content.len());
+        Ok(String::from_utf8_lossy(&content[start..end]).into_owned())
+    }
+
+    async fn list_path(&self, system_name: &str, path: PathBuf, show_hidden: bool) -> Result<Vec<PathEntry>> {
+        self.check_system(system_name)?;
+        let state = self.state();
+        if !state.dirs.contains(&path) {
+            return Err(eyre!("{} is not a directory", path.display()));
+        }
+        let dirs = state
+            .dirs
+            .iter()
+            .filter(|d| d.parent() == Some(path.as_path()))
+            .map(|d| (d, PathType::Directory, None));
+        let files = state
+            .files
+            .iter()
+            .filter(|(f, _)| f.parent() == Some(path.as_path()))
+            .map(|(f, content)| (f, PathType::File, Some(content.len())));
+        Ok(dirs
+            .chain(files)
+            .filter_map(|(p, path_type, size)| {
+                let name = p.file_name()?.to_string_lossy().into_owned();
+                (show_hidden || !name.starts_with('.')).then_some(PathEntry {
+                    name,
+                    path_type,
+                    permissions: None,
+                    size,
+                })
+            })
+            .collect())
+    }
+
+    async fn checksum(&self, system_name: &str, path: PathBuf) -> Result<Option<String>> {
+        self.read(system_name, &path)?;
+        Ok(None)
+    }
+
+    async fn stat_path(&self, system_name: &str, path: PathBuf) -> Result<Option<FileStat>> {
+        self.check_system(system_name)?;
+        let state = self.state();
+        Ok(match state.files.get(&path) {
+            Some(content) => Some(FileStat {
+                size: content.len() as i64,
+            }),
+            None => state.dirs.contains(&path).then_some(FileStat { size: 0 }),
+        })
+    }
+
+    async fn file_type(&self, system_name: &str, path: PathBuf) -> Result<Option<String>> {
+        self.check_system(system_name)?;
+        Ok(self.exists(&path).then(|| "data".to_owned()))
+    }
+
+    async fn compress(
+        &self,
+        _system_name: &str,
+        _path: PathBuf,
+        _target: PathBuf,
+        _compression: ArchiveCompression,
+    ) -> Result<()> {
+        Err(eyre!("the fake backend doesn't support archives"))
+    }
+
+    async fn extract(
+        &self,
+        _system_name: &str,
+        _path: PathBuf,
+        _target: PathBuf,
+        _compression: ArchiveCompression,
+    ) -> Result<()> {
+        Err(eyre!("the fake backend doesn't support archives"))
+    }
+
+    async fn transfer_upload(
+        &self,
+        _system_name: &str,
+        _account: Option<String>,
+        _target: PathBuf,
+        _size: i64,
+    ) -> Result<(JobId, S3Upload)> {
+        Err(eyre!("the fake backend doesn't support s3 transfers"))
+    }
+
+    async fn transfer_download(
+        &self,
+        _system_name: &str,
+        _account: Option<String>,
+        _path: PathBuf,
+    ) -> Result<(JobId, Url)> {
+        Err(eyre!("the fake backend doesn't support s3 transfers"))
+    }
+
+    async fn transfer_copy(
+        &self,
+        system_name: &str,
+        _account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+    ) -> Result<JobId> {
+        self.check_system(system_name)?;
+        self.transfer_job(self.copy(&path, &target))
+    }
+
+    async fn transfer_move(
+        &self,
+        system_name: &str,
+        _account: Option<String>,
+        path: PathBuf,
+        target: PathBuf,
+    ) -> Result<JobId> {
+        self.check_system(system_name)?;
+        let result = self.copy(&path, &target).and_then(|_| self.remove(&path));
+        self.transfer_job(result)
+    }
+
+    async fn transfer_rm(&self, system_name: &str, _account: Option<String>, path: PathBuf) -> Result<JobId> {
+        self.check_system(system_name)?;
+        self.transfer_job(self.remove(&path))
+    }
+
+    async fn transfer_compress(
+        &self,
+        _system_name: &str,
+        _account: Option<String>,
+        _path: PathBuf,
+        _target: PathBuf,
+        _compression: ArchiveCompression,
+    ) -> Result<JobId> {
+        Err(eyre!("the fake backend doesn't support archives"))
+    }
+
+    async fn transfer_extract(
+        &self,
+        _system_name: &str,
+        _account: Option<String>,
+        _path: PathBuf,
+        _target: PathBuf,
+        _compression: ArchiveCompression,
+    ) -> Result<JobId> {
+        Err(eyre!("the fake backend doesn't support archives"))
+    }
+
+    async fn get_userinfo(&self, system_name: &str) -> Result<UserInfo> {
+        self.check_system(system_name)?;
+        Ok(UserInfo {
+            id: "1000".to_owned(),
+            name: "user".to_owned(),
+            group: "users".to_owned(),
+        })
+    }
+}
@@ -1,2 +1,5 @@
+pub mod backend;
 pub mod client;
+#[cfg(test)]
+pub mod fake;
 pub mod types;
@@ -2,14 +2,16 @@ use chrono::prelude::*;
 use color_eyre::eyre::{Context, Result};
 use eyre::eyre;
 use firecrest_client::types::{
-    BackendServiceType, File as CSCSFile, FileStat as CSCSFileStat, FileSystem as CSCSFileSystem, FileSystemDataType,
-    HPCCluster, JobMetadataModel, JobModel, S3TransferResponse, SchedulerServiceHealth, UserInfoResponse,
+    BackendServiceType, CompressionType, File as CSCSFile, FileStat as CSCSFileStat, FileSystem as CSCSFileSystem,
+    FileSystemDataType, HPCCluster, JobMetadataModel, JobModel, S3TransferResponse, SchedulerServiceHealth,
+    UserInfoResponse,
 };
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use strum::Display;
 use strum_macros::{VariantArray, VariantNames};
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Hash, tabled::Tabled, Serialize)]
 pub struct JobId(String);
 
 impl JobId {
@@ -56,7 +58,44 @@ impl From<i64> for JobId {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+/// A slurm dependency of a job on another job
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobDependency {
+    /// start once the other job has started
+    After(JobId),
+    /// start once the other job has finished successfully
+    AfterOk(JobId),
+}
+
+impl JobDependency {
+    /// Formats dependencies in the syntax of `sbatch --dependency`, all of them have to be satisfied
+    pub fn to_slurm(dependencies: &[JobDependency]) -> Option<String> {
+        let after: Vec<_> = dependencies
+            .iter()
+            .filter_map(|d| match d {
+                JobDependency::After(id) => Some(id.to_string()),
+                JobDependency::AfterOk(_) => None,
+            })
+            .collect();
+        let after_ok: Vec<_> = dependencies
+            .iter()
+            .filter_map(|d| match d {
+                JobDependency::AfterOk(id) => Some(id.to_string()),
+                JobDependency::After(_) => None,
+            })
+            .collect();
+        let mut parts = vec![];
+        if !after.is_empty() {
+            parts.push(format!("after:{}", after.join(":")));
+        }
+        if !after_ok.is_empty() {
+            parts.push(format!("afterok:{}", after_ok.join(":")));
+        }
+        (!parts.is_empty()).then(|| parts.join(","))
+    }
+}
+
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct UserInfo {
     pub id: String,
     pub name: String,
@@ -72,7 +111,7 @@ impl From<UserInfoResponse> for UserInfo {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Display)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Display, Serialize)]
 pub enum FileSystemType {
     Users,
     Store,
@@ -106,7 +145,7 @@ impl From<String> for FileSystemType {
         }
     }
 }
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct FileSystem {
     pub data_type: FileSystemType,
     pub default_work_dir: bool,
@@ -122,14 +161,14 @@ impl From<CSCSFileSystem> for FileSystem {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Display)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Display, Serialize)]
 pub enum PathType {
     Directory,
     File,
     Link,
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct PathEntry {
     #[tabled(order = 3)]
     pub name: String,
@@ -181,7 +220,26 @@ impl From<CSCSFileStat> for FileStat {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Display, VariantArray, VariantNames)]
+/// Output stream of a job
+#[derive(Debug, Eq, Clone, Copy, PartialEq, PartialOrd, Ord, Hash, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+impl LogStream {
+    pub fn path(self, job: &JobDetail) -> &str {
+        match self {
+            LogStream::Stdout => &job.stdout,
+            LogStream::Stderr => &job.stderr,
+        }
+    }
+}
+
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Display, VariantArray, VariantNames, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum JobStatus {
     Pending,
     Running,
@@ -191,6 +249,15 @@ pub enum JobStatus {
     Timeout,
     Requeued,
 }
+impl JobStatus {
+    /// Whether the job is done and its status won't change anymore
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Finished | JobStatus::Cancelled | JobStatus::Failed | JobStatus::Timeout
+        )
+    }
+}
 impl From<String> for JobStatus {
     fn from(value: String) -> Self {
         match value.split_whitespace().next().unwrap_or("").to_uppercase().as_str() {
@@ -205,7 +272,7 @@ impl From<String> for JobStatus {
         }
     }
 }
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct Job {
     pub id: JobId,
     pub name: String,
@@ -235,7 +302,7 @@ impl From<JobModel> for Job {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct JobDetail {
     pub id: JobId,
     pub name: String,
@@ -283,7 +350,7 @@ impl From<(JobModel, JobMetadataModel)> for JobDetail {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Display, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, Display, tabled::Tabled, Serialize)]
 pub enum ServiceType {
     Scheduler,
     Filesystem,
@@ -316,7 +383,7 @@ impl From<String> for ServiceType {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct ServicesHealth {
     #[tabled(order = 1)]
     healthy: bool,
@@ -337,7 +404,7 @@ impl From<SchedulerServiceHealth> for ServicesHealth {
     }
 }
 
-#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled)]
+#[derive(Debug, Eq, Clone, PartialEq, PartialOrd, Ord, tabled::Tabled, Serialize)]
 pub struct System {
     pub name: String,
     #[tabled(skip)]
@@ -393,3 +460,41 @@ impl S3Upload {
         })
     }
 }
+
+/// Compression of a tar archive, derived from the archive file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveCompression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+impl ArchiveCompression {
+    pub fn from_path(path: &std::path::Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            ArchiveCompression::Gzip
+        } else if name.ends_with(".tar.bz2") || name.ends_with(".tbz2") {
+            ArchiveCompression::Bzip2
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            ArchiveCompression::Xz
+        } else {
+            ArchiveCompression::None
+        }
+    }
+}
+
+impl From<ArchiveCompression> for CompressionType {
+    fn from(value: ArchiveCompression) -> Self {
+        match value {
+            ArchiveCompression::None => CompressionType::None,
+            ArchiveCompression::Gzip => CompressionType::Gzip,
+            ArchiveCompression::Bzip2 => CompressionType::Bzip2,
+            ArchiveCompression::Xz => CompressionType::Xz,
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::{
+    Result, Section,
+    eyre::{Context, eyre},
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::{ComputePlatform, Config, SiteConfig, active_site},
+    cscs::{
+        api_client::{backend::ComputeBackend, client::CscsApi},
+        identity::{active_identity, secret_name, site_secret_name},
+        oauth2::{
+            ACCESS_TOKEN_EXPIRY_SECRET_NAME, ACCESS_TOKEN_SECRET_NAME, CLIENT_ID_SECRET_NAME,
+            CLIENT_SECRET_SECRET_NAME, LoginTokens, REFRESH_TOKEN_SECRET_NAME, client_credentials_login,
+            refresh_token_login,
+        },
+    },
+    util::secrets::{Secret, get_secret, store_secret},
+};
+
+/// Tokens are refreshed this long before they expire, so a request never goes out with a token that expires in flight
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Lifetime assumed for tokens the auth server doesn't report an expiry for
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// The access tokens in use by this process by identity, shared by all handlers.
+///
+/// This is an async mutex so concurrent callers wait for a single refresh instead of each logging in themselves.
+static ACCESS_TOKENS: LazyLock<Mutex<HashMap<String, CachedToken>>> = LazyLock::new(Default::default);
+/// Api clients per platform, together with the access token they were created with
+static API_CLIENTS: LazyLock<std::sync::Mutex<HashMap<ComputePlatform, ApiClient>>> = LazyLock::new(Default::default);
+
+type ApiClient = (String, Arc<dyn ComputeBackend>);
+
+#[derive(Clone, Debug)]
+struct CachedToken {
+    token: Secret,
+    expires_at: SystemTime,
+}
+
+impl CachedToken {
+    fn new(token: Secret, expires_in: Option<Duration>, now: SystemTime) -> Self {
+        Self {
+            token,
+            expires_at: now + expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME),
+        }
+    }
+
+    fn is_fresh(&self, now: SystemTime) -> bool {
+        now + REFRESH_MARGIN < self.expires_at
+    }
+
+    /// Reads the token persisted by a previous run, if there is one
+    async fn load(identity: &str) -> Result<Option<Self>> {
+        let (Some(token), Some(expiry)) = (
+            get_secret(&secret_name(ACCESS_TOKEN_SECRET_NAME, identity)).await?,
+            get_secret(&secret_name(ACCESS_TOKEN_EXPIRY_SECRET_NAME, identity)).await?,
+        ) else {
+            return Ok(None);
+        };
+        let Ok(expiry) = expiry.0.parse::<u64>() else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            token,
+            expires_at: UNIX_EPOCH + Duration::from_secs(expiry),
+        }))
+    }
+}
+
+/// The secrets persisted after a login, the refresh token is only replaced if the auth server rotated it
+fn token_secrets(
+    site: &str,
+    identity: &str,
+    token: &CachedToken,
+    refresh_token: Option<Secret>,
+) -> Result<Vec<(String, Secret)>> {
+    let expiry = token.expires_at.duration_since(UNIX_EPOCH)?.as_secs();
+    let mut secrets = vec![
+        (
+            site_secret_name(ACCESS_TOKEN_SECRET_NAME, site, identity),
+            token.token.clone(),
+        ),
+        (
+            site_secret_name(ACCESS_TOKEN_EXPIRY_SECRET_NAME, site, identity),
+            Secret::new(expiry.to_string()),
+        ),
+    ];
+    // the auth server rotates refresh tokens, so the old one is useless once we got a new one
+    if let Some(refresh_token) = refresh_token {
+        secrets.push((
+            site_secret_name(REFRESH_TOKEN_SECRET_NAME, site, identity),
+            refresh_token,
+        ));
+    }
+    Ok(secrets)
+}
+
+/// Returns a valid access token, only logging in again if the cached one is about to expire
+pub(crate) async fn get_access_token(config: &Config) -> Result<Secret> {
+    let identity = active_identity();
+    let mut cached = ACCESS_TOKENS.lock().await;
+    let now = SystemTime::now();
+    if let Some(token) = cached.get(&identity)
+        && token.is_fresh(now)
+    {
+        return Ok(token.token.clone());
+    }
+    // the keyring is only a cache, so we can still log in if it isn't available
+    if !cached.contains_key(&identity)
+        && let Ok(Some(token)) = CachedToken::load(&identity).await
+        && token.is_fresh(now)
+    {
+        cached.insert(identity, token.clone());
+        return Ok(token.token);
+    }
+    let tokens = login(config, &identity).await?;
+    let token = cache_token(&mut cached, &identity, tokens).await;
+    Ok(token)
+}
+
+/// Replaces the cached access token (and refresh token, if any), e.g. after an explicit login
+pub(crate) async fn set_access_token(tokens: LoginTokens) -> Secret {
+    let mut cached = ACCESS_TOKENS.lock().await;
+    cache_token(&mut cached, &active_identity(), tokens).await
+}
+
+/// Returns an api client for the platform, shared with all other callers as long as the access token stays the same
+pub(crate) async fn get_api_client(
+    config: &Config,
+    platform: Option<ComputePlatform>,
+) -> Result<Arc<dyn ComputeBackend>> {
+    let access_token = get_access_token(config).await?;
+    let platform = platform.unwrap_or(config.values.cscs.current_platform.clone());
+    let mut clients = API_CLIENTS.lock().map_err(|_| eyre!("api client cache is poisoned"))?;
+    if let Some((token, client)) = clients.get(&platform)
+        && *token == access_token.0
+    {
+        return Ok(client.clone());
+    }
+    let client: Arc<dyn ComputeBackend> = Arc::new(CscsApi::new(
+        access_token.0.clone(),
+        &config.values.current_site()?,
+        &platform,
+    )?);
+    clients.insert(platform, (access_token.0, client.clone()));
+    Ok(client)
+}
+
+async fn cache_token(cached: &mut HashMap<String, CachedToken>, identity: &str, tokens: LoginTokens) -> Secret {
+    let token = CachedToken::new(tokens.access_token, tokens.expires_in, SystemTime::now());
+    // persisting is best effort, the token is still cached for the lifetime of this process
+    if let Ok(secrets) = token_secrets(&active_site(), identity, &token, tokens.refresh_token) {
+        for (name, secret) in secrets {
+            let _ = store_secret(&name, secret).await;
+        }
+    }
+    let access_token = token.token.clone();
+    cached.insert(identity.to_owned(), token);
+    access_token
+}
+
+async fn login(config: &Config, identity: &str) -> Result<LoginTokens> {
+    // client credentials set in the config take precedence over a device login
+    let refresh_token = if config.values.cscs.client_id.is_none() && config.values.cscs.client_secret.is_none() {
+        get_secret(&secret_name(REFRESH_TOKEN_SECRET_NAME, identity)).await?
+    } else {
+        None
+    };
+    let client_credentials = client_credentials(config, identity).await?;
+    login_with(&config.values.current_site()?, refresh_token, client_credentials).await
+}
+
+/// The client id and secret from the config, or the ones stored when logging in as a service account
+async fn client_credentials(config: &Config, identity: &str) -> Result<Option<(Secret, Secret)>> {
+    let client_id = match config.values.cscs.client_id.clone() {
+        Some(client_id) => Some(Secret(client_id)),
+        None => get_secret(&secret_name(CLIENT_ID_SECRET_NAME, identity)).await?,
+    };
+    let client_secret = match config.values.cscs.client_secret.clone() {
+        Some(client_secret) => Some(Secret(client_secret)),
+        None => get_secret(&secret_name(CLIENT_SECRET_SECRET_NAME, identity)).await?,
+    };
+    Ok(client_id.zip(client_secret))
+}
+
+/// Refreshes the access token if we have a refresh token, falling back to the client credentials if that fails
+async fn login_with(
+    site: &SiteConfig,
+    refresh_token: Option<Secret>,
+    client_credentials: Option<(Secret, Secret)>,
+) -> Result<LoginTokens> {
+    if let Some(refresh_token) = refresh_token {
+        match (refresh_token_login(site, refresh_token).await, client_credentials) {
+            (Ok(tokens), _) => return Ok(tokens),
+            (Err(e), None) => {
+                return Err(e)
+                    .wrap_err("couldn't refresh access token")
+                    .suggestion("your session might have expired, log in again with `coman cscs login --device`");
+            }
+            (Err(_), Some((client_id, client_secret))) => {
+                return client_credentials_login(site, client_id, client_secret)
+                    .await
+                    .wrap_err("couldn't refresh access token or log in with the stored client credentials");
+            }
+        }
+    }
+    let (client_id, client_secret) = client_credentials.ok_or(eyre!("not logged in"))?;
+    client_credentials_login(site, client_id, client_secret).await
+}
+
+#[cfg(test)]
+mod tests {
+    use injectorpp::interface::injector::*;
+
+    use super::*;
+    use crate::config::DEFAULT_SITE;
+
+    #[test]
+    fn test_token_freshness() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let token = CachedToken::new(Secret::new("token".to_owned()), Some(Duration::from_secs(300)), now);
+        assert!(token.is_fresh(now));
+        assert!(token.is_fresh(now + Duration::from_secs(200)));
+        // refreshed ahead of the actual expiry
+        assert!(!token.is_fresh(now + Duration::from_secs(250)));
+        assert!(!token.is_fresh(now + Duration::from_secs(400)));
+
+        let token = CachedToken::new(Secret::new("token".to_owned()), None, now);
+        assert_eq!(token.expires_at, now + DEFAULT_TOKEN_LIFETIME);
+    }
+
+    #[test]
+    fn test_refresh_token_rotation() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let token = CachedToken::new(Secret::new("access".to_owned()), Some(Duration::from_secs(300)), now);
+
+        let secrets = token_secrets(DEFAULT_SITE, "work", &token, Some(Secret::new("rotated".to_owned()))).unwrap();
+        let secrets: HashMap<String, String> = secrets.into_iter().map(|(name, secret)| (name, secret.0)).collect();
+        assert_eq!(
+            secrets[&site_secret_name(ACCESS_TOKEN_SECRET_NAME, DEFAULT_SITE, "work")],
+            "access"
+        );
+        assert_eq!(
+            secrets[&site_secret_name(ACCESS_TOKEN_EXPIRY_SECRET_NAME, DEFAULT_SITE, "work")],
+            "1000300"
+        );
+        assert_eq!(
+            secrets[&site_secret_name(REFRESH_TOKEN_SECRET_NAME, DEFAULT_SITE, "work")],
+            "rotated"
+        );
+
+        // the stored refresh token is kept if the server didn't rotate it
+        let secrets = token_secrets(DEFAULT_SITE, "work", &token, None).unwrap();
+        assert!(
+            !secrets
+                .iter()
+                .any(|(name, _)| *name == site_secret_name(REFRESH_TOKEN_SECRET_NAME, DEFAULT_SITE, "work"))
+        );
+    }
+
+    fn tokens(access_token: &str, refresh_token: Option<&str>) -> LoginTokens {
+        LoginTokens {
+            access_token: Secret::new(access_token.to_owned()),
+            refresh_token: refresh_token.map(|t| Secret::new(t.to_owned())),
+            expires_in: Some(Duration::from_secs(300)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_login() {
+        let site = SiteConfig::default();
+        {
+            let mut injector = InjectorPP::new();
+            injector
+                .when_called_async(injectorpp::async_func!(
+                    refresh_token_login(&site, Secret::new(String::new())),
+                    Result<LoginTokens>
+                ))
+                .will_return_async(injectorpp::async_return!(
+                    Ok(tokens("refreshed", Some("rotated"))),
+                    Result<LoginTokens>
+                ));
+            let result = login_with(&site, Some(Secret::new("refresh".to_owned())), None)
+                .await
+                .unwrap();
+            assert_eq!(result.access_token.0, "refreshed");
+            assert_eq!(result.refresh_token.unwrap().0, "rotated");
+        }
+        {
+            let mut injector = InjectorPP::new();
+            injector
+                .when_called_async(injectorpp::async_func!(
+                    refresh_token_login(&site, Secret::new(String::new())),
+                    Result<LoginTokens>
+                ))
+                .will_return_async(injectorpp::async_return!(
+                    Err(eyre!("refresh token expired")),
+                    Result<LoginTokens>
+                ));
+            injector
+                .when_called_async(injectorpp::async_func!(
+                    client_credentials_login(&site, Secret::new(String::new()), Secret::new(String::new())),
+                    Result<LoginTokens>
+                ))
+                .will_return_async(injectorpp::async_return!(
+                    Ok(tokens("client", None)),
+                    Result<LoginTokens>
+                ));
+            // falls back to the client credentials if the refresh fails
+            let credentials = (Secret::new("id".to_owned()), Secret::new("secret".to_owned()));
+            let result = login_with(&site, Some(Secret::new("refresh".to_owned())), Some(credentials))
+                .await
+                .unwrap();
+            assert_eq!(result.access_token.0, "client");
+
+            let result = login_with(&site, Some(Secret::new("refresh".to_owned())), None).await;
+            assert!(result.is_err());
+        }
+    }
+}
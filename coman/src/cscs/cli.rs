@@ -1,55 +1,95 @@
 use std::{
-    io::{SeekFrom, Write},
+    io::Write,
     path::PathBuf,
     time::{Duration, Instant},
 };
 
 use bytesize::ByteSize;
-use color_eyre::{Result, eyre::Context};
+use chrono::{DateTime, Local};
+use color_eyre::{Result, Section, eyre::Context};
 use eyre::eyre;
-use futures::StreamExt;
 use inquire::{Password, Select, Text};
 use itertools::Itertools;
-use reqwest::Url;
 use strum::VariantArray;
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
-};
 
 use crate::{
-    cli::app::JobIdOrName,
-    config::{ComputePlatform, Config},
+    cli::{
+        app::JobIdOrName,
+        log::{LogPrinter, interleave},
+        output::OutputFormat,
+    },
+    config::{ComputePlatform, Config, Profile, active_site, get_config_dir},
     cscs::{
         api_client::{
-            client::JobStartOptions,
-            types::{JobId, JobStatus},
+            client::{EdfSpec, JobStartOptions, ScriptSpec},
+            types::{JobDependency, JobDetail, JobId, JobStatus, LogStream, UserInfo},
         },
+        edf::check_edf_file,
         handlers::{
-            cscs_file_delete, cscs_file_download, cscs_file_list, cscs_file_upload, cscs_job_cancel, cscs_job_details,
-            cscs_job_list, cscs_job_log, cscs_job_start, cscs_login, cscs_port_forward, cscs_resource_usage,
-            cscs_system_list, cscs_system_set, get_available_compute_platforms,
+            cscs_file_chown, cscs_file_compress, cscs_file_copy, cscs_file_delete, cscs_file_download,
+            cscs_file_download_archive, cscs_file_download_dir, cscs_file_extract, cscs_file_head, cscs_file_link,
+            cscs_file_list, cscs_file_move, cscs_file_sync, cscs_file_type, cscs_file_upload, cscs_file_upload_dir,
+            cscs_file_upload_resume, cscs_file_verify, cscs_file_view, cscs_job_cancel, cscs_job_details,
+            cscs_job_files, cscs_job_list, cscs_job_log, cscs_job_log_follow, cscs_job_render, cscs_job_start,
+            cscs_job_wait, cscs_login, cscs_login_device_code, cscs_port_forward, cscs_resource_usage,
+            cscs_system_list, cscs_system_set, cscs_transfer_wait, cscs_whoami, get_available_compute_platforms,
+        },
+        history::{JobOverrides, JobSubmission, find_submission, load_history, lookup_submission},
+        identity::{
+            DEFAULT_IDENTITY, LoginMethod, active_identity, check_identity, known_secret_names, list_identities,
+            login_method, remove_identity, secret_name, set_active_identity,
         },
+        notifications::{JobWatcher, send_notification},
+        oauth2::{CLIENT_ID_SECRET_NAME, CLIENT_SECRET_SECRET_NAME},
+        pipeline::{pipeline_run, pipeline_status},
+        s3::ranged_download,
+        sweep::sweep_combinations,
+    },
+    util::{
+        secret_file::PASSPHRASE_ENV_VAR,
+        secrets::{SecretBackend, configured_backend, env_var_name, migrate_secrets},
     },
 };
 
-pub(crate) async fn cli_cscs_login() -> Result<()> {
-    let client_id = Text::new("Client Id:").prompt()?;
-    let client_secret = Password::new("Client Secret:").prompt()?;
+pub(crate) async fn cli_cscs_login(device: bool, profile: Option<String>) -> Result<()> {
+    if let Some(profile) = profile.clone() {
+        set_active_identity(profile)?;
+    }
+    let mut config = Config::new()?;
+    let result = if device {
+        cscs_login_device_code(&config).await
+    } else {
+        let client_id = Text::new("Client Id:").prompt()?;
+        let client_secret = Password::new("Client Secret:").prompt()?;
+        cscs_login(&config, client_id, client_secret).await
+    };
 
-    match cscs_login(client_id, client_secret).await {
+    match result {
         Ok(_) => {
             println!("Successfully logged in");
         }
         Err(e) => Err(e).wrap_err("couldn't get acccess token")?,
     };
+    if let Some(profile) = profile
+        && config
+            .values
+            .cscs
+            .identity
+            .clone()
+            .unwrap_or(DEFAULT_IDENTITY.to_owned())
+            != profile
+    {
+        println!(
+            "Run `coman cscs identity use {}` to use this identity for the current project",
+            profile
+        );
+    }
 
     // select compute platform
-    let mut config = Config::new()?;
-
     let source = config.value_source("cscs.current_platform");
-    if !source.1 && !source.2 {
-        let available_platforms: Vec<_> = get_available_compute_platforms()
+    // sites without separate apis per platform ignore it anyway
+    if !source.1 && !source.2 && config.values.current_site()?.has_platforms() {
+        let available_platforms: Vec<_> = get_available_compute_platforms(&config)
             .await
             .unwrap_or(<ComputePlatform as VariantArray>::VARIANTS.to_vec())
             .iter()
@@ -73,11 +113,17 @@ pub(crate) async fn cli_cscs_login() -> Result<()> {
 }
 pub(crate) async fn cli_cscs_job_list(
     status: Option<Vec<JobStatus>>,
+    output: OutputFormat,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    match cscs_job_list(status, system, platform).await {
+    let config = Config::new()?;
+    match cscs_job_list(&config, status, system, platform).await {
         Ok(jobs) => {
+            if let Some(rendered) = output.render(&jobs)? {
+                println!("{}", rendered);
+                return Ok(());
+            }
             let mut table = tabled::Table::new(jobs);
             table.with(tabled::settings::Style::modern());
             println!("{}", table);
@@ -88,13 +134,14 @@ pub(crate) async fn cli_cscs_job_list(
 }
 
 async fn maybe_job_id_from_name(
+    config: &Config,
     j: JobIdOrName,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<JobId> {
     match j {
         JobIdOrName::Id(id) => Ok(id.into()),
-        JobIdOrName::Name(name) => match cscs_job_list(None, system, platform).await {
+        JobIdOrName::Name(name) => match cscs_job_list(config, None, system, platform).await {
             Ok(jobs) => {
                 let job = jobs
                     .iter()
@@ -109,12 +156,18 @@ async fn maybe_job_id_from_name(
 }
 pub(crate) async fn cli_cscs_job_detail(
     job_id: JobIdOrName,
+    output: OutputFormat,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    let job_id = maybe_job_id_from_name(job_id, system.clone(), platform.clone()).await?;
-    match cscs_job_details(job_id, system, platform).await {
+    let config = Config::new()?;
+    let job_id = maybe_job_id_from_name(&config, job_id, system.clone(), platform.clone()).await?;
+    match cscs_job_details(&config, job_id, system, platform).await {
         Ok(Some(job)) => {
+            if let Some(rendered) = output.render_one(&job)? {
+                println!("{}", rendered);
+                return Ok(());
+            }
             let data = &[
                 ("Id", job.id.to_string()),
                 ("Name", job.name),
@@ -143,43 +196,156 @@ pub(crate) async fn cli_cscs_job_detail(
     }
 }
 
+/// Exit code for a finished job, so `job wait` can be used in scripts
+fn job_exit_code(job: &JobDetail) -> i32 {
+    let exit_code = i32::try_from(job.exit_code).ok().filter(|c| (1..=255).contains(c));
+    match job.status {
+        JobStatus::Timeout => 124,
+        JobStatus::Cancelled => 125,
+        JobStatus::Failed => exit_code.unwrap_or(1),
+        _ if job.exit_code == 0 => 0,
+        _ => exit_code.unwrap_or(1),
+    }
+}
+
+/// Waits for a job to finish and returns the exit code coman should exit with
+pub(crate) async fn cli_cscs_job_wait(
+    job: JobIdOrName,
+    live: bool,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<i32> {
+    let config = Config::new()?;
+    let job_id = maybe_job_id_from_name(&config, job, system.clone(), platform.clone()).await?;
+    let job = cscs_job_wait(&config, job_id, system, platform, |job| {
+        if live {
+            let elapsed = job
+                .start_date
+                .map(|start| {
+                    let elapsed = job.end_date.unwrap_or(Local::now()) - start;
+                    format!(
+                        " for {:02}:{:02}:{:02}",
+                        elapsed.num_hours(),
+                        elapsed.num_minutes() % 60,
+                        elapsed.num_seconds() % 60
+                    )
+                })
+                .unwrap_or_default();
+            // pad so a shorter status fully overwrites the previous one
+            print!(
+                "\r{:<70}",
+                format!("{} ({}): {}{}", job.name, job.id, job.status, elapsed)
+            );
+            std::io::stdout().flush()?;
+        }
+        Ok(())
+    })
+    .await?;
+    if live {
+        println!();
+    }
+    println!("Job {} {} with exit code {}", job.id, job.status, job.exit_code);
+    Ok(job_exit_code(&job))
+}
+
+/// Which logs to show and how, for `cli_cscs_job_log`
+pub(crate) struct JobLogOptions {
+    pub stderr: bool,
+    pub all: bool,
+    pub follow: bool,
+    pub lines: usize,
+    pub since: Option<DateTime<Local>>,
+}
+
 pub(crate) async fn cli_cscs_job_log(
     job_id: JobIdOrName,
-    stderr: bool,
+    options: JobLogOptions,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    let job_id = maybe_job_id_from_name(job_id, system.clone(), platform.clone()).await?;
-    match cscs_job_log(job_id, stderr, system, platform).await {
-        Ok(content) => {
-            println!("{}", content);
-            Ok(())
+    let config = Config::new()?;
+    let job_id = maybe_job_id_from_name(&config, job_id, system.clone(), platform.clone()).await?;
+    let streams = if options.all {
+        vec![LogStream::Stdout, LogStream::Stderr]
+    } else if options.stderr {
+        vec![LogStream::Stderr]
+    } else {
+        vec![LogStream::Stdout]
+    };
+    let mut printer = LogPrinter::new(options.all, options.since);
+    if options.follow {
+        let job = cscs_job_log_follow(
+            &config,
+            job_id,
+            &streams,
+            options.lines,
+            system,
+            platform,
+            |stream, chunk| {
+                for line in printer.push(stream, chunk) {
+                    println!("{line}");
+                }
+                Ok(())
+            },
+        )
+        .await?;
+        for line in printer.flush() {
+            println!("{line}");
         }
-        Err(e) => Err(e),
+        eprintln!("Job {} {}", job.id, job.status);
+        return Ok(());
+    }
+    let mut logs = vec![];
+    for stream in streams {
+        let content = cscs_job_log(
+            &config,
+            job_id.clone(),
+            stream,
+            options.lines,
+            system.clone(),
+            platform.clone(),
+        )
+        .await?;
+        logs.push((stream, content));
     }
+    for (stream, line) in interleave(logs) {
+        for line in printer.push(stream, &format!("{line}\n")) {
+            println!("{line}");
+        }
+    }
+    Ok(())
 }
 
 pub(crate) async fn cli_cscs_port_forward(
     source_port: u16,
     destination_port: u16,
     job: JobIdOrName,
+    node: usize,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    let job_id = maybe_job_id_from_name(job, system.clone(), platform.clone()).await?;
+    let config = Config::new()?;
+    let job_id = maybe_job_id_from_name(&config, job, system.clone(), platform.clone()).await?;
     println!("running port forward for job {job_id}");
-    cscs_port_forward(job_id, source_port, destination_port, system).await
+    cscs_port_forward(&config, job_id, node, source_port, destination_port, system).await
 }
 
 pub(crate) async fn cli_cscs_job_resource_usage(
     job: JobIdOrName,
+    node: Option<usize>,
+    output: OutputFormat,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    let job_id = maybe_job_id_from_name(job, system.clone(), platform.clone()).await?;
-    let result = cscs_resource_usage(job_id, system)
+    let config = Config::new()?;
+    let job_id = maybe_job_id_from_name(&config, job, system.clone(), platform.clone()).await?;
+    let result = cscs_resource_usage(&config, job_id, node, system)
         .await
         .wrap_err("failed to fetch resource usage")?;
+    if let Some(rendered) = output.render_one(&result)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
     println!("CPU: {:.1}%", result.cpu);
     println!(
         "Memory: RSS {:.1}, VSZ: {:.1}",
@@ -205,35 +371,237 @@ pub(crate) async fn cli_cscs_job_resource_usage(
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
+/// Jobs a new job depends on, as passed on the command line
+pub(crate) struct JobDependencies {
+    pub after: Vec<JobIdOrName>,
+    pub afterok: Vec<JobIdOrName>,
+}
+
+async fn add_dependencies(
+    config: &Config,
+    options: &mut JobStartOptions,
+    dependencies: JobDependencies,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    for job in dependencies.after {
+        let job_id = maybe_job_id_from_name(config, job, system.clone(), platform.clone()).await?;
+        options.dependencies.push(JobDependency::After(job_id));
+    }
+    for job in dependencies.afterok {
+        let job_id = maybe_job_id_from_name(config, job, system.clone(), platform.clone()).await?;
+        options.dependencies.push(JobDependency::AfterOk(job_id));
+    }
+    Ok(())
+}
+
 pub(crate) async fn cli_cscs_job_start(
+    config: &Config,
     name: Option<String>,
-    options: JobStartOptions,
+    mut options: JobStartOptions,
+    dependencies: JobDependencies,
     system: Option<String>,
     platform: Option<ComputePlatform>,
     account: Option<String>,
 ) -> Result<()> {
-    match cscs_job_start(name, options, system, platform, account).await {
-        Ok(_) => {
-            println!("Job started");
+    add_dependencies(config, &mut options, dependencies, system.clone(), platform.clone()).await?;
+    let sweep = if options.sweep.is_empty() {
+        None
+    } else {
+        Some(sweep_combinations(&options.sweep)?)
+    };
+    match cscs_job_start(config, name, options, system, platform, account).await {
+        Ok(job_id) => {
+            let Some(combinations) = sweep else {
+                println!("Job started");
+                return Ok(());
+            };
+            println!("Started job array {} with {} tasks", job_id, combinations.len());
+            let mut builder = tabled::builder::Builder::new();
+            builder.push_record(["job id", "parameters"]);
+            for (index, parameters) in combinations.into_iter().enumerate() {
+                builder.push_record([
+                    format!("{}_{}", job_id, index),
+                    parameters
+                        .into_iter()
+                        .map(|(key, value)| format!("{key}={value}"))
+                        .join(" "),
+                ]);
+            }
+            let mut table = builder.build();
+            table.with(tabled::settings::Style::modern());
+            println!("{}", table);
             Ok(())
         }
         Err(e) => Err(e),
     }
 }
 
+/// Shows (or writes to `render_dir`) everything a job would be submitted with, without submitting it
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn cli_cscs_job_render(
+    config: &Config,
+    name: Option<String>,
+    mut options: JobStartOptions,
+    dependencies: JobDependencies,
+    render_dir: Option<PathBuf>,
+    output: OutputFormat,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    account: Option<String>,
+) -> Result<()> {
+    add_dependencies(config, &mut options, dependencies, system.clone(), platform.clone()).await?;
+    let (submission, files) = cscs_job_render(config, name, options, system, platform, account).await?;
+    write_render(&mut std::io::stdout(), &submission, files, render_dir, output)
+}
+
+/// Writes the output of a dry run, warnings go to stderr so structured output stays parseable
+fn write_render(
+    out: &mut impl Write,
+    submission: &JobSubmission,
+    files: Vec<(PathBuf, String)>,
+    render_dir: Option<PathBuf>,
+    output: OutputFormat,
+) -> Result<()> {
+    // structured output only contains the submission, so it can be parsed
+    let table = output == OutputFormat::Table;
+    match output.render_one(submission)? {
+        Some(rendered) => writeln!(out, "{}", rendered)?,
+        None => writeln!(out, "{}", submission_table(submission)?)?,
+    }
+    for (path, content) in files {
+        match &render_dir {
+            Some(render_dir) => {
+                // keep the layout of the remote job directory, e.g. for the scripts of sweeps
+                let target = render_dir.join(path.strip_prefix(&submission.remote_dir).unwrap_or(&path));
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&target, content).wrap_err(format!("couldn't write {}", target.display()))?;
+                if table {
+                    writeln!(out, "Wrote {}", target.display())?;
+                }
+            }
+            None if table => writeln!(out, "\n--- {} ---\n{}", path.display(), content.trim_end())?,
+            None => {}
+        }
+    }
+    if table {
+        writeln!(out, "\nDry run, nothing was submitted")?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_job_resubmit(
+    job: JobIdOrName,
+    name: Option<String>,
+    overrides: JobOverrides,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    account: Option<String>,
+) -> Result<()> {
+    let config = Config::new()?;
+    let key = match &job {
+        JobIdOrName::Id(id) => id.to_string(),
+        JobIdOrName::Name(name) => name.clone(),
+    };
+    if let Some(submission) = lookup_submission(&key)? {
+        // job ids are only unique per site, and the system might not exist at the active one
+        if submission.site != active_site() {
+            return Err(eyre!("job {} was submitted to site {}", key, submission.site)).with_suggestion(|| {
+                format!(
+                    "resubmit it with `coman cscs --site {} job resubmit {}`",
+                    submission.site, key
+                )
+            });
+        }
+        let options = submission.resubmit_options(overrides)?;
+        return cli_cscs_job_start(
+            &config,
+            name.or(Some(submission.name)),
+            options,
+            JobDependencies {
+                after: vec![],
+                afterok: vec![],
+            },
+            system.or(Some(submission.system)),
+            platform,
+            account.or(submission.account),
+        )
+        .await;
+    }
+
+    // not submitted from this machine, fall back to the files of the job on the remote
+    if overrides.changes_job_files() {
+        return Err(eyre!("job {} isn't in the local job history", key))
+            .suggestion("only slurm options and the name can be changed for jobs not submitted from this machine");
+    }
+    let job_name = match job {
+        JobIdOrName::Id(id) => {
+            cscs_job_details(&config, id.into(), system.clone(), platform.clone())
+                .await?
+                .ok_or(eyre!("couldn't find job {}", id))?
+                .name
+        }
+        JobIdOrName::Name(name) => name,
+    };
+    let (script_path, environment_path) = cscs_job_files(&config, &job_name, system.clone(), platform.clone()).await?;
+    eprintln!(
+        "Job isn't in the local history, resubmitting with {}",
+        script_path.display()
+    );
+    // the environment file contains the ssh endpoint of the original job, so it can't be used for ssh again
+    eprintln!("Warning: ssh and port forwarding aren't available for jobs resubmitted from remote files");
+    let options = JobStartOptions {
+        stdout: overrides.stdout,
+        stderr: overrides.stderr,
+        edf_spec: overrides.edf_spec.unwrap_or(EdfSpec::Remote(environment_path)),
+        script_spec: overrides.script_spec.unwrap_or(ScriptSpec::Remote(script_path)),
+        no_ssh: true,
+        ssh_key: None,
+        no_coman: overrides.no_coman,
+        slurm: overrides.slurm,
+        ..Default::default()
+    };
+    cli_cscs_job_start(
+        &config,
+        name.or(Some(job_name)),
+        options,
+        JobDependencies {
+            after: vec![],
+            afterok: vec![],
+        },
+        system,
+        platform,
+        account,
+    )
+    .await
+}
+
 pub(crate) async fn cli_cscs_job_cancel(
     job_id: JobIdOrName,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    let job_id = maybe_job_id_from_name(job_id, system.clone(), platform.clone()).await?;
-    cscs_job_cancel(job_id, system, platform).await
+    let config = Config::new()?;
+    let job_id = maybe_job_id_from_name(&config, job_id, system.clone(), platform.clone()).await?;
+    cscs_job_cancel(&config, job_id, system, platform).await
 }
 
-pub(crate) async fn cli_cscs_system_list(platform: Option<ComputePlatform>) -> Result<()> {
-    match cscs_system_list(platform).await {
+pub(crate) fn cli_cscs_edf_validate(file: PathBuf) -> Result<()> {
+    check_edf_file(&file)?;
+    println!("{} is valid", file.display());
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_system_list(output: OutputFormat, platform: Option<ComputePlatform>) -> Result<()> {
+    let config = Config::new()?;
+    match cscs_system_list(&config, platform).await {
         Ok(systems) => {
+            if let Some(rendered) = output.render(&systems)? {
+                println!("{}", rendered);
+                return Ok(());
+            }
             let mut table = tabled::Table::new(systems);
             table.with(tabled::settings::Style::modern());
             println!("{}", table);
@@ -246,13 +614,141 @@ pub(crate) async fn cli_cscs_set_system(system_name: String, global: bool) -> Re
     cscs_system_set(system_name, global).await
 }
 
+pub(crate) async fn cli_cscs_whoami(
+    output: OutputFormat,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    #[derive(serde::Serialize)]
+    struct WhoAmI {
+        identity: String,
+        login: Option<LoginMethod>,
+        account: Option<String>,
+        #[serde(flatten)]
+        user: UserInfo,
+    }
+    let identity = active_identity();
+    let login = if config.values.cscs.client_id.is_some() {
+        Some(LoginMethod::ClientCredentials)
+    } else {
+        login_method(&identity)?
+    };
+    let user = cscs_whoami(&config, system, platform)
+        .await
+        .wrap_err(format!("couldn't get user info for identity {}", identity))?;
+    let whoami = WhoAmI {
+        identity,
+        login,
+        account: config.values.cscs.account,
+        user,
+    };
+    if let Some(rendered) = output.render_one(&whoami)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
+    let data = &[
+        ("Identity", whoami.identity),
+        ("Login", whoami.login.map(|l| l.to_string()).unwrap_or_default()),
+        ("Account", whoami.account.unwrap_or_default()),
+        ("User", whoami.user.name),
+        ("User Id", whoami.user.id),
+        ("Group", whoami.user.group),
+    ];
+    let mut table = tabled::Table::nohead(data);
+    table.with(tabled::settings::Style::modern());
+    println!("{}", table);
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_identity_list(output: OutputFormat) -> Result<()> {
+    let identities = list_identities().await?;
+    if let Some(rendered) = output.render(&identities)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
+    if identities.is_empty() {
+        println!("No identities, log in with `coman cscs login` first");
+        return Ok(());
+    }
+    let mut table = tabled::Table::new(identities);
+    table.with(tabled::settings::Style::modern());
+    println!("{}", table);
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_identity_use(name: String, global: bool) -> Result<()> {
+    check_identity(&name).await?;
+    let mut config = Config::new()?;
+    config.set("cscs.identity", name, global)
+}
+
+pub(crate) fn cli_secrets_status() {
+    let backend = configured_backend();
+    println!("Secrets are stored in the {} backend", backend);
+    match backend {
+        SecretBackend::Keyring => println!("Credentials are kept in the secure storage of your operating system"),
+        SecretBackend::File => println!(
+            "Credentials are kept encrypted in {}, set {} to avoid the passphrase prompt",
+            get_config_dir().join("secrets.enc").display(),
+            PASSPHRASE_ENV_VAR
+        ),
+        SecretBackend::Env => println!(
+            "Credentials are read from {} and {}",
+            env_var_name(&secret_name(CLIENT_ID_SECRET_NAME, &active_identity())),
+            env_var_name(&secret_name(CLIENT_SECRET_SECRET_NAME, &active_identity()))
+        ),
+    }
+}
+
+pub(crate) async fn cli_secrets_migrate(to: SecretBackend, from: Option<SecretBackend>, keep: bool) -> Result<()> {
+    let from = from.unwrap_or(configured_backend());
+    if to == SecretBackend::Env {
+        return Err(eyre!("the env secret backend is read only")).suggestion(format!(
+            "set {} and {} and run `coman config set --global secrets.backend env` instead",
+            env_var_name(&secret_name(CLIENT_ID_SECRET_NAME, &active_identity())),
+            env_var_name(&secret_name(CLIENT_SECRET_SECRET_NAME, &active_identity()))
+        ));
+    }
+    let migrated = migrate_secrets(from, to, &known_secret_names()?, keep).await?;
+    let mut config = Config::new()?;
+    config
+        .set("secrets.backend", to.to_string(), true)
+        .wrap_err("couldn't switch secret backend")?;
+    println!(
+        "Moved {} secrets from the {} to the {} backend",
+        migrated.len(),
+        from,
+        to
+    );
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_identity_remove(name: String) -> Result<()> {
+    remove_identity(&name).await?;
+    println!("Removed identity {}", name);
+    if active_identity() == name {
+        println!(
+            "Identity {} is still selected in your config, pick another one with `coman cscs identity use`",
+            name
+        );
+    }
+    Ok(())
+}
+
 pub(crate) async fn cli_cscs_file_list(
     path: PathBuf,
+    output: OutputFormat,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    match cscs_file_list(path, system, platform).await {
+    let config = Config::new()?;
+    match cscs_file_list(&config, path, system, platform).await {
         Ok(path_entries) => {
+            if let Some(rendered) = output.render(&path_entries)? {
+                println!("{}", rendered);
+                return Ok(());
+            }
             let mut table = tabled::Table::new(path_entries);
             table.with(tabled::settings::Style::empty());
             println!("{}", table);
@@ -263,10 +759,13 @@ pub(crate) async fn cli_cscs_file_list(
 }
 pub(crate) async fn cli_cscs_file_delete(
     path: PathBuf,
+    recursive: bool,
+    account: Option<String>,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    match cscs_file_delete(path, system, platform).await {
+    let config = Config::new()?;
+    match cscs_file_delete(&config, path, recursive, account, system, platform).await {
         Ok(()) => {
             println!("Path removed");
             Ok(())
@@ -275,19 +774,187 @@ pub(crate) async fn cli_cscs_file_delete(
     }
 }
 
+pub(crate) async fn cli_cscs_file_head(
+    path: PathBuf,
+    lines: Option<usize>,
+    bytes: Option<usize>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    let content = cscs_file_head(&config, path, lines, bytes, system, platform).await?;
+    print!("{}", content);
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_cat(
+    path: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    let content = cscs_file_view(&config, path, system, platform).await?;
+    print!("{}", content);
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_type(
+    path: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    match cscs_file_type(&config, path, system, platform).await? {
+        Some(file_type) => println!("{}", file_type),
+        None => println!("unknown"),
+    }
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_copy(
+    source: PathBuf,
+    target: PathBuf,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    println!("started copy job, this might take a while");
+    cscs_file_copy(&config, source, target, account, system, platform).await?;
+    println!("Path copied");
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_move(
+    source: PathBuf,
+    target: PathBuf,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    println!("started move job, this might take a while");
+    cscs_file_move(&config, source, target, account, system, platform).await?;
+    println!("Path moved");
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_link(
+    target: PathBuf,
+    link: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    cscs_file_link(&config, target, link, system, platform).await?;
+    println!("Link created");
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_chown(
+    path: PathBuf,
+    owner: String,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    let (owner, group) = match owner.split_once(':') {
+        Some((owner, group)) => (owner, Some(group.to_owned()).filter(|g| !g.is_empty())),
+        None => (owner.as_str(), None),
+    };
+    let owner = Some(owner.to_owned()).filter(|o| !o.is_empty());
+    if owner.is_none() && group.is_none() {
+        return Err(eyre!("either an owner or a group has to be set"));
+    }
+    cscs_file_chown(&config, path, owner, group, system, platform).await?;
+    println!("Owner changed");
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_tar(
+    source: PathBuf,
+    archive: PathBuf,
+    direct: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    if !direct {
+        println!("started compression job, this might take a while");
+    }
+    cscs_file_compress(&config, source, archive, direct, account, system, platform).await?;
+    println!("Archive created");
+    Ok(())
+}
+
+pub(crate) async fn cli_cscs_file_untar(
+    archive: PathBuf,
+    target: PathBuf,
+    direct: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    if !direct {
+        println!("started extraction job, this might take a while");
+    }
+    cscs_file_extract(&config, archive, target, direct, account, system, platform).await?;
+    println!("Archive extracted");
+    Ok(())
+}
+
+/// Flags of `coman cscs file upload`
+pub(crate) struct UploadOptions {
+    pub recursive: bool,
+    pub resume: bool,
+    pub parallel: Option<usize>,
+}
+
+pub(crate) async fn cli_cscs_file_download_archive(
+    remote: PathBuf,
+    local: PathBuf,
+    extract: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    let target = cscs_file_download_archive(&config, remote, local, extract, account, system, platform).await?;
+    println!("Folder successfully downloaded to {}", target.display());
+    Ok(())
+}
+
 pub(crate) async fn cli_cscs_file_download(
     remote: PathBuf,
     local: PathBuf,
+    recursive: bool,
     account: Option<String>,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
+    let config = Config::new()?;
+    if recursive {
+        cscs_file_download_dir(&config, remote, local, account, system, platform).await?;
+        println!("Folder successfully downloaded");
+        return Ok(());
+    }
     let local = if local.is_dir() {
         local.join(remote.file_name().ok_or(eyre!("couldn't get name of remote file"))?)
     } else {
         local
     };
-    match cscs_file_download(remote, local.clone(), account, system.clone(), platform.clone()).await {
+    match cscs_file_download(
+        &config,
+        remote.clone(),
+        local.clone(),
+        account,
+        system.clone(),
+        platform.clone(),
+    )
+    .await
+    {
         Ok(None) => {
             println!("File successfully downloaded");
             Ok(())
@@ -296,39 +963,28 @@ pub(crate) async fn cli_cscs_file_download(
             // file is large, so we created a transfer job to s3 that we need to wait on
             // then we can download from s3
             println!("started s3 transfer job {}", job_data.0);
-            let mut transfer_done = false;
-            while !transfer_done {
-                if let Some(job) = cscs_job_details(job_data.0.clone(), system.clone(), platform.clone()).await? {
-                    match job.status {
-                        JobStatus::Pending | JobStatus::Requeued | JobStatus::Running => {}
-                        JobStatus::Finished => transfer_done = true,
-                        JobStatus::Cancelled | JobStatus::Failed => return Err(eyre!("transfer job failed")),
-                        JobStatus::Timeout => return Err(eyre!("transfer job timed out")),
-                    }
-                }
-                tokio::time::sleep(Duration::from_secs(2)).await;
-            }
+            cscs_transfer_wait(&config, job_data.0.clone(), system.clone(), platform.clone()).await?;
 
             // download from s3
             println!("Downloading file from s3, this might take a while");
 
-            let mut output = File::create(local).await?;
-            let mut stream = reqwest::get(job_data.1).await?.bytes_stream();
+            let parallelism = config.values.cscs.transfer_parallelism();
             let mut progress = 0;
             let mut start_time = Instant::now();
-            while let Some(chunk_result) = stream.next().await {
-                let chunk = chunk_result?;
-                output.write_all(&chunk).await?;
-                progress += chunk.len();
-
+            ranged_download(&job_data.1, &local, job_data.2 as u64, parallelism, |bytes| {
+                progress += bytes;
                 if start_time.elapsed() >= Duration::from_secs(1) {
                     print!("\rDownloaded {}/{}Mb", progress / 1024 / 1024, job_data.2 / 1024 / 1024);
                     std::io::stdout().flush()?;
                     start_time = Instant::now();
                 }
-            }
-            output.flush().await?;
+                Ok(())
+            })
+            .await
+            .suggestion("run the same download again to resume it")?;
             println!(); //force newline
+            println!("Verifying checksum");
+            cscs_file_verify(&config, remote, local, system, platform).await?;
             println!("Download complete");
 
             Ok(())
@@ -340,68 +996,303 @@ pub(crate) async fn cli_cscs_file_download(
 pub(crate) async fn cli_cscs_file_upload(
     local: PathBuf,
     remote: PathBuf,
+    options: UploadOptions,
     account: Option<String>,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    match cscs_file_upload(local.clone(), remote, account, system, platform).await {
-        Ok(None) => {
-            println!("File successfully uploaded");
-            Ok(())
+    let config = Config::new()?;
+    if local.is_dir() {
+        if !options.recursive {
+            return Err(eyre!("local path is a directory")).suggestion("use --recursive to upload whole directories");
         }
-        Ok(Some(transfer_data)) => {
-            println!("starting file transfer, this might take a while");
-            let mut etags: Vec<String> = Vec::new();
-            let client = reqwest::Client::new();
-            let num_parts = transfer_data.1.num_parts;
-            for (chunk_id, transfer_url) in transfer_data.1.parts_upload_urls.into_iter().enumerate() {
-                println!(
-                    "Uploading part {}/{} ({}Mb)",
-                    chunk_id + 1,
-                    num_parts,
-                    transfer_data.1.part_size / 1024 / 1024
-                );
-                let etag = upload_chunk(
-                    local.clone(),
-                    (chunk_id as u64) * transfer_data.1.part_size,
-                    transfer_data.1.part_size,
-                    transfer_url,
-                )
-                .await?;
-                etags.push(etag);
+        cscs_file_upload_dir(&config, local, remote, account, system, platform).await?;
+        println!("Folder successfully uploaded");
+        return Ok(());
+    }
+    let mut state = if options.resume {
+        let state = cscs_file_upload_resume(&config, local, remote, system, platform).await?;
+        println!(
+            "resuming file transfer, {}/{} parts already uploaded",
+            state.etags.len(),
+            state.parts_upload_urls.len()
+        );
+        state
+    } else {
+        match cscs_file_upload(&config, local, remote, account, system, platform).await? {
+            None => {
+                println!("File successfully uploaded");
+                return Ok(());
+            }
+            Some(state) => {
+                println!("starting file transfer, this might take a while");
+                state
             }
+        }
+    };
+    let parallelism = options
+        .parallel
+        .unwrap_or(config.values.cscs.transfer_parallelism())
+        .max(1);
+    let num_parts = state.parts_upload_urls.len();
+    let part_size = state.part_size / 1024 / 1024;
+    state
+        .upload(parallelism, |done, _| {
+            println!("Uploaded part {}/{} ({}Mb)", done, num_parts, part_size);
+            Ok(())
+        })
+        .await
+        .suggestion("continue the upload with --resume")?;
+    println!("done");
+    Ok(())
+}
 
-            let body = etags
-                .into_iter()
-                .enumerate()
-                .map(|(i, etag)| (i + 1, etag))
-                .map(|(i, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", i, etag))
-                .join("");
-            let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", body);
-            let req = client.post(transfer_data.1.complete_upload_url).body(body).build()?;
-            let resp = client.execute(req).await?;
-            match resp.error_for_status() {
-                Ok(_) => {
-                    println!("done");
-                    Ok(())
+pub(crate) async fn cli_cscs_file_sync(
+    local: PathBuf,
+    remote: PathBuf,
+    delete: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let config = Config::new()?;
+    let summary = cscs_file_sync(&config, local, remote, delete, account, system, platform).await?;
+    for path in summary.uploaded.iter() {
+        println!("uploaded {}", path.display());
+    }
+    for path in summary.deleted.iter() {
+        println!("deleted {}", path.display());
+    }
+    println!(
+        "Sync done: {} uploaded, {} unchanged, {} deleted",
+        summary.uploaded.len(),
+        summary.unchanged,
+        summary.deleted.len()
+    );
+    Ok(())
+}
+
+pub(crate) async fn cli_pipeline_run(file: PathBuf) -> Result<()> {
+    let run = pipeline_run(&file).await?;
+    let mut builder = tabled::builder::Builder::new();
+    builder.push_record(["step", "job id"]);
+    for (step, job_id) in run.steps {
+        builder.push_record([step, job_id]);
+    }
+    let mut table = builder.build();
+    table.with(tabled::settings::Style::modern());
+    println!("Submitted pipeline {}", run.name);
+    println!("{}", table);
+    Ok(())
+}
+
+pub(crate) fn cli_config_profiles(output: OutputFormat) -> Result<()> {
+    #[derive(serde::Serialize)]
+    struct NamedProfile {
+        name: String,
+        #[serde(flatten)]
+        profile: Profile,
+    }
+    let config = Config::new()?;
+    let profiles: Vec<_> = config
+        .values
+        .profiles
+        .into_iter()
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .map(|(name, profile)| NamedProfile { name, profile })
+        .collect();
+    if let Some(rendered) = output.render(&profiles)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
+    if profiles.is_empty() {
+        println!("No profiles defined, add them as `[profiles.<name>]` to your coman.toml");
+        return Ok(());
+    }
+    let mut builder = tabled::builder::Builder::default();
+    builder.push_record(["name", "settings"]);
+    for NamedProfile { name, profile } in profiles {
+        builder.push_record([name, toml::to_string(&profile)?.trim().to_owned()]);
+    }
+    let mut table = builder.build();
+    table.with(tabled::settings::Style::modern());
+    println!("{}", table);
+    Ok(())
+}
+
+pub(crate) fn cli_history_list(limit: Option<usize>, output: OutputFormat) -> Result<()> {
+    let mut history = load_history()?;
+    history.reverse();
+    if let Some(limit) = limit {
+        history.truncate(limit);
+    }
+    if let Some(rendered) = output.render(&history)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
+    let mut table = tabled::Table::new(history);
+    table.with(tabled::settings::Style::modern());
+    println!("{}", table);
+    Ok(())
+}
+
+pub(crate) fn cli_history_show(job: String, output: OutputFormat) -> Result<()> {
+    let submission = find_submission(&job)?;
+    if let Some(rendered) = output.render_one(&submission)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
+    println!("{}", submission_table(&submission)?);
+    for (path, content) in [
+        (&submission.script_path, &submission.script),
+        (&submission.environment_path, &submission.environment),
+    ] {
+        match content {
+            Some(content) => println!("\n--- {} ---\n{}", path.display(), content.trim_end()),
+            None => println!("\n--- {} --- (provided remotely, not recorded)", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Key/value table of the settings a job was submitted with
+fn submission_table(submission: &JobSubmission) -> Result<tabled::Table> {
+    let git = submission.git_commit.as_ref().map(|commit| {
+        if submission.git_dirty {
+            format!("{commit} (with uncommitted changes)")
+        } else {
+            commit.clone()
+        }
+    });
+    let job = if submission.job_id.is_empty() {
+        submission.name.clone()
+    } else {
+        format!("{} ({})", submission.name, submission.job_id)
+    };
+    let rows = [
+        ("Job", job),
+        ("System", submission.system.clone()),
+        ("Account", submission.account.clone().unwrap_or_default()),
+        (
+            "Submitted",
+            submission.submitted.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ),
+        ("Image", submission.image.clone().unwrap_or_default()),
+        ("Command", submission.command.join(" ")),
+        ("Workdir", submission.workdir.clone()),
+        ("Env", submission.env.iter().map(|(k, v)| format!("{k}={v}")).join("\n")),
+        (
+            "Mounts",
+            submission.mounts.iter().map(|(k, v)| format!("{k}:{v}")).join("\n"),
+        ),
+        ("Slurm", toml::to_string(&submission.slurm)?.trim().to_owned()),
+        ("Dependency", submission.dependency.clone().unwrap_or_default()),
+        (
+            "Submitted from",
+            submission
+                .local_dir
+                .as_ref()
+                .map(|d| d.display().to_string())
+                .unwrap_or_default(),
+        ),
+        ("Git commit", git.unwrap_or_default()),
+        ("Remote dir", submission.remote_dir.display().to_string()),
+    ];
+    let mut builder = tabled::builder::Builder::default();
+    for (key, value) in rows {
+        builder.push_record([key.to_owned(), value]);
+    }
+    let mut table = builder.build();
+    table.with(tabled::settings::Style::modern());
+    Ok(table)
+}
+
+pub(crate) async fn cli_watch(interval: Duration) -> Result<()> {
+    let config = Config::new()?;
+    let mut watcher = JobWatcher::new(config.values.notifications.clone(), None, None)?;
+    if watcher.config().sinks.is_empty() {
+        eprintln!("No notification sinks configured, notifications will only be printed");
+        eprintln!("Set `notifications.sinks` in your coman.toml to get desktop, bell or webhook notifications");
+    }
+    println!("Watching jobs, press Ctrl+C to stop");
+    loop {
+        match watcher.check(&config).await {
+            Ok(notifications) => {
+                for notification in notifications {
+                    println!(
+                        "[{}] {}: {}",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        notification.title,
+                        notification.message
+                    );
+                    if let Err(e) = send_notification(watcher.config(), &notification).await {
+                        eprintln!("{:?}", e.wrap_err("couldn't send notification"));
+                    }
                 }
-                Err(e) => Err(e).wrap_err("failed to complete upload"),
             }
+            // keep watching, errors like network issues are usually temporary
+            Err(e) => eprintln!("{:?}", e.wrap_err("couldn't check jobs")),
         }
-        Err(e) => Err(e),
+        tokio::time::sleep(interval).await;
     }
 }
 
-pub(crate) async fn upload_chunk(path: PathBuf, offset: u64, size: u64, url: Url) -> Result<String> {
-    let client = reqwest::Client::new();
+pub(crate) async fn cli_pipeline_status(name: Option<String>, output: OutputFormat) -> Result<()> {
+    let (run, statuses) = pipeline_status(name).await?;
+    if let Some(rendered) = output.render(&statuses)? {
+        println!("{}", rendered);
+        return Ok(());
+    }
+    let finished = statuses
+        .iter()
+        .filter(|s| s.status == JobStatus::Finished.to_string())
+        .count();
+    println!(
+        "Pipeline {} (submitted {}): {}/{} steps finished",
+        run.name,
+        run.submitted.format("%Y-%m-%d %H:%M:%S"),
+        finished,
+        statuses.len()
+    );
+    let mut table = tabled::Table::new(statuses);
+    table.with(tabled::settings::Style::modern());
+    println!("{}", table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use current_dir::Cwd;
+
+    use super::*;
+    use crate::cscs::{api_client::fake::FakeBackend, handlers::render_job};
 
-    let source_file = File::open(path).await?;
-    let mut buf = vec![];
-    let mut reader = BufReader::new(source_file);
-    reader.seek(SeekFrom::Start(offset)).await?;
-    let mut chunk = reader.take(size);
-    chunk.read_to_end(&mut buf).await?;
-    let req = client.put(url).body(buf).build()?;
-    let resp = client.execute(req).await?;
-    Ok(resp.headers()["etag"].to_str()?.to_owned())
+    #[tokio::test]
+    async fn test_dry_run_output() {
+        // the active site is read from the config in the current directory, which other tests change
+        {
+            let _locked_cwd = Cwd::mutex().lock().expect("couldn't get cwd lock");
+            active_site();
+        }
+        let config = Config::defaults().unwrap();
+        let backend = FakeBackend::new(&["daint"]);
+        // without an image there is no image metadata, which used to print a warning to stdout
+        let options = JobStartOptions {
+            no_ssh: true,
+            no_coman: true,
+            ..Default::default()
+        };
+        let (submission, files) = render_job(&backend, &config, "daint", Some("train".to_owned()), options, None)
+            .await
+            .unwrap();
+        assert!(!files.is_empty());
+        // nothing was created on the cluster
+        assert!(!backend.exists("/scratch/user/train"));
+
+        let mut out = vec![];
+        write_render(&mut out, &submission, files, None, OutputFormat::Json).unwrap();
+        let rendered: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(rendered["name"], "train");
+        assert_eq!(rendered["system"], "daint");
+    }
 }
@@ -0,0 +1,288 @@
+use std::{fmt::Display, ops::Range, path::Path};
+
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use toml_edit::{Document, Item, TableLike, Value};
+
+/// Top level keys of the environment definition format (EDF) of the CSCS container engine
+const EDF_KEYS: [&str; 8] = [
+    "base_environment",
+    "image",
+    "mounts",
+    "workdir",
+    "writable",
+    "entrypoint",
+    "env",
+    "annotations",
+];
+const MOUNT_FLAGS: [&str; 5] = ["ro", "rw", "sqsh", "private", "rprivate"];
+
+/// A problem found in an environment definition file
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdfIssue {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for EdfIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Checks an environment definition file against the format the container engine expects
+pub fn validate_edf(content: &str) -> Vec<EdfIssue> {
+    let line = |span: Option<Range<usize>>| span.map(|s| content[..s.start].matches('\n').count() + 1).unwrap_or(1);
+    let document = match Document::parse(content) {
+        Ok(document) => document,
+        Err(e) => {
+            return vec![EdfIssue {
+                line: line(e.span()),
+                message: format!("invalid toml: {}", e.message()),
+            }];
+        }
+    };
+    let mut issues = vec![];
+    let mut issue = |span: Option<Range<usize>>, message: String| {
+        issues.push(EdfIssue {
+            line: line(span),
+            message,
+        })
+    };
+    for (key, item) in document.iter() {
+        // tables without a header of their own have no span, so point at their key instead
+        let span = document.key(key).and_then(|k| k.span()).or(item.span());
+        match key {
+            "image" | "workdir" => match item.as_str() {
+                Some("") => issue(span, format!("`{key}` can't be empty")),
+                Some(path) if key == "workdir" && !is_absolute(path) => {
+                    issue(span, format!("`workdir` must be an absolute path, got `{path}`"))
+                }
+                Some(_) => {}
+                None => issue(span, format!("`{key}` must be a string, got {}", item.type_name())),
+            },
+            "writable" | "entrypoint" if item.as_bool().is_none() => {
+                issue(span, format!("`{key}` must be true or false, got {}", item.type_name()))
+            }
+            "base_environment" => {
+                let valid =
+                    item.as_str().is_some() || item.as_array().is_some_and(|a| a.iter().all(|v| v.as_str().is_some()));
+                if !valid {
+                    issue(
+                        span,
+                        "`base_environment` must be a string or a list of strings".to_owned(),
+                    );
+                }
+            }
+            "mounts" => match item.as_array() {
+                Some(mounts) => {
+                    for mount in mounts.iter() {
+                        match mount.as_str() {
+                            Some(m) => {
+                                if let Err(message) = validate_mount(m) {
+                                    issue(mount.span().or(span.clone()), message);
+                                }
+                            }
+                            None => issue(
+                                mount.span().or(span.clone()),
+                                format!("mounts must be strings, got {}", mount.type_name()),
+                            ),
+                        }
+                    }
+                }
+                None => issue(span, format!("`mounts` must be a list, got {}", item.type_name())),
+            },
+            "env" => match item.as_table_like() {
+                Some(env) => {
+                    for (name, value) in env.iter() {
+                        let span = env.key(name).and_then(|k| k.span()).or(value.span());
+                        if !is_env_name(name) {
+                            issue(span.clone(), format!("invalid environment variable name `{name}`"));
+                        }
+                        if value.as_str().is_none() {
+                            issue(
+                                span,
+                                format!(
+                                    "environment variable `{name}` must be a string, got {}",
+                                    value.type_name()
+                                ),
+                            );
+                        }
+                    }
+                }
+                None => issue(span, format!("`env` must be a table, got {}", item.type_name())),
+            },
+            "annotations" => match item.as_table_like() {
+                Some(annotations) => {
+                    let mut values = vec![];
+                    annotation_values(annotations, "", &mut values);
+                    for (name, value, value_span) in values {
+                        let span = value_span.or(span.clone());
+                        if !name.split('.').all(is_annotation_segment) {
+                            issue(span.clone(), format!("invalid annotation key `{name}`"));
+                        }
+                        match value.and_then(|v| v.as_str()) {
+                            Some(v) if name.ends_with(".enabled") && v != "true" && v != "false" => issue(
+                                span,
+                                format!("annotation `{name}` must be \"true\" or \"false\", got \"{v}\""),
+                            ),
+                            Some(_) => {}
+                            None => issue(
+                                span,
+                                format!(
+                                    "annotation `{name}` must be a string, e.g. `{name} = \"{}\"`",
+                                    value.map(|v| v.to_string().trim().to_owned()).unwrap_or_default()
+                                ),
+                            ),
+                        }
+                    }
+                }
+                None => issue(span, format!("`annotations` must be a table, got {}", item.type_name())),
+            },
+            "writable" | "entrypoint" => {}
+            _ => issue(
+                span,
+                format!("unknown key `{key}`, expected one of {}", EDF_KEYS.join(", ")),
+            ),
+        }
+    }
+    issues
+}
+
+/// Validates an environment definition file, returning an error listing all problems
+pub fn check_edf(content: &str, name: &str) -> Result<()> {
+    let issues = validate_edf(content);
+    if issues.is_empty() {
+        return Ok(());
+    }
+    Err(eyre!(
+        "invalid environment definition {}:\n{}",
+        name,
+        issues.iter().map(|i| format!("  {i}")).collect::<Vec<_>>().join("\n")
+    ))
+    .suggestion("see https://docs.cscs.ch/software/container-engine/ for the format of environment definition files")
+}
+
+/// Validates an environment definition file on disk
+pub fn check_edf_file(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path).wrap_err(format!("couldn't read {}", path.display()))?;
+    check_edf(&content, &path.display().to_string())
+}
+
+/// Full dotted key, value and location of an annotation
+type Annotation<'a> = (String, Option<&'a Value>, Option<Range<usize>>);
+
+/// Collects the values of all (possibly nested) annotations with their full dotted key
+fn annotation_values<'a>(table: &'a dyn TableLike, prefix: &str, values: &mut Vec<Annotation<'a>>) {
+    for (key, item) in table.iter() {
+        let name = if prefix.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        let span = table.key(key).and_then(|k| k.span()).or(item.span());
+        match item {
+            Item::Value(Value::InlineTable(_)) | Item::Table(_) => {
+                if let Some(nested) = item.as_table_like() {
+                    annotation_values(nested, &name, values);
+                }
+            }
+            _ => values.push((name, item.as_value(), span)),
+        }
+    }
+}
+
+/// Checks mounts of the form `SOURCE:DESTINATION[:FLAGS]`
+fn validate_mount(mount: &str) -> std::result::Result<(), String> {
+    let parts: Vec<&str> = mount.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return Err(format!(
+            "invalid mount `{mount}`, expected `SOURCE:DESTINATION` or `SOURCE:DESTINATION:FLAGS`"
+        ));
+    }
+    if !is_absolute(parts[0]) {
+        return Err(format!("mount source `{}` must be an absolute path", parts[0]));
+    }
+    if !is_absolute(parts[1]) {
+        return Err(format!("mount destination `{}` must be an absolute path", parts[1]));
+    }
+    if let Some(flags) = parts.get(2)
+        && let Some(flag) = flags.split(',').find(|f| !MOUNT_FLAGS.contains(f))
+    {
+        return Err(format!(
+            "unknown mount flag `{flag}` in `{mount}`, expected one of {}",
+            MOUNT_FLAGS.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Absolute paths, which may also start with an environment variable like `${SCRATCH}`
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('$')
+}
+
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_annotation_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_edf() {
+        let valid = r#"
+image = "ubuntu:24.04"
+mounts = ["${SCRATCH}:/scratch", "/users/me/coman.sqsh:/coman:sqsh"]
+workdir = "/scratch"
+
+[env]
+LR = "0.1"
+
+[annotations]
+com.hooks.ssh.enabled = "true"
+com.hooks.ssh.port = "15263"
+"#;
+        assert_eq!(validate_edf(valid), vec![]);
+
+        let invalid = r#"imgae = "ubuntu"
+mounts = ["/scratch", "relative:/data", "/a:/b:rx"]
+workdir = "scratch"
+
+[env]
+LR = 0.1
+
+[annotations]
+com.hooks.ssh.enabled = true
+com.hooks.dcgm.enabled = "yes"
+"#;
+        let issues: Vec<String> = validate_edf(invalid).iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            issues,
+            vec![
+                "line 1: unknown key `imgae`, expected one of base_environment, image, mounts, workdir, writable, entrypoint, env, annotations",
+                "line 2: invalid mount `/scratch`, expected `SOURCE:DESTINATION` or `SOURCE:DESTINATION:FLAGS`",
+                "line 2: mount source `relative` must be an absolute path",
+                "line 2: unknown mount flag `rx` in `/a:/b:rx`, expected one of ro, rw, sqsh, private, rprivate",
+                "line 3: `workdir` must be an absolute path, got `scratch`",
+                "line 6: environment variable `LR` must be a string, got float",
+                "line 9: annotation `com.hooks.ssh.enabled` must be a string, e.g. `com.hooks.ssh.enabled = \"true\"`",
+                "line 10: annotation `com.hooks.dcgm.enabled` must be \"true\" or \"false\", got \"yes\"",
+            ]
+        );
+
+        let broken = validate_edf("image = \"ubuntu\"\nmounts = [\n");
+        assert_eq!(broken.len(), 1);
+        assert!(broken[0].message.starts_with("invalid toml"));
+    }
+}
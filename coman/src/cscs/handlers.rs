@@ -3,17 +3,18 @@ use std::os::unix::fs::MetadataExt;
 #[cfg(target_family = "windows")]
 use std::os::windows::fs::MetadataExt;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
     str::FromStr,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use base64::prelude::*;
 use color_eyre::{Result, Section, eyre::eyre};
 use eyre::Context;
 use futures::StreamExt;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use iroh::{Endpoint, EndpointId, SecretKey};
 use itertools::Itertools;
 use regex::Regex;
@@ -33,83 +34,81 @@ use super::api_client::client::{EdfSpec, ScriptSpec};
 use crate::{
     cli::{
         app::COMAN_VERSION,
+        exec::node_secret_key,
         rpc::{COMAN_RPC_ALPN, ComanRPCClient, ResourceUsage},
     },
-    config::{ComputePlatform, Config, get_data_dir},
+    config::{ComputePlatform, Config, EndpointMode, active_site, get_data_dir},
     cscs::{
         api_client::{
-            client::{CscsApi, JobStartOptions},
+            backend::ComputeBackend,
+            client::JobStartOptions,
             types::{
-                FileStat, FileSystemType, Job, JobDetail, JobId, JobStatus, PathEntry, PathType, S3Upload, System,
-                UserInfo,
+                ArchiveCompression, FileStat, FileSystemType, Job, JobDependency, JobDetail, JobId, JobStatus,
+                LogStream, PathEntry, PathType, System, UserInfo,
             },
         },
-        cli::upload_chunk,
+        auth::{get_api_client, set_access_token},
+        edf::check_edf,
+        history::{JobSubmission, git_state, record_submission},
+        identity::{LoginMethod, active_identity, record_login, secret_name},
         oauth2::{
-            CLIENT_ID_SECRET_NAME, CLIENT_SECRET_SECRET_NAME, client_credentials_login, finish_cscs_device_login,
-            start_cscs_device_login,
+            CLIENT_ID_SECRET_NAME, CLIENT_SECRET_SECRET_NAME, REFRESH_TOKEN_SECRET_NAME, client_credentials_login,
+            finish_cscs_device_login, start_cscs_device_login,
         },
+        s3::{UploadState, multipart_upload, ranged_download},
+        sweep::sweep_combinations,
     },
     util::{
-        keyring::{Secret, get_secret, store_secret},
+        secrets::{Secret, delete_secret, store_secret},
+        shell::{check_variable_name, shell_quote, shell_quote_filter},
         types::{DockerImageMeta, DockerImageUrl},
     },
 };
 
 const CSCS_MAX_DIRECT_SIZE: usize = 5242880;
-
-async fn get_access_token() -> Result<Secret> {
-    let config = Config::new()?;
-    let client_id = if let Some(client_id) = config.values.cscs.client_id {
-        Secret(client_id)
-    } else {
-        match get_secret(CLIENT_ID_SECRET_NAME).await {
-            Ok(Some(client_id)) => client_id,
-            Ok(None) => Err(eyre!("not logged in"))?,
-            Err(e) => Err(e)?,
-        }
-    };
-    let client_secret = if let Some(client_secret) = config.values.cscs.client_secret {
-        Secret(client_secret)
-    } else {
-        match get_secret(CLIENT_SECRET_SECRET_NAME).await {
-            Ok(Some(client_secret)) => client_secret,
-            Ok(None) => Err(eyre!("not logged in"))?,
-            Err(e) => Err(e)?,
-        }
-    };
-    let token = client_credentials_login(client_id, client_secret).await?;
-    Ok(token.0)
-}
-
-pub(crate) async fn cscs_login(client_id: String, client_secret: String) -> Result<()> {
+const JOB_POLL_MIN_INTERVAL: Duration = Duration::from_secs(2);
+const JOB_POLL_MAX_INTERVAL: Duration = Duration::from_secs(60);
+const TRANSFER_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Finished jobs are eventually purged from the scheduler, so a transfer job we can't find isn't coming back
+const TRANSFER_MAX_MISSING_POLLS: u32 = 10;
+/// Transfer jobs have a much shorter time limit on the cluster, this only guards against waiting forever
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+const LOG_FOLLOW_INTERVAL: Duration = Duration::from_secs(2);
+const LOG_READ_CHUNK_SIZE: usize = CSCS_MAX_DIRECT_SIZE;
+const COMAN_IGNORE_FILE: &str = ".comanignore";
+
+pub(crate) async fn cscs_login(config: &Config, client_id: String, client_secret: String) -> Result<()> {
+    let site = config.values.current_site()?;
+    let identity = active_identity();
     let client_id_secret = Secret::new(client_id);
-    store_secret(CLIENT_ID_SECRET_NAME, client_id_secret.clone()).await?;
+    store_secret(&secret_name(CLIENT_ID_SECRET_NAME, &identity), client_id_secret.clone()).await?;
     let client_secret_secret = Secret::new(client_secret);
-    store_secret(CLIENT_SECRET_SECRET_NAME, client_secret_secret.clone()).await?;
-    client_credentials_login(client_id_secret, client_secret_secret)
-        .await
-        .map(|_| ())
-}
-pub(crate) async fn get_available_compute_platforms() -> Result<Vec<ComputePlatform>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let mut platforms = Vec::new();
-            for platform in ComputePlatform::iter() {
-                let api_client = CscsApi::new(access_token.0.clone(), Some(platform.clone()))?;
-                if (api_client.list_systems().await).is_ok() {
-                    platforms.push(platform);
-                }
-            }
-            Ok(platforms)
+    store_secret(
+        &secret_name(CLIENT_SECRET_SECRET_NAME, &identity),
+        client_secret_secret.clone(),
+    )
+    .await?;
+    let tokens = client_credentials_login(&site, client_id_secret, client_secret_secret).await?;
+    // otherwise a previous device login would keep taking precedence
+    delete_secret(&secret_name(REFRESH_TOKEN_SECRET_NAME, &identity)).await?;
+    set_access_token(tokens).await;
+    record_login(&identity, LoginMethod::ClientCredentials)
+}
+pub(crate) async fn get_available_compute_platforms(config: &Config) -> Result<Vec<ComputePlatform>> {
+    let mut platforms = Vec::new();
+    for platform in ComputePlatform::iter() {
+        let api_client = get_api_client(config, Some(platform.clone())).await?;
+        if (api_client.list_systems().await).is_ok() {
+            platforms.push(platform);
         }
-        Err(e) => Err(e),
     }
+    Ok(platforms)
 }
 
-#[allow(dead_code)]
-pub async fn cscs_login_device_code() -> Result<(Secret, Option<Secret>)> {
-    let (details, verify_url) = start_cscs_device_login().await?;
+/// Logs in with the user's own CSCS identity by authorizing coman in the browser
+pub async fn cscs_login_device_code(config: &Config) -> Result<()> {
+    let site = config.values.current_site()?;
+    let (details, verify_url) = start_cscs_device_login(&site).await?;
 
     println!("Please visit {} and authorize this application.", verify_url);
     open::that(verify_url.clone())
@@ -118,15 +117,43 @@ pub async fn cscs_login_device_code() -> Result<(Secret, Option<Secret>)> {
             std::io::Result::Ok(())
         })
         .unwrap();
-    finish_cscs_device_login(details).await
+    let tokens = finish_cscs_device_login(&site, details).await?;
+    set_access_token(tokens).await;
+    record_login(&active_identity(), LoginMethod::Device)
 }
 
-pub async fn cscs_system_list(platform: Option<ComputePlatform>) -> Result<Vec<System>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            api_client.list_systems().await
-        }
+/// The CSCS user the current identity logs in as
+pub async fn cscs_whoami(
+    config: &Config,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<UserInfo> {
+    let api_client = get_api_client(config, platform).await?;
+    api_client
+        .get_userinfo(&system.unwrap_or(config.values.cscs.current_system.clone()))
+        .await
+}
+
+/// Sets the first available compute platform as the current one, unless one is configured already
+pub(crate) async fn cscs_set_default_platform() -> Result<()> {
+    let mut config = Config::new()?;
+    let source = config.value_source("cscs.current_platform");
+    if !source.1
+        && !source.2
+        && config.values.current_site()?.has_platforms()
+        && let Ok(available_platforms) = get_available_compute_platforms(&config).await
+        && let Some(platform) = available_platforms.first()
+    {
+        config
+            .set("cscs.current_platform", platform.to_string(), true)
+            .wrap_err("couldn't set current platform")?;
+    }
+    Ok(())
+}
+
+pub async fn cscs_system_list(config: &Config, platform: Option<ComputePlatform>) -> Result<Vec<System>> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => api_client.list_systems().await,
         Err(e) => Err(e),
     }
 }
@@ -137,16 +164,19 @@ pub async fn cscs_system_set(system_name: String, global: bool) -> Result<()> {
 }
 
 pub async fn cscs_job_list(
+    config: &Config,
     status: Option<Vec<JobStatus>>,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<Vec<Job>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             api_client
-                .list_jobs(status, &system.unwrap_or(config.values.cscs.current_system), None)
+                .list_jobs(
+                    status,
+                    &system.unwrap_or(config.values.cscs.current_system.clone()),
+                    None,
+                )
                 .await
         }
         Err(e) => Err(e),
@@ -154,61 +184,222 @@ pub async fn cscs_job_list(
 }
 
 pub async fn cscs_job_details(
+    config: &Config,
     job_id: JobId,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<Option<JobDetail>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             api_client
-                .get_job(&system.unwrap_or(config.values.cscs.current_system), job_id)
+                .get_job(&system.unwrap_or(config.values.cscs.current_system.clone()), job_id)
                 .await
         }
         Err(e) => Err(e),
     }
 }
 
+/// Polls a job until it reaches a terminal state, polling less often the longer the status doesn't change.
+///
+/// `on_update` is called with the job details after every poll.
+pub async fn cscs_job_wait<F>(
+    config: &Config,
+    job_id: JobId,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    mut on_update: F,
+) -> Result<JobDetail>
+where
+    F: FnMut(&JobDetail) -> Result<()>,
+{
+    let mut interval = JOB_POLL_MIN_INTERVAL;
+    let mut last_status = None;
+    loop {
+        let job = cscs_job_details(config, job_id.clone(), system.clone(), platform.clone())
+            .await?
+            .ok_or(eyre!("job {} not found", job_id))?;
+        on_update(&job)?;
+        if job.status.is_terminal() {
+            return Ok(job);
+        }
+        if last_status.as_ref() == Some(&job.status) {
+            interval = interval.mul_f64(1.5).min(JOB_POLL_MAX_INTERVAL);
+        } else {
+            interval = JOB_POLL_MIN_INTERVAL;
+            last_status = Some(job.status.clone());
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
 pub async fn cscs_job_log(
+    config: &Config,
     job_id: JobId,
-    stderr: bool,
+    stream: LogStream,
+    lines: usize,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<String> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
-            let current_system = &system.unwrap_or(config.values.cscs.current_system);
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
             let job = api_client.get_job(current_system, job_id.clone()).await?;
             if job.is_none() {
                 return Err(eyre!("couldn't find job {}", job_id));
             }
-            let path = if stderr {
-                job.unwrap().stderr
-            } else {
-                job.unwrap().stdout
-            };
+            let path = job_log_path(&job.unwrap(), stream)?;
+            api_client.tail(current_system, path, lines).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn job_log_path(job: &JobDetail, stream: LogStream) -> Result<PathBuf> {
+    let path = stream.path(job);
+    if path.is_empty() {
+        return Err(eyre!("No {} log exists for job {}", stream, job.id))
+            .suggestion("It might take a while for the log to be created, try again later");
+    }
+    Ok(PathBuf::from(path))
+}
+
+/// Streams the logs of a job as they are written, starting with the last `lines` lines of each stream.
+///
+/// New output is passed to `on_output` in chunks that don't necessarily end on a line boundary.
+/// Returns once the job reached a terminal state and its logs have been read completely.
+pub async fn cscs_job_log_follow<F>(
+    config: &Config,
+    job_id: JobId,
+    streams: &[LogStream],
+    lines: usize,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    mut on_output: F,
+) -> Result<JobDetail>
+where
+    F: FnMut(LogStream, &str) -> Result<()>,
+{
+    let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+    let mut offsets: HashMap<LogStream, usize> = HashMap::new();
+    loop {
+        // get a fresh client every iteration, as following a log can outlive an access token
+        let api_client = get_api_client(config, platform.clone()).await?;
+        let job = api_client
+            .get_job(current_system, job_id.clone())
+            .await?
+            .ok_or(eyre!("couldn't find job {}", job_id))?;
+        for stream in streams {
+            let path = stream.path(&job);
             if path.is_empty() {
-                return Err(eyre!(
-                    "No {} log exists for job {}",
-                    if stderr { "stderr" } else { "stdout" },
-                    job_id
-                ))
-                .suggestion("It might take a while for the log to be created, try again later");
+                continue;
             }
-
             let path = PathBuf::from(path);
-            api_client.tail(current_system, path, 100).await
+            // the log doesn't exist until the job starts running
+            let Some(stat) = api_client.stat_path(current_system, path.clone()).await.ok().flatten() else {
+                continue;
+            };
+            let size = stat.size.max(0) as usize;
+            match offsets.get(stream).copied() {
+                None => {
+                    // stat before tailing, so nothing written in between gets lost
+                    let content = api_client.tail(current_system, path, lines).await?;
+                    on_output(*stream, &content)?;
+                }
+                Some(offset) if size > offset => {
+                    let content = read_log_range(api_client.as_ref(), current_system, path, offset, size).await?;
+                    on_output(*stream, &content)?;
+                }
+                _ => {}
+            }
+            offsets.insert(*stream, size);
         }
-        Err(e) => Err(e),
+        if job.status.is_terminal() {
+            return Ok(job);
+        }
+        tokio::time::sleep(LOG_FOLLOW_INTERVAL).await;
+    }
+}
+
+/// The part of a job log written after a given offset
+pub struct JobLogUpdate {
+    pub path: PathBuf,
+    pub content: String,
+    /// offset to continue reading from next time
+    pub offset: usize,
+}
+
+/// Reads everything written to a job log since `offset`, so the whole log can be built up incrementally.
+///
+/// Without an offset, nothing is read and the returned offset points to the current end of the log. With
+/// `max_bytes`, at most that much is read and the rest is left for the next call.
+pub async fn cscs_job_log_from(
+    config: &Config,
+    job_id: JobId,
+    stream: LogStream,
+    offset: Option<usize>,
+    max_bytes: Option<usize>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<JobLogUpdate> {
+    let api_client = get_api_client(config, platform).await?;
+    let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+    let job = api_client
+        .get_job(current_system, job_id.clone())
+        .await?
+        .ok_or(eyre!("couldn't find job {}", job_id))?;
+    let path = job_log_path(&job, stream)?;
+    let size = match api_client.stat_path(current_system, path.clone()).await.ok().flatten() {
+        Some(stat) => stat.size.max(0) as usize,
+        // the log doesn't exist until the job starts running
+        None => offset.unwrap_or(0),
+    };
+    let offset = offset.unwrap_or(size);
+    let end = max_bytes.map_or(size, |max_bytes| size.min(offset + max_bytes));
+    let content = if end > offset {
+        read_log_range(api_client.as_ref(), current_system, path.clone(), offset, end).await?
+    } else {
+        String::new()
+    };
+    Ok(JobLogUpdate {
+        path,
+        content,
+        offset: end.max(offset),
+    })
+}
+
+async fn read_log_range(
+    api_client: &dyn ComputeBackend,
+    system: &str,
+    path: PathBuf,
+    start: usize,
+    end: usize,
+) -> Result<String> {
+    let mut content = String::new();
+    let mut offset = start;
+    while offset < end {
+        let chunk = (end - offset).min(LOG_READ_CHUNK_SIZE);
+        content.push_str(&api_client.view(system, path.clone(), chunk, offset).await?);
+        offset += chunk;
     }
+    Ok(content)
 }
 
-pub async fn cscs_resource_usage(job_id: JobId, system: Option<String>) -> Result<ResourceUsage> {
-    let endpoint_id = get_endpoint_id(job_id, system).await?;
+/// Gets the resource usage of one node of a job, or the usage aggregated over all nodes if `node` is `None`
+pub async fn cscs_resource_usage(
+    config: &Config,
+    job_id: JobId,
+    node: Option<usize>,
+    system: Option<String>,
+) -> Result<ResourceUsage> {
+    let endpoint_ids = match node {
+        Some(node) => vec![get_endpoint_id(config, job_id, node, system).await?],
+        None => get_endpoint_ids(config, job_id, system).await?,
+    };
+    let usages = futures::future::try_join_all(endpoint_ids.into_iter().map(node_resource_usage)).await?;
+    Ok(ResourceUsage::aggregate(usages))
+}
 
+async fn node_resource_usage(endpoint_id: EndpointId) -> Result<ResourceUsage> {
     let alpn: Vec<u8> = COMAN_RPC_ALPN.to_vec();
     let secret_key = SecretKey::generate(&mut rand::rng());
     let endpoint = Endpoint::builder().secret_key(secret_key).bind().await?;
@@ -232,12 +423,14 @@ pub async fn cscs_resource_usage(job_id: JobId, system: Option<String>) -> Resul
 }
 
 pub async fn cscs_port_forward(
+    config: &Config,
     job_id: JobId,
+    node: usize,
     source_port: u16,
     destination_port: u16,
     system: Option<String>,
 ) -> Result<()> {
-    let endpoint_id = get_endpoint_id(job_id, system).await?;
+    let endpoint_id = get_endpoint_id(config, job_id, node, system).await?;
     let listener = TcpListener::bind(format!("127.0.0.1:{source_port}")).await?;
     println!("forwarding connection for port {source_port}");
 
@@ -247,19 +440,18 @@ pub async fn cscs_port_forward(
     }
 }
 
-async fn get_endpoint_id(job_id: JobId, system: Option<String>) -> Result<iroh::PublicKey, eyre::Error> {
-    let data_dir = get_data_dir();
-    let config = Config::new().unwrap();
-    let current_system = &system.unwrap_or(config.values.cscs.current_system);
-    let job_info = cscs_job_details(job_id.clone(), Some(current_system.clone()), None).await?;
-    if job_info.is_none() {
-        return Err(eyre!("remote job does not exist!"));
-    } else if let Some(job_info) = job_info
-        && job_info.status != JobStatus::Running
-    {
-        return Err(eyre!("remote job is not in running state, connection not available"));
+/// Path of the file storing the iroh endpoint id of a node of a job
+pub(crate) fn endpoint_file(current_system: &str, job_id: &JobId, node: usize) -> PathBuf {
+    // nodes other than 0 keep the `<system>_<job id>.` prefix so garbage collection also picks them up
+    if node == 0 {
+        get_data_dir().join(format!("{}_{}.endpoint", current_system, job_id))
+    } else {
+        get_data_dir().join(format!("{}_{}.node{}.endpoint", current_system, job_id, node))
     }
-    let endpoint_id = std::fs::read_to_string(data_dir.join(format!("{}_{}.endpoint", current_system, job_id)))?;
+}
+
+fn read_endpoint_id(path: &Path) -> Result<EndpointId> {
+    let endpoint_id = std::fs::read_to_string(path)?;
     let endpoint_id = EndpointId::from_str(if endpoint_id.len() == 64 {
         &endpoint_id
     } else if endpoint_id.len() > 64 {
@@ -270,6 +462,45 @@ async fn get_endpoint_id(job_id: JobId, system: Option<String>) -> Result<iroh::
     Ok(endpoint_id)
 }
 
+async fn ensure_job_running(config: &Config, job_id: &JobId, current_system: &str) -> Result<()> {
+    let job_info = cscs_job_details(config, job_id.clone(), Some(current_system.to_owned()), None).await?;
+    if job_info.is_none() {
+        return Err(eyre!("remote job does not exist!"));
+    } else if let Some(job_info) = job_info
+        && job_info.status != JobStatus::Running
+    {
+        return Err(eyre!("remote job is not in running state, connection not available"));
+    }
+    Ok(())
+}
+
+async fn get_endpoint_id(config: &Config, job_id: JobId, node: usize, system: Option<String>) -> Result<EndpointId> {
+    let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+    ensure_job_running(config, &job_id, current_system).await?;
+    let path = endpoint_file(current_system, &job_id, node);
+    if node > 0 && !path.exists() {
+        return Err(eyre!("job {} has no endpoint for node {}", job_id, node)).suggestion(
+            "only multi-node jobs submitted with `endpoint_mode = \"per_node\"` have an endpoint per node",
+        );
+    }
+    read_endpoint_id(&path)
+}
+
+/// Endpoint ids of all nodes of a job, ordered by node index
+async fn get_endpoint_ids(config: &Config, job_id: JobId, system: Option<String>) -> Result<Vec<EndpointId>> {
+    let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+    ensure_job_running(config, &job_id, current_system).await?;
+    let mut endpoint_ids = vec![read_endpoint_id(&endpoint_file(current_system, &job_id, 0))?];
+    for node in 1.. {
+        let path = endpoint_file(current_system, &job_id, node);
+        if !path.exists() {
+            break;
+        }
+        endpoint_ids.push(read_endpoint_id(&path)?);
+    }
+    Ok(endpoint_ids)
+}
+
 async fn process_port_forward(endpoint_id: EndpointId, destination_port: u16, mut socket: TcpStream) -> Result<()> {
     println!("accepted connection for destination port {destination_port}");
     let alpn: Vec<u8> = format!("/coman/{destination_port}").into_bytes();
@@ -306,23 +537,73 @@ async fn process_port_forward(endpoint_id: EndpointId, destination_port: u16, mu
     }
 }
 
-pub async fn cscs_job_cancel(job_id: JobId, system: Option<String>, platform: Option<ComputePlatform>) -> Result<()> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
+pub async fn cscs_job_cancel(
+    config: &Config,
+    job_id: JobId,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             api_client
-                .cancel_job(&system.unwrap_or(config.values.cscs.current_system), job_id)
+                .cancel_job(&system.unwrap_or(config.values.cscs.current_system.clone()), job_id)
                 .await
         }
         Err(e) => Err(e),
     }
 }
 
+/// Where the files of a job are written to: the cluster, or for dry runs just a list of the files that would be
+/// uploaded
+struct JobFiles<'a> {
+    api_client: &'a dyn ComputeBackend,
+    current_system: &'a str,
+    dry_run: bool,
+    written: Vec<(PathBuf, String)>,
+}
+
+impl<'a> JobFiles<'a> {
+    fn new(api_client: &'a dyn ComputeBackend, current_system: &'a str, dry_run: bool) -> Self {
+        Self {
+            api_client,
+            current_system,
+            dry_run,
+            written: vec![],
+        }
+    }
+
+    async fn mkdir(&self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.api_client.mkdir(self.current_system, path.to_path_buf()).await
+    }
+
+    /// Creates a directory only accessible by the user
+    async fn mkdir_private(&self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.mkdir(path).await?;
+        self.api_client
+            .chmod(self.current_system, path.to_path_buf(), "700")
+            .await
+    }
+
+    async fn upload(&mut self, path: PathBuf, content: String) -> Result<()> {
+        if !self.dry_run {
+            self.api_client
+                .upload(self.current_system, path.clone(), content.clone().into_bytes())
+                .await?;
+        }
+        self.written.push((path, content));
+        Ok(())
+    }
+}
+
 async fn setup_ssh(
-    api_client: &CscsApi,
+    files: &mut JobFiles<'_>,
     base_path: &Path,
-    current_system: &str,
     options: &JobStartOptions,
     config: &Config,
 ) -> Result<Option<(PathBuf, SecretKey)>> {
@@ -356,8 +637,8 @@ async fn setup_ssh(
             let remote_path = base_path.join(filename);
             let public_key = std::fs::read_to_string(path.clone())?;
 
-            api_client
-                .upload(current_system, remote_path.clone(), public_key.into_bytes())
+            files
+                .upload(remote_path.clone(), public_key)
                 .await
                 .wrap_err(eyre!("couldn't upload ssh public key"))?;
             Ok(Some((remote_path, secret)))
@@ -366,7 +647,7 @@ async fn setup_ssh(
     }
 }
 
-async fn garbage_collect_ssh(api_client: &CscsApi, current_system: &str) -> Result<()> {
+async fn garbage_collect_ssh(api_client: &dyn ComputeBackend, current_system: &str) -> Result<()> {
     let data_dir = get_data_dir();
     if !data_dir.exists() {
         return Ok(());
@@ -430,12 +711,15 @@ async fn store_ssh_information(
     job_id: &JobId,
     job_name: &str,
     secret_key: &SecretKey,
+    nodes: usize,
 ) -> Result<String> {
     let data_dir = get_data_dir();
-    std::fs::write(
-        data_dir.join(format!("{}_{}.endpoint", current_system, job_id)),
-        format!("{}", secret_key.public()),
-    )?;
+    for node in 0..nodes {
+        std::fs::write(
+            endpoint_file(current_system, job_id, node),
+            format!("{}", node_secret_key(secret_key, node).public()),
+        )?;
+    }
     let coman_ssh_config_path = data_dir.join("ssh_config");
     let coman_ssh_config = std::fs::OpenOptions::new()
         .create(true)
@@ -445,13 +729,26 @@ async fn store_ssh_information(
     let mut writer = BufWriter::new(coman_ssh_config);
     write!(
         writer,
-        "\n#Start {0}\nHost {0}\n    Hostname {1}\n    User {2}\n    ProxyCommand coman proxy {3} {4}\n#End {0}",
+        "\n#Start {0}\nHost {0}\n    Hostname {1}\n    User {2}\n    ProxyCommand coman proxy {3} {4}",
         connection_name,
         secret_key.public(),
         user_info.name,
         current_system,
         job_id
     )?;
+    for node in 1..nodes {
+        write!(
+            writer,
+            "\nHost {0}-node{5}\n    Hostname {1}\n    User {2}\n    ProxyCommand coman proxy --node {5} {3} {4}",
+            connection_name,
+            node_secret_key(secret_key, node).public(),
+            user_info.name,
+            current_system,
+            job_id,
+            node
+        )?;
+    }
+    write!(writer, "\n#End {0}", connection_name)?;
     let ssh_dir = directories::UserDirs::new()
         .ok_or(eyre!("couldn't find home dir"))?
         .home_dir()
@@ -502,11 +799,7 @@ async fn maybe_download_latest_squash(current_system: &str, config: &Config) ->
     };
     if target_path.exists() {
         // if file exists, check checksum against newest version and delete if no match
-        let mut hasher = Sha256::new();
-        let mut file = std::fs::File::open(&target_path)?;
-        let _n = std::io::copy(&mut file, &mut hasher)?;
-        let hash = hasher.finalize();
-        let hash = format!("{hash:02x}");
+        let hash = sha256_file(&target_path)?;
 
         let client = reqwest::Client::new();
         let resp = client
@@ -554,17 +847,20 @@ async fn maybe_download_latest_squash(current_system: &str, config: &Config) ->
     Ok(target_path)
 }
 async fn inject_coman_squash(
-    api_client: &CscsApi,
+    config: &Config,
+    files: &JobFiles<'_>,
     base_path: &Path,
-    current_system: &str,
     options: &JobStartOptions,
 ) -> Result<Option<PathBuf>> {
     if options.no_coman {
         return Ok(None);
     }
-    let config = Config::new().unwrap();
-    let local_squash_path = maybe_download_latest_squash(current_system, &config).await?;
     let target = base_path.join("coman.sqsh");
+    if files.dry_run {
+        return Ok(Some(target));
+    }
+    let (api_client, current_system) = (files.api_client, files.current_system);
+    let local_squash_path = maybe_download_latest_squash(current_system, config).await?;
     let file_meta = std::fs::metadata(local_squash_path.clone()).wrap_err("couldn't load coman squash file")?;
 
     #[cfg(target_family = "unix")]
@@ -573,11 +869,7 @@ async fn inject_coman_squash(
     #[cfg(target_family = "windows")]
     let size = file_meta.file_size() as usize;
 
-    let mut hasher = Sha256::new();
-    let mut file = std::fs::File::open(&local_squash_path)?;
-    let _n = std::io::copy(&mut file, &mut hasher)?;
-    let hash = hasher.finalize();
-    let hash = format!("{hash:02x}");
+    let hash = sha256_file(&local_squash_path)?;
 
     let response = api_client.checksum(current_system, target.clone()).await;
     if let Ok(Some(remote_hash)) = response {
@@ -591,70 +883,38 @@ async fn inject_coman_squash(
     }
     //upload squash file
     let transfer_data = api_client
-        .transfer_upload(current_system, config.values.cscs.account, target.clone(), size as i64)
+        .transfer_upload(
+            current_system,
+            config.values.cscs.account.clone(),
+            target.clone(),
+            size as i64,
+        )
         .await
         .wrap_err(eyre!("couldn't upload coman squash file"))?;
-    let mut etags: Vec<String> = Vec::new();
-    let client = reqwest::Client::new();
     let num_parts = transfer_data.1.num_parts;
-    for (chunk_id, transfer_url) in transfer_data.1.parts_upload_urls.into_iter().enumerate() {
-        println!(
-            "Uploading part {}/{} ({}Mb)",
-            chunk_id + 1,
-            num_parts,
-            transfer_data.1.part_size / 1024 / 1024
-        );
-        let etag = upload_chunk(
-            local_squash_path.clone(),
-            (chunk_id as u64) * transfer_data.1.part_size,
-            transfer_data.1.part_size,
-            transfer_url,
-        )
-        .await?;
-        etags.push(etag);
-    }
-
-    let body = etags
-        .into_iter()
-        .enumerate()
-        .map(|(i, etag)| (i + 1, etag))
-        .map(|(i, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", i, etag))
-        .join("");
-    let body = format!(
-        "<CompleteMultipartUpload xmlns=\"http://s3.amazonaws.com/doc/2006-03-01\">{}</CompleteMultipartUpload>",
-        body
-    );
-    let req = client.post(transfer_data.1.complete_upload_url).body(body).build()?;
-    let resp = client.execute(req).await?;
-    resp.error_for_status()?;
-    // wait for transfer job to finish
-    loop {
-        match cscs_job_details(transfer_data.0.clone(), Some(current_system.to_string()), None).await? {
-            Some(JobDetail {
-                status: JobStatus::Finished,
-                ..
-            }) => break,
-            Some(JobDetail {
-                status: JobStatus::Cancelled | JobStatus::Failed | JobStatus::Timeout,
-                ..
-            }) => {
-                return Err(eyre!(
-                    "Uploading coman sqsh failed, check job {} for more details",
-                    transfer_data.0
-                ));
-            }
-            Some(_) | None => {}
-        }
-        tokio::time::sleep(Duration::from_millis(500)).await;
-    }
+    multipart_upload(
+        &local_squash_path,
+        size as u64,
+        &transfer_data.1,
+        config.values.cscs.transfer_parallelism(),
+        BTreeMap::new(),
+        |part, _, _| {
+            println!("Uploaded part {}/{}", part + 1, num_parts);
+            Ok(())
+        },
+    )
+    .await?;
+    wait_for_transfer_job(api_client, current_system, &transfer_data.0)
+        .await
+        .wrap_err("uploading coman squash file failed")?;
     Ok(Some(target))
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn handle_edf(
-    api_client: &CscsApi,
+    config: &Config,
+    files: &mut JobFiles<'_>,
     base_path: &Path,
-    current_system: &str,
     envvars: &HashMap<String, String>,
     coman_squash: &Option<PathBuf>,
     ssh_public_key_path: &Option<PathBuf>,
@@ -662,16 +922,18 @@ async fn handle_edf(
     workdir: &str,
     options: &JobStartOptions,
     image_meta: &Option<DockerImageMeta>,
-) -> Result<PathBuf> {
-    let config = Config::new().unwrap();
+) -> Result<(PathBuf, Option<String>)> {
     let environment_path = base_path.join("environment.toml");
 
     let environment_template = match options.edf_spec.clone() {
-        EdfSpec::Generate => config.values.cscs.edf_file_template,
+        EdfSpec::Generate => config.values.cscs.edf_file_template.clone(),
         EdfSpec::Local(local_path) => std::fs::read_to_string(local_path.clone())?,
-        EdfSpec::Remote(path) => return Ok(path),
+        EdfSpec::Remote(path) => return Ok((path, None)),
     };
 
+    for key in envvars.keys() {
+        check_variable_name(key).wrap_err("invalid environment variable")?;
+    }
     let mut tera = tera::Tera::default();
 
     tera.add_raw_template("environment.toml", &environment_template)?;
@@ -682,7 +944,7 @@ async fn handle_edf(
 
     // check and validate image if set
     if let Some(meta) = image_meta {
-        if let Some(system_info) = config.values.cscs.systems.get(current_system)
+        if let Some(system_info) = config.values.cscs.systems.get(files.current_system)
             && !meta
                 .platforms
                 .iter()
@@ -690,7 +952,7 @@ async fn handle_edf(
         {
             return Err(eyre!(
                 "System {} only supports images with architecture(s) '{}' but the supplied image is for architecture(s) '{}'",
-                current_system,
+                files.current_system,
                 system_info.architecture.join(","),
                 meta.platforms
                     .iter()
@@ -700,11 +962,11 @@ async fn handle_edf(
             ));
         }
     } else {
-        println!("warn: no docker image metadata found, skipping validation");
+        eprintln!("Warning: no docker image metadata found, skipping validation");
     }
     if let Some(image) = options.image.clone() {
         context.insert("edf_image", &image.to_edf());
-    } else if let Some(image) = config.values.cscs.image {
+    } else if let Some(image) = config.values.cscs.image.clone() {
         let image: DockerImageUrl = image.try_into()?;
         context.insert("edf_image", &image.to_edf());
     }
@@ -721,60 +983,65 @@ async fn handle_edf(
     context.insert("mount", &mount);
     context.insert("ssh_public_key", &ssh_public_key_path);
     context.insert("coman_squash", &coman_squash);
-    if let Some(iroh_secret) = iroh_secret {
-        // set iroh secret key
-        let encoded_secret = BASE64_STANDARD.encode(iroh_secret.to_bytes());
-        context.insert("iroh_secret", &encoded_secret);
+    context.insert("endpoint_mode", &config.values.cscs.endpoint_mode.to_string());
+    // set iroh secret key
+    let encoded_secret = iroh_secret.as_ref().map(|s| BASE64_STANDARD.encode(s.to_bytes()));
+    if let Some(encoded_secret) = &encoded_secret {
+        context.insert("iroh_secret", encoded_secret);
     }
 
     let environment_file = tera.render("environment.toml", &context)?;
-    api_client.mkdir(current_system, base_path.to_path_buf()).await?;
-    api_client.chmod(current_system, base_path.to_path_buf(), "700").await?;
-    api_client
-        .upload(current_system, environment_path.clone(), environment_file.into_bytes())
-        .await?;
-    Ok(environment_path)
+    // the secret gives access to the job, so it isn't kept in the local job history
+    let recorded_file = match &encoded_secret {
+        Some(secret) => environment_file.replace(secret, "<redacted>"),
+        None => environment_file.clone(),
+    };
+    check_edf(&recorded_file, &environment_path.display().to_string())
+        .suggestion("check `cscs.edf_file_template` in your config or the file passed with `--local-edf`")?;
+    files.mkdir_private(base_path).await?;
+    // nothing is started in a dry run, so the secret is of no use and is hidden like in the history
+    let environment_file = if files.dry_run {
+        recorded_file.clone()
+    } else {
+        environment_file
+    };
+    files.upload(environment_path.clone(), environment_file).await?;
+    Ok((environment_path, Some(recorded_file)))
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn handle_script(
-    api_client: &CscsApi,
+    config: &Config,
+    files: &mut JobFiles<'_>,
     job_name: &str,
     base_path: &Path,
-    current_system: &str,
     environment_path: &Path,
     coman_squash: Option<PathBuf>,
     workdir: &str,
     options: &JobStartOptions,
     image_meta: &Option<DockerImageMeta>,
-) -> Result<PathBuf> {
-    let config = Config::new().unwrap();
+) -> Result<(PathBuf, Option<String>)> {
     let script_path = base_path.join("script.sh");
     let script_template = match options.script_spec.clone() {
-        ScriptSpec::Generate => config.values.cscs.sbatch_script_template,
+        ScriptSpec::Generate => config.values.cscs.sbatch_script_template.clone(),
         ScriptSpec::Local(local_path) => std::fs::read_to_string(local_path)?,
-        ScriptSpec::Remote(script_path) => return Ok(script_path),
+        ScriptSpec::Remote(_) if !options.sweep.is_empty() => {
+            return Err(eyre!("sweeps need a script template, they can't use a remote script"));
+        }
+        ScriptSpec::Remote(_) if !options.dependencies.is_empty() => {
+            return Err(eyre!(
+                "job dependencies need a script template, they can't be added to a remote script"
+            ));
+        }
+        ScriptSpec::Remote(script_path) => return Ok((script_path, None)),
     };
 
     let mut tera = tera::Tera::default();
+    tera.register_filter("shell_quote", shell_quote_filter);
     tera.add_raw_template("script.sh", &script_template)?;
     let mut context = tera::Context::new();
     context.insert("name", &job_name);
-    let command = match &options.command {
-        Some(cmd) => cmd.clone(),
-        None => {
-            if !config.values.cscs.command.is_empty() {
-                config.values.cscs.command.clone()
-            } else {
-                // use default entrypoint
-                if let Some(meta) = image_meta {
-                    meta.clone().entrypoint.unwrap_or_default()
-                } else {
-                    vec![]
-                }
-            }
-        }
-    };
+    let command = job_command(options, config, image_meta);
     context.insert("command", &command.join(" "));
     context.insert("environment_file", &environment_path.to_path_buf());
     context.insert("container_workdir", &workdir);
@@ -785,169 +1052,462 @@ async fn handle_script(
     } else {
         context.insert("coman_init", &false);
     }
-    let script = tera.render("script.sh", &context)?;
-    api_client
-        .upload(current_system, script_path.clone(), script.into_bytes())
-        .await?;
+    context.insert("partition", &options.slurm.partition);
+    context.insert("reservation", &options.slurm.reservation);
+    context.insert("constraints", &options.slurm.constraints);
+    context.insert("nodes", &options.slurm.nodes);
+    context.insert("ntasks", &options.slurm.ntasks);
+    context.insert("gpus_per_node", &options.slurm.gpus_per_node);
+    context.insert("walltime", &options.slurm.walltime);
+    let dependency = JobDependency::to_slurm(&options.dependencies);
+    context.insert("dependency", &dependency);
+    context.insert("sweep", &BTreeMap::<String, String>::new());
+    if options.sweep.is_empty() {
+        let script = tera.render("script.sh", &context)?;
+        check_script_dependency(&script, &dependency)?;
+        files.upload(script_path.clone(), script.clone()).await?;
+        return Ok((script_path, Some(script)));
+    }
+
+    // render one script per combination and submit them as a job array that runs the script of the current task
+    let combinations = sweep_combinations(&options.sweep)?;
+    let sweep_dir = base_path.join("sweep");
+    files.mkdir(&sweep_dir).await?;
+    let mut directives = vec![];
+    for (index, parameters) in combinations.iter().enumerate() {
+        let parameters: BTreeMap<_, _> = parameters.iter().cloned().collect();
+        context.insert("sweep", &parameters);
+        context.insert("sweep_index", &index);
+        let script = tera.render("script.sh", &context)?;
+        if index == 0 {
+            check_script_dependency(&script, &dependency)?;
+            directives = script
+                .lines()
+                .filter(|l| l.starts_with("#SBATCH"))
+                .map(array_log_directive)
+                .collect();
+        }
+        files.upload(sweep_dir.join(format!("{index}.sh")), script).await?;
+    }
+    let array_script = format!(
+        "#!/bin/bash\n{}\n#SBATCH --array=0-{}\nexec bash {}/\"${{SLURM_ARRAY_TASK_ID}}.sh\"\n",
+        directives.join("\n"),
+        combinations.len() - 1,
+        shell_quote(&sweep_dir.to_string_lossy())
+    );
+    files.upload(script_path.clone(), array_script.clone()).await?;
 
-    Ok(script_path)
+    Ok((script_path, Some(array_script)))
 }
 
-pub async fn cscs_job_start(
-    name: Option<String>,
-    options: JobStartOptions,
+/// Gives every task of a job array its own log file in an `#SBATCH` output or error directive
+fn array_log_directive(directive: &str) -> String {
+    for flag in ["--output=", "--error=", "--output ", "--error ", "-o ", "-e "] {
+        if let Some(path) = directive.strip_prefix("#SBATCH ").and_then(|d| d.strip_prefix(flag)) {
+            return format!("#SBATCH {flag}{}", array_log_path(path.trim()));
+        }
+    }
+    directive.to_owned()
+}
+
+/// Adds the array job and task id to a log file name, e.g. `out.log` becomes `out_%A_%a.log`, unless it already
+/// differs per task
+fn array_log_path(path: &str) -> String {
+    if path.contains("%a") || path.contains("%j") {
+        return path.to_owned();
+    }
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{dir}/"), name),
+        None => (String::new(), path),
+    };
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{dir}{stem}_%A_%a.{extension}"),
+        _ => format!("{dir}{name}_%A_%a"),
+    }
+}
+
+/// FirecREST can't submit job dependencies, so they have to end up in the `#SBATCH` directives of the script
+fn check_script_dependency(script: &str, dependency: &Option<String>) -> Result<()> {
+    let Some(dependency) = dependency else {
+        return Ok(());
+    };
+    let directive = format!("--dependency={dependency}");
+    if script
+        .lines()
+        .any(|l| l.starts_with("#SBATCH") && l.contains(&directive))
+    {
+        return Ok(());
+    }
+    Err(eyre!("the job script doesn't pass the job dependencies to slurm"))
+        .suggestion("add `#SBATCH --dependency={{dependency}}` to your script template")
+}
+
+/// The scratch file system of a system, where job files are put
+async fn scratch_path(api_client: &dyn ComputeBackend, current_system: &str) -> Result<PathBuf> {
+    match api_client.get_system(current_system).await? {
+        Some(system) => Ok(PathBuf::from(
+            system
+                .file_systems
+                .iter()
+                .find(|fs| fs.data_type == FileSystemType::Scratch)
+                .ok_or(eyre!("couldn't find scratch space for system"))?
+                .path
+                .clone(),
+        )),
+        None => Err(eyre!("couldn't get system description for {}", current_system)),
+    }
+}
+
+/// The script and environment file that the last job with this name was started with, as left on the remote
+pub async fn cscs_job_files(
+    config: &Config,
+    job_name: &str,
     system: Option<String>,
     platform: Option<ComputePlatform>,
-    account: Option<String>,
-) -> Result<()> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new()?;
+) -> Result<(PathBuf, PathBuf)> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
-            let account = account.or(config.values.cscs.account.clone());
             let user_info = api_client.get_userinfo(current_system).await?;
-            let job_name = name
-                .or(config.values.name.clone())
-                .unwrap_or(format!("{}-coman", user_info.name));
-            let current_system_info = api_client.get_system(current_system).await?;
-            let scratch = match current_system_info {
-                Some(system) => PathBuf::from(
-                    system
-                        .file_systems
-                        .iter()
-                        .find(|fs| fs.data_type == FileSystemType::Scratch)
-                        .ok_or(eyre!("couldn't find scratch space for system"))?
-                        .path
-                        .clone(),
-                ),
-                None => {
-                    return Err(eyre!("couldn't get system description for {}", current_system));
+            let base_path = scratch_path(api_client.as_ref(), current_system)
+                .await?
+                .join(user_info.name)
+                .join(job_name);
+            let script_path = base_path.join("script.sh");
+            let environment_path = base_path.join("environment.toml");
+            for path in [&script_path, &environment_path] {
+                if !matches!(api_client.stat_path(current_system, path.clone()).await, Ok(Some(_))) {
+                    return Err(eyre!("couldn't find {} of job {}", path.display(), job_name))
+                        .suggestion("the files are only kept if the job was submitted with coman");
                 }
-            };
-            let container_workdir = options
-                .container_workdir
-                .clone()
-                .unwrap_or(config.values.cscs.workdir.clone().unwrap_or("/scratch".to_owned()));
-            let base_path = scratch.join(user_info.name.clone()).join(&job_name);
-            api_client.mkdir(current_system, base_path.to_path_buf()).await?;
-            api_client.chmod(current_system, base_path.to_path_buf(), "700").await?;
-
-            let mut envvars = config.values.cscs.env.clone();
-            envvars.extend(options.env.clone());
-
-            let (ssh_public_key_path, secret_key) =
-                setup_ssh(&api_client, &base_path, current_system, &options, &config)
-                    .await?
-                    .unzip();
-            if ssh_public_key_path.is_none() {
-                println!(
-                    "Warning: No ssh key found, specify it with --ssh-key if you want to use ssh connections through coman"
-                );
-            }
-            let coman_squash = inject_coman_squash(&api_client, &base_path, current_system, &options).await?;
-            if coman_squash.is_none() {
-                println!("Warning: coman squash wasn't templated and is needed for ssh through coman to work");
             }
-            // check and validate image if set
-            let docker_image = if let Some(image) = options.image.clone() {
-                Some(image)
-            } else if let Some(image) = config.values.cscs.image {
-                let image = image.try_into()?;
-                Some(image)
-            } else {
-                println!(
-                    "Warning: No docker image specified (-i), functionality like SSH and port forwarding only works when running a docker image"
-                );
-                None
-            };
-            let image_meta = if let Some(docker_image) = docker_image {
-                if let Some(system_info) = config.values.cscs.systems.get(current_system) {
-                    let mut meta = None;
-                    for arch in system_info.architecture.iter() {
-                        if let Ok(img_meta) = docker_image.inspect(arch).await {
-                            meta = Some(img_meta);
-                            break;
-                        }
-                    }
-                    if meta.is_none() {
-                        println!("couldn't get image information, skipping checks");
-                    }
-                    meta
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
+            Ok((script_path, environment_path))
+        }
+        Err(e) => Err(e),
+    }
+}
 
-            let environment_path = handle_edf(
-                &api_client,
-                &base_path,
-                current_system,
-                &envvars,
-                &coman_squash,
-                &ssh_public_key_path,
-                &secret_key,
-                &container_workdir,
-                &options,
-                &image_meta,
-            )
-            .await?;
+/// The command a job runs, from the options, the config or the entrypoint of the image
+fn job_command(options: &JobStartOptions, config: &Config, image_meta: &Option<DockerImageMeta>) -> Vec<String> {
+    match &options.command {
+        Some(cmd) => cmd.clone(),
+        None => {
+            if !config.values.cscs.command.is_empty() {
+                config.values.cscs.command.clone()
+            } else {
+                // use default entrypoint
+                if let Some(meta) = image_meta {
+                    meta.clone().entrypoint.unwrap_or_default()
+                } else {
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
+/// A job with all its files written, ready to be started
+struct PreparedJob {
+    submission: JobSubmission,
+    /// remote files of the job and their content
+    files: Vec<(PathBuf, String)>,
+    options: JobStartOptions,
+    envvars: HashMap<String, String>,
+    user_info: UserInfo,
+    secret_key: Option<SecretKey>,
+    endpoint_nodes: usize,
+}
+
+/// Resolves all settings of a job and renders and uploads its files. In a dry run, nothing is created on the cluster.
+async fn prepare_job(
+    api_client: &dyn ComputeBackend,
+    config: &Config,
+    current_system: &str,
+    name: Option<String>,
+    mut options: JobStartOptions,
+    account: Option<String>,
+    dry_run: bool,
+) -> Result<PreparedJob> {
+    let mut files = JobFiles::new(api_client, current_system, dry_run);
+    let account = account.or(config.values.cscs.account.clone());
+    options.slurm = options.slurm.or((&config.values.cscs).into());
+    if !options.sweep.is_empty() && !options.no_ssh {
+        // all tasks of the job array would share the same iroh endpoint
+        eprintln!("Warning: ssh and port forwarding aren't available for sweeps");
+        options.no_ssh = true;
+    }
+    if !options.sweep.is_empty() {
+        // otherwise all tasks of the job array write to the same log files
+        let array_log = |path: PathBuf| PathBuf::from(array_log_path(&path.to_string_lossy()));
+        options.stdout = options.stdout.map(array_log);
+        options.stderr = options.stderr.map(array_log);
+    }
+    let user_info = api_client.get_userinfo(current_system).await?;
+    let job_name = name
+        .or(config.values.name.clone())
+        .unwrap_or(format!("{}-coman", user_info.name));
+    let scratch = scratch_path(api_client, current_system).await?;
+    let container_workdir = options
+        .container_workdir
+        .clone()
+        .unwrap_or(config.values.cscs.workdir.clone().unwrap_or("/scratch".to_owned()));
+    let base_path = scratch.join(user_info.name.clone()).join(&job_name);
+    files.mkdir_private(&base_path).await?;
+
+    let mut envvars = config.values.cscs.env.clone();
+    envvars.extend(options.env.clone());
+    let mut mounts: Vec<_> = config.values.cscs.mounts.clone().into_iter().collect();
+    mounts.append(&mut options.mount);
+    options.mount = mounts;
+
+    let (ssh_public_key_path, secret_key) = setup_ssh(&mut files, &base_path, &options, config).await?.unzip();
+    if ssh_public_key_path.is_none() {
+        eprintln!(
+            "Warning: No ssh key found, specify it with --ssh-key if you want to use ssh connections through coman"
+        );
+    }
+    let coman_squash = inject_coman_squash(config, &files, &base_path, &options).await?;
+    if coman_squash.is_none() {
+        eprintln!("Warning: coman squash wasn't templated and is needed for ssh through coman to work");
+    }
+
+    // check and validate image if set
+    let docker_image = if let Some(image) = options.image.clone() {
+        Some(image)
+    } else if let Some(image) = config.values.cscs.image.clone() {
+        let image = image.try_into()?;
+        Some(image)
+    } else {
+        eprintln!(
+            "Warning: No docker image specified (-i), functionality like SSH and port forwarding only works when running a docker image"
+        );
+        None
+    };
+    let image_meta = if let Some(docker_image) = &docker_image {
+        if let Some(system_info) = config.values.cscs.systems.get(current_system) {
+            let mut meta = None;
+            for arch in system_info.architecture.iter() {
+                if let Ok(img_meta) = docker_image.inspect(arch).await {
+                    meta = Some(img_meta);
+                    break;
+                }
+            }
+            if meta.is_none() {
+                eprintln!("couldn't get image information, skipping checks");
+            }
+            meta
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let (environment_path, environment) = handle_edf(
+        config,
+        &mut files,
+        &base_path,
+        &envvars,
+        &coman_squash,
+        &ssh_public_key_path,
+        &secret_key,
+        &container_workdir,
+        &options,
+        &image_meta,
+    )
+    .await?;
+
+    let (script_path, script) = handle_script(
+        config,
+        &mut files,
+        &job_name,
+        &base_path,
+        &environment_path,
+        coman_squash,
+        &container_workdir,
+        &options,
+        &image_meta,
+    )
+    .await?;
+
+    let endpoint_nodes = match config.values.cscs.endpoint_mode {
+        EndpointMode::PerNode => options.slurm.nodes.unwrap_or(1).max(1) as usize,
+        EndpointMode::RankZero => 1,
+    };
+    let local_dir = std::env::current_dir().ok();
+    let git_state = local_dir.as_deref().and_then(git_state);
+    let mut mounts: BTreeMap<String, String> = options.mount.iter().cloned().collect();
+    mounts.entry("${SCRATCH}".to_owned()).or_insert("/scratch".to_owned());
+    let submission = JobSubmission {
+        job_id: String::new(),
+        name: job_name.clone(),
+        system: current_system.to_owned(),
+        site: active_site(),
+        account,
+        submitted: chrono::Local::now(),
+        image: docker_image.as_ref().map(|i| i.to_string()),
+        command: job_command(&options, config, &image_meta),
+        workdir: container_workdir.clone(),
+        env: envvars.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        mounts,
+        port_forward: options.port_forward.clone(),
+        slurm: options.slurm.clone(),
+        dependency: JobDependency::to_slurm(&options.dependencies),
+        sweep: options.sweep.clone(),
+        stdout: options.stdout.clone(),
+        stderr: options.stderr.clone(),
+        script_spec: options.script_spec.clone(),
+        edf_spec: options.edf_spec.clone(),
+        local_dir,
+        git_commit: git_state.as_ref().map(|(commit, _)| commit.clone()),
+        git_dirty: git_state.is_some_and(|(_, dirty)| dirty),
+        remote_dir: base_path.clone(),
+        script_path,
+        environment_path,
+        script,
+        environment,
+    };
+    Ok(PreparedJob {
+        submission,
+        files: files.written,
+        options,
+        envvars,
+        user_info,
+        secret_key,
+        endpoint_nodes,
+    })
+}
+
+/// Renders all files of a job and resolves its settings without creating anything on the cluster
+pub async fn cscs_job_render(
+    config: &Config,
+    name: Option<String>,
+    options: JobStartOptions,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    account: Option<String>,
+) -> Result<(JobSubmission, Vec<(PathBuf, String)>)> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            render_job(api_client.as_ref(), config, current_system, name, options, account).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) async fn render_job(
+    api_client: &dyn ComputeBackend,
+    config: &Config,
+    current_system: &str,
+    name: Option<String>,
+    options: JobStartOptions,
+    account: Option<String>,
+) -> Result<(JobSubmission, Vec<(PathBuf, String)>)> {
+    let prepared = prepare_job(api_client, config, current_system, name, options, account, true).await?;
+    Ok((prepared.submission, prepared.files))
+}
 
-            let script_path = handle_script(
-                &api_client,
-                &job_name,
-                &base_path,
+pub async fn cscs_job_start(
+    config: &Config,
+    name: Option<String>,
+    options: JobStartOptions,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    account: Option<String>,
+) -> Result<JobId> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let PreparedJob {
+                mut submission,
+                options,
+                envvars,
+                user_info,
+                secret_key,
+                endpoint_nodes,
+                ..
+            } = prepare_job(
+                api_client.as_ref(),
+                config,
                 current_system,
-                &environment_path,
-                coman_squash,
-                &container_workdir,
-                &options,
-                &image_meta,
+                name,
+                options,
+                account,
+                false,
             )
             .await?;
-
+            let job_name = submission.name.clone();
             // start job
             let job_id = api_client
-                .start_job(current_system, account, &job_name, script_path, envvars, options)
+                .start_job(
+                    current_system,
+                    submission.account.clone(),
+                    &job_name,
+                    submission.script_path.clone(),
+                    envvars,
+                    options,
+                )
                 .await?
                 .ok_or(eyre!("didn't get job id for created job"))?;
 
+            submission.job_id = job_id.to_string();
+            if let Err(e) = record_submission(&submission) {
+                // the job is running already, so don't fail because of the history
+                eprintln!("Warning: couldn't record job in history: {e:?}");
+            }
+
             if let Some(secret_key) = secret_key {
                 // store connection information in data dir and set up ssh connection
-                garbage_collect_ssh(&api_client, current_system).await?;
-                let connection_name =
-                    store_ssh_information(current_system, &user_info, &job_id, &job_name, &secret_key).await?;
+                garbage_collect_ssh(api_client.as_ref(), current_system).await?;
+                let connection_name = store_ssh_information(
+                    current_system,
+                    &user_info,
+                    &job_id,
+                    &job_name,
+                    &secret_key,
+                    endpoint_nodes,
+                )
+                .await?;
                 println!("Use ssh {}@{} to connect to the job", user_info.name, connection_name);
+                if endpoint_nodes > 1 {
+                    println!(
+                        "Use ssh {}@{}-node<N> to connect to node N (1 to {})",
+                        user_info.name,
+                        connection_name,
+                        endpoint_nodes - 1
+                    );
+                }
             }
 
-            Ok(())
+            Ok(job_id)
         }
         Err(e) => Err(e),
     }
 }
 
 pub async fn cscs_file_list(
+    config: &Config,
     path: PathBuf,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<Vec<PathEntry>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             api_client
-                .list_path(&system.unwrap_or(config.values.cscs.current_system), path, false)
+                .list_path(
+                    &system.unwrap_or(config.values.cscs.current_system.clone()),
+                    path,
+                    false,
+                )
                 .await
         }
         Err(e) => Err(e),
     }
 }
-pub async fn file_system_roots(type_filter: Option<FileSystemType>) -> Result<Vec<PathEntry>> {
-    let config = Config::new().expect("couldn't load config");
-    let user_info = cscs_user_info(None, None).await?;
-    let systems = cscs_system_list(None).await?;
+pub async fn file_system_roots(config: &Config, type_filter: Option<FileSystemType>) -> Result<Vec<PathEntry>> {
+    let user_info = cscs_user_info(config, None, None).await?;
+    let systems = cscs_system_list(config, None).await?;
     let system = systems
         .iter()
         .find(|s| s.name == config.values.cscs.current_system)
@@ -964,7 +1524,13 @@ pub async fn file_system_roots(type_filter: Option<FileSystemType>) -> Result<Ve
         system.file_systems.clone()
     };
     for fs in filesystems {
-        let entry = match cscs_stat_path(PathBuf::from(fs.path.clone()).join(user_info.name.clone()), None, None).await
+        let entry = match cscs_stat_path(
+            config,
+            PathBuf::from(fs.path.clone()).join(user_info.name.clone()),
+            None,
+            None,
+        )
+        .await
         {
             Ok(Some(_)) => PathEntry {
                 name: format!("{}/{}", fs.path.clone(), user_info.name),
@@ -985,28 +1551,239 @@ pub async fn file_system_roots(type_filter: Option<FileSystemType>) -> Result<Ve
 }
 
 pub async fn cscs_file_delete(
+    config: &Config,
     remote: PathBuf,
+    recursive: bool,
+    account: Option<String>,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<()> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
-            let current_system = &system.unwrap_or(config.values.cscs.current_system);
-            let paths = api_client.list_path(current_system, remote.clone(), false).await?;
-            let path = paths.first().ok_or(eyre!("remote path doesn't exist"))?;
-            if let PathType::Directory = path.path_type {
-                return Err(eyre!("remote path must be a file, not directory"));
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            remove_remote_path(api_client.as_ref(), current_system, account, remote, recursive).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn remove_remote_path(
+    api_client: &dyn ComputeBackend,
+    current_system: &str,
+    account: Option<String>,
+    remote: PathBuf,
+    recursive: bool,
+) -> Result<()> {
+    match remote_path_type(api_client, current_system, &remote).await? {
+        Some(PathType::Directory) if recursive => {
+            // removing a large directory can exceed the time limit of direct operations, so use a transfer job
+            let job_id = api_client.transfer_rm(current_system, account, remote).await?;
+            wait_for_transfer_job(api_client, current_system, &job_id).await
+        }
+        Some(PathType::Directory) => {
+            Err(eyre!("remote path must be a file, not directory")).suggestion("use --recursive to remove directories")
+        }
+        Some(_) => api_client.rm_path(current_system, remote).await,
+        None => Err(eyre!("remote path doesn't exist")),
+    }
+}
+
+pub async fn cscs_file_head(
+    config: &Config,
+    path: PathBuf,
+    lines: Option<usize>,
+    bytes: Option<usize>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<String> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            api_client.head(current_system, path, lines, bytes).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_view(
+    config: &Config,
+    path: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<String> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            // view is limited in size, so read the file in chunks until we get a partial one
+            let mut content = String::new();
+            loop {
+                let chunk = api_client
+                    .view(current_system, path.clone(), CSCS_MAX_DIRECT_SIZE, content.len())
+                    .await?;
+                let done = chunk.len() < CSCS_MAX_DIRECT_SIZE;
+                content.push_str(&chunk);
+                if done {
+                    break;
+                }
             }
-            api_client.rm_path(current_system, remote).await?;
-            Ok(())
+            Ok(content)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_type(
+    config: &Config,
+    path: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<Option<String>> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            api_client.file_type(current_system, path).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_copy(
+    config: &Config,
+    source: PathBuf,
+    target: PathBuf,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            let job_id = api_client
+                .transfer_copy(current_system, account, source, target)
+                .await?;
+            wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_move(
+    config: &Config,
+    source: PathBuf,
+    target: PathBuf,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            let job_id = api_client
+                .transfer_move(current_system, account, source, target)
+                .await?;
+            wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_link(
+    config: &Config,
+    target: PathBuf,
+    link: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            api_client.symlink(current_system, target, link).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_chown(
+    config: &Config,
+    path: PathBuf,
+    owner: Option<String>,
+    group: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            api_client.chown(current_system, path, owner, group).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates a tar archive of a remote path, the compression is derived from the archive extension
+///
+/// With `direct`, the archive is created by the api itself instead of a transfer job, which is faster for small
+/// paths but subject to the time limit of api requests
+pub async fn cscs_file_compress(
+    config: &Config,
+    source: PathBuf,
+    archive: PathBuf,
+    direct: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            let compression = ArchiveCompression::from_path(&archive);
+            if direct {
+                return api_client.compress(current_system, source, archive, compression).await;
+            }
+            let job_id = api_client
+                .transfer_compress(current_system, account, source, archive, compression)
+                .await?;
+            wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Extracts a remote tar archive into a directory, the compression is derived from the archive extension
+///
+/// With `direct`, the archive is extracted by the api itself instead of a transfer job, see [`cscs_file_compress`]
+pub async fn cscs_file_extract(
+    config: &Config,
+    archive: PathBuf,
+    target: PathBuf,
+    direct: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            let compression = ArchiveCompression::from_path(&archive);
+            if direct {
+                return api_client.extract(current_system, archive, target, compression).await;
+            }
+            let job_id = api_client
+                .transfer_extract(current_system, account, archive, target, compression)
+                .await?;
+            wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await
         }
         Err(e) => Err(e),
     }
 }
 
 pub async fn cscs_file_download(
+    config: &Config,
     remote: PathBuf,
     local: PathBuf,
     account: Option<String>,
@@ -1018,11 +1795,9 @@ pub async fn cscs_file_download(
     } else {
         local
     };
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
-            let current_system = &system.unwrap_or(config.values.cscs.current_system);
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
             let paths = api_client.list_path(current_system, remote.clone(), false).await?;
             let path = paths.first().ok_or(eyre!("remote path doesn't exist"))?;
             if let PathType::Directory = path.path_type {
@@ -1036,7 +1811,7 @@ pub async fn cscs_file_download(
                 Ok(None)
             } else {
                 // download via s3
-                let account = account.or(config.values.cscs.account);
+                let account = account.or(config.values.cscs.account.clone());
                 let job_data = api_client.transfer_download(current_system, account, remote).await?;
                 Ok(Some((job_data.0, job_data.1, size)))
             }
@@ -1044,18 +1819,19 @@ pub async fn cscs_file_download(
         Err(e) => Err(e),
     }
 }
+/// Uploads a local file, returns the state of the multipart upload for large files that need to go through s3
 pub async fn cscs_file_upload(
+    config: &Config,
     local: PathBuf,
     remote: PathBuf,
     account: Option<String>,
     system: Option<String>,
     platform: Option<ComputePlatform>,
-) -> Result<Option<(JobId, S3Upload)>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
-            let current_system = &system.unwrap_or(config.values.cscs.current_system);
+) -> Result<Option<UploadState>> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let requested_remote = remote.clone();
             let existing = api_client.list_path(current_system, remote.clone(), false).await?;
             let remote = if !existing.is_empty() {
                 if existing.len() == 1 && existing[0].path_type == PathType::File {
@@ -1081,44 +1857,909 @@ pub async fn cscs_file_upload(
                 Ok(None)
             } else {
                 // upload via s3
-                let account = account.or(config.values.cscs.account);
-                let transfer_data = api_client
-                    .transfer_upload(current_system, account, remote, size as i64)
+                let account = account.or(config.values.cscs.account.clone());
+                let (job_id, transfer_data) = api_client
+                    .transfer_upload(current_system, account, remote.clone(), size as i64)
                     .await?;
+                let state = UploadState::new(
+                    current_system,
+                    &local,
+                    &requested_remote,
+                    remote,
+                    job_id,
+                    &transfer_data,
+                )?;
+                state.save()?;
+                Ok(Some(state))
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Compares the sha256 of a downloaded file with the checksum of the remote file
+pub async fn cscs_file_verify(
+    config: &Config,
+    remote: PathBuf,
+    local: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            verify_download(api_client.as_ref(), current_system, &remote, &local).await
+        }
+        Err(e) => Err(e),
+    }
+}
 
-                Ok(Some(transfer_data))
+/// Compares the sha256 of a downloaded file with the checksum of the remote file it was downloaded from
+async fn verify_download(
+    api_client: &dyn ComputeBackend,
+    current_system: &str,
+    remote: &Path,
+    local: &Path,
+) -> Result<()> {
+    let remote_hash = api_client
+        .checksum(current_system, remote.to_path_buf())
+        .await?
+        .ok_or(eyre!("couldn't get checksum of {}", remote.display()))?;
+    let local = local.to_path_buf();
+    tokio::task::spawn_blocking(move || check_checksum(&local, &remote_hash)).await?
+}
+
+/// Fails if the sha256 of a local file doesn't match `expected`
+pub(crate) fn check_checksum(local: &Path, expected: &str) -> Result<()> {
+    let local_hash = sha256_file(local)?;
+    if local_hash != expected {
+        return Err(eyre!(
+            "checksum mismatch for {}: local {} but remote {}",
+            local.display(),
+            local_hash,
+            expected
+        ))
+        .suggestion("download the file again");
+    }
+    Ok(())
+}
+
+/// Loads the state of an interrupted upload so it can be continued
+pub async fn cscs_file_upload_resume(
+    config: &Config,
+    local: PathBuf,
+    remote: PathBuf,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<UploadState> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let state = UploadState::load(current_system, &local, &remote)?
+                .ok_or(eyre!("no interrupted upload found for {}", local.display()))
+                .suggestion("start the upload without --resume")?;
+            if !state.local_unchanged()? {
+                state.remove()?;
+                return Err(eyre!("local file changed since the upload was started"))
+                    .suggestion("start the upload again without --resume");
+            }
+            // the s3 upload urls are only valid as long as the transfer job waits for the upload
+            match api_client
+                .get_job(current_system, JobId::new(state.job_id.clone()))
+                .await?
+            {
+                Some(JobDetail {
+                    status: JobStatus::Pending | JobStatus::Running | JobStatus::Requeued,
+                    ..
+                }) => Ok(state),
+                _ => {
+                    state.remove()?;
+                    Err(eyre!(
+                        "transfer job {} isn't waiting for the upload anymore",
+                        state.job_id
+                    ))
+                    .suggestion("start the upload again without --resume")
+                }
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Hex encoded sha256 of a local file, comparable to what `CscsApi::checksum` returns
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = std::fs::File::open(path)?;
+    let _n = std::io::copy(&mut file, &mut hasher)?;
+    let hash = hasher.finalize();
+    Ok(format!("{hash:02x}"))
+}
+
+/// Directories and files (with their size) of a directory tree, relative to its root
+type DirectoryTree = (Vec<PathBuf>, Vec<(PathBuf, usize)>);
+
+/// Aggregated progress of a transfer spanning multiple files
+struct TransferProgress {
+    total_files: usize,
+    done_files: usize,
+    total_bytes: usize,
+    done_bytes: usize,
+    last_report: Instant,
+}
+
+impl TransferProgress {
+    fn new(files: &[(PathBuf, usize)]) -> Self {
+        Self {
+            total_files: files.len(),
+            done_files: 0,
+            total_bytes: files.iter().map(|(_, size)| size).sum(),
+            done_bytes: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    fn advance(&mut self, bytes: usize) -> Result<()> {
+        self.done_bytes += bytes;
+        if self.last_report.elapsed() >= Duration::from_secs(1) {
+            self.report()?;
+        }
+        Ok(())
+    }
+
+    fn file_done(&mut self) -> Result<()> {
+        self.done_files += 1;
+        self.report()
+    }
+
+    fn report(&mut self) -> Result<()> {
+        print!(
+            "\rTransferred {}/{} files ({}/{}Mb)",
+            self.done_files,
+            self.total_files,
+            self.done_bytes / 1024 / 1024,
+            self.total_bytes / 1024 / 1024
+        );
+        std::io::stdout().flush()?;
+        self.last_report = Instant::now();
+        Ok(())
+    }
+}
+
+/// Get the type of a remote path by looking it up in its parent directory, returns None if it doesn't exist
+///
+/// A missing parent directory also means the path doesn't exist, any other error is returned
+async fn remote_path_type(
+    api_client: &dyn ComputeBackend,
+    current_system: &str,
+    path: &Path,
+) -> Result<Option<PathType>> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        // filesystem root
+        return Ok(Some(PathType::Directory));
+    };
+    let entries = match api_client.list_path(current_system, parent.to_path_buf(), true).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            return match Box::pin(remote_path_type(api_client, current_system, parent)).await? {
+                None => Ok(None),
+                Some(_) => Err(e),
+            };
+        }
+    };
+    Ok(entries
+        .into_iter()
+        .find(|e| e.name.as_str() == name)
+        .map(|e| e.path_type))
+}
+
+/// Walks a remote directory tree, returning all directories and all files (with their size) relative to `root`
+async fn walk_remote_tree(api_client: &dyn ComputeBackend, current_system: &str, root: &Path) -> Result<DirectoryTree> {
+    let mut directories = vec![];
+    let mut files = vec![];
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let entries = api_client.list_path(current_system, root.join(&relative), true).await?;
+        for entry in entries {
+            let path = relative.join(&entry.name);
+            match entry.path_type {
+                PathType::Directory => {
+                    directories.push(path.clone());
+                    pending.push(path);
+                }
+                PathType::File => files.push((path, entry.size.unwrap_or_default())),
+                PathType::Link => println!("skipping symbolic link {}", root.join(path).display()),
+            }
+        }
+    }
+    Ok((directories, files))
+}
+
+/// Walks a local directory tree, returning all directories and all files (with their size) relative to `root`
+///
+/// Paths matched by `ignore` are skipped, ignored directories aren't descended into
+fn walk_local_tree(root: &Path, ignore: &Gitignore) -> Result<DirectoryTree> {
+    let mut directories = vec![];
+    let mut files = vec![];
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in std::fs::read_dir(root.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if ignore.matched(&path, file_type.is_dir()).is_ignore() {
+                continue;
+            }
+            if file_type.is_dir() {
+                directories.push(path.clone());
+                pending.push(path);
+            } else if file_type.is_file() {
+                files.push((path, entry.metadata()?.len() as usize));
+            } else {
+                println!(
+                    "skipping {}, only regular files are uploaded",
+                    root.join(path).display()
+                );
             }
         }
+    }
+    Ok((directories, files))
+}
+
+/// Waits for a transfer job, e.g. of a large file to s3, to finish
+pub async fn cscs_transfer_wait(
+    config: &Config,
+    job_id: JobId,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn wait_for_transfer_job(api_client: &dyn ComputeBackend, current_system: &str, job_id: &JobId) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + TRANSFER_TIMEOUT;
+    let mut missing_polls = 0;
+    loop {
+        match api_client.get_job(current_system, job_id.clone()).await? {
+            Some(JobDetail {
+                status: JobStatus::Finished,
+                ..
+            }) => return Ok(()),
+            Some(JobDetail {
+                status: JobStatus::Cancelled | JobStatus::Failed,
+                ..
+            }) => return Err(eyre!("transfer job {} failed", job_id)),
+            Some(JobDetail {
+                status: JobStatus::Timeout,
+                ..
+            }) => return Err(eyre!("transfer job {} timed out", job_id)),
+            Some(_) => missing_polls = 0,
+            None => {
+                missing_polls += 1;
+                if missing_polls >= TRANSFER_MAX_MISSING_POLLS {
+                    return Err(eyre!("transfer job {} not found", job_id))
+                        .suggestion("check whether the transfer finished with `coman cscs file list`");
+                }
+            }
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(eyre!("gave up waiting for transfer job {}", job_id));
+        }
+        tokio::time::sleep(TRANSFER_POLL_INTERVAL).await;
+    }
+}
+
+async fn s3_download(
+    url: Url,
+    local: &Path,
+    size: usize,
+    parallelism: usize,
+    progress: &mut TransferProgress,
+) -> Result<()> {
+    ranged_download(&url, local, size as u64, parallelism, |bytes| {
+        progress.advance(bytes as usize)
+    })
+    .await
+}
+
+/// Uploads files relative to `local` to the same relative path below `remote`, waiting for any s3 transfers
+///
+/// Small files overwrite `existing` remote files in place, large ones can't so the remote file is removed right
+/// before its s3 transfer starts.
+#[allow(clippy::too_many_arguments)]
+async fn upload_tree_files(
+    api_client: &dyn ComputeBackend,
+    current_system: &str,
+    account: Option<String>,
+    parallelism: usize,
+    local: &Path,
+    remote: &Path,
+    files: &[(PathBuf, usize)],
+    existing: &HashSet<PathBuf>,
+) -> Result<()> {
+    let mut progress = TransferProgress::new(files);
+    let mut transfer_jobs = vec![];
+    for (path, size) in files.iter() {
+        if *size < CSCS_MAX_DIRECT_SIZE {
+            let contents = std::fs::read(local.join(path))?;
+            api_client.upload(current_system, remote.join(path), contents).await?;
+            progress.advance(*size)?;
+        } else {
+            if existing.contains(path) {
+                // s3 transfers fail on existing files
+                api_client.rm_path(current_system, remote.join(path)).await?;
+            }
+            let (job_id, transfer_data) = api_client
+                .transfer_upload(current_system, account.clone(), remote.join(path), *size as i64)
+                .await?;
+            multipart_upload(
+                &local.join(path),
+                *size as u64,
+                &transfer_data,
+                parallelism,
+                BTreeMap::new(),
+                |_, _, length| progress.advance(length as usize),
+            )
+            .await?;
+            transfer_jobs.push(job_id);
+        }
+        progress.file_done()?;
+    }
+    println!(); //force newline
+    if !transfer_jobs.is_empty() {
+        println!("waiting for {} transfer job(s) to finish", transfer_jobs.len());
+        for job_id in transfer_jobs {
+            wait_for_transfer_job(api_client, current_system, &job_id).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn cscs_file_download_dir(
+    config: &Config,
+    remote: PathBuf,
+    local: PathBuf,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    let local = if local.is_dir() {
+        local.join(
+            remote
+                .file_name()
+                .ok_or(eyre!("couldn't get name of remote directory"))?,
+        )
+    } else {
+        local
+    };
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            match remote_path_type(api_client.as_ref(), current_system, &remote).await? {
+                Some(PathType::Directory) => {}
+                Some(_) => {
+                    return Err(eyre!("remote path is not a directory"))
+                        .suggestion("download single files without `--recursive`");
+                }
+                None => return Err(eyre!("remote path doesn't exist")),
+            }
+            let (directories, files) = walk_remote_tree(api_client.as_ref(), current_system, &remote).await?;
+            std::fs::create_dir_all(&local)?;
+            for directory in directories {
+                std::fs::create_dir_all(local.join(directory))?;
+            }
+
+            // start all s3 transfer jobs first so they can run while we download the small files
+            let mut s3_transfers = vec![];
+            let mut direct_transfers = vec![];
+            for (path, size) in files.iter() {
+                if *size < CSCS_MAX_DIRECT_SIZE {
+                    direct_transfers.push((path, *size));
+                } else {
+                    let job_data = api_client
+                        .transfer_download(current_system, account.clone(), remote.join(path))
+                        .await?;
+                    s3_transfers.push((path, *size, job_data));
+                }
+            }
+
+            let mut progress = TransferProgress::new(&files);
+            for (path, size) in direct_transfers {
+                let contents = api_client.download(current_system, remote.join(path)).await?;
+                std::fs::write(local.join(path), contents)?;
+                verify_download(
+                    api_client.as_ref(),
+                    current_system,
+                    &remote.join(path),
+                    &local.join(path),
+                )
+                .await?;
+                progress.advance(size)?;
+                progress.file_done()?;
+            }
+            for (path, size, (job_id, url)) in s3_transfers {
+                wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await?;
+                s3_download(
+                    url,
+                    &local.join(path),
+                    size,
+                    config.values.cscs.transfer_parallelism(),
+                    &mut progress,
+                )
+                .await?;
+                verify_download(
+                    api_client.as_ref(),
+                    current_system,
+                    &remote.join(path),
+                    &local.join(path),
+                )
+                .await?;
+                progress.file_done()?;
+            }
+            println!(); //force newline
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Downloads a remote directory as a single archive that is compressed on the cluster
+///
+/// The archive is removed from the cluster afterwards. If `extract` is set, the archive is unpacked into `local`
+/// and the local archive is removed as well. Returns the path of the local archive or extracted folder.
+pub async fn cscs_file_download_archive(
+    config: &Config,
+    remote: PathBuf,
+    local: PathBuf,
+    extract: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<PathBuf> {
+    let name = remote
+        .file_name()
+        .ok_or(eyre!("couldn't get name of remote directory"))?
+        .to_string_lossy()
+        .to_string();
+    let archive_name = format!("{}.tar.gz", name);
+    let local_archive = if extract {
+        std::fs::create_dir_all(&local)?;
+        local.join(&archive_name)
+    } else if local.is_dir() {
+        local.join(&archive_name)
+    } else {
+        local.clone()
+    };
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            match remote_path_type(api_client.as_ref(), current_system, &remote).await? {
+                Some(PathType::Directory) => {}
+                Some(_) => {
+                    return Err(eyre!("remote path is not a directory"))
+                        .suggestion("download single files without `--archive`");
+                }
+                None => return Err(eyre!("remote path doesn't exist")),
+            }
+            let remote_archive = remote.with_file_name(format!(
+                ".{}-{}.tar.gz",
+                name,
+                chrono::Utc::now().format("%Y%m%d%H%M%S")
+            ));
+
+            println!("compressing {} on the cluster", remote.display());
+            let job_id = api_client
+                .transfer_compress(
+                    current_system,
+                    account.clone(),
+                    remote.clone(),
+                    remote_archive.clone(),
+                    ArchiveCompression::Gzip,
+                )
+                .await?;
+            wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await?;
+
+            let result = async {
+                let size = api_client
+                    .list_path(current_system, remote_archive.clone(), false)
+                    .await?
+                    .first()
+                    .and_then(|e| e.size)
+                    .ok_or(eyre!("couldn't determine archive size"))?;
+                // always go through s3, archives of whole directories quickly exceed the direct download limit
+                let (job_id, url) = api_client
+                    .transfer_download(current_system, account.clone(), remote_archive.clone())
+                    .await?;
+                wait_for_transfer_job(api_client.as_ref(), current_system, &job_id).await?;
+                let mut progress = TransferProgress::new(&[(local_archive.clone(), size)]);
+                s3_download(
+                    url,
+                    &local_archive,
+                    size,
+                    config.values.cscs.transfer_parallelism(),
+                    &mut progress,
+                )
+                .await?;
+                progress.file_done()?;
+                println!(); //force newline
+                verify_download(api_client.as_ref(), current_system, &remote_archive, &local_archive).await?;
+                Ok::<(), eyre::Report>(())
+            }
+            .await;
+            // clean up the remote archive whether the download worked or not
+            let cleanup = api_client.rm_path(current_system, remote_archive).await;
+            result?;
+            cleanup.wrap_err("couldn't remove archive from the cluster")?;
+
+            if extract {
+                let archive = std::fs::File::open(&local_archive)?;
+                tar::Archive::new(flate2::read::GzDecoder::new(archive))
+                    .unpack(&local)
+                    .wrap_err("couldn't extract archive")?;
+                std::fs::remove_file(&local_archive)?;
+                Ok(local.join(name))
+            } else {
+                Ok(local_archive)
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn cscs_file_upload_dir(
+    config: &Config,
+    local: PathBuf,
+    remote: PathBuf,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<()> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+            // a non-existent parent is fine, mkdir creates it
+            let remote = match remote_path_type(api_client.as_ref(), current_system, &remote).await? {
+                Some(PathType::Directory) => {
+                    remote.join(local.file_name().ok_or(eyre!("couldn't get name of local directory"))?)
+                }
+                Some(_) => return Err(eyre!("remote path already exists and is not a directory")),
+                None => remote,
+            };
+            let (directories, files) = walk_local_tree(&local, &Gitignore::empty())?;
+            api_client.mkdir(current_system, remote.clone()).await?;
+            for directory in directories {
+                api_client.mkdir(current_system, remote.join(directory)).await?;
+            }
+
+            upload_tree_files(
+                api_client.as_ref(),
+                current_system,
+                account,
+                config.values.cscs.transfer_parallelism(),
+                &local,
+                &remote,
+                &files,
+                &HashSet::new(),
+            )
+            .await?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Result of syncing a local folder to the cluster
+pub struct SyncSummary {
+    pub uploaded: Vec<PathBuf>,
+    pub unchanged: usize,
+    pub deleted: Vec<PathBuf>,
+}
+
+/// Builds the matcher for the `.comanignore` file in `root`, if there is one
+fn load_coman_ignore(root: &Path) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    let ignore_file = root.join(COMAN_IGNORE_FILE);
+    if ignore_file.exists()
+        && let Some(e) = builder.add(&ignore_file)
+    {
+        return Err(e).wrap_err(format!("couldn't parse {}", ignore_file.display()));
+    }
+    builder.build().wrap_err("couldn't build ignore rules")
+}
+
+/// Mirrors a local folder to a remote folder, only uploading files that are new or whose checksum differs
+///
+/// Files matched by a `.comanignore` file in the local folder are neither uploaded nor deleted
+pub async fn cscs_file_sync(
+    config: &Config,
+    local: PathBuf,
+    remote: PathBuf,
+    delete: bool,
+    account: Option<String>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<SyncSummary> {
+    if !local.is_dir() {
+        return Err(eyre!("local path must be a directory"));
+    }
+    let ignore = load_coman_ignore(&local)?;
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
+            let current_system = &system.unwrap_or(config.values.cscs.current_system.clone());
+            let account = account.or(config.values.cscs.account.clone());
+
+            let (local_directories, local_files) = walk_local_tree(&local, &ignore)?;
+            let (remote_directories, remote_files) =
+                match remote_path_type(api_client.as_ref(), current_system, &remote).await? {
+                    Some(PathType::Directory) => walk_remote_tree(api_client.as_ref(), current_system, &remote).await?,
+                    Some(_) => return Err(eyre!("remote path already exists and is not a directory")),
+                    None => {
+                        api_client.mkdir(current_system, remote.clone()).await?;
+                        (vec![], vec![])
+                    }
+                };
+            let remote_directories: HashSet<PathBuf> = remote_directories.into_iter().collect();
+            let remote_files: HashMap<PathBuf, usize> = remote_files.into_iter().collect();
+
+            for directory in local_directories.iter() {
+                if !remote_directories.contains(directory) {
+                    api_client.mkdir(current_system, remote.join(directory)).await?;
+                }
+            }
+
+            let mut changed = vec![];
+            let mut replaced = HashSet::new();
+            let mut unchanged = 0;
+            for (path, size) in local_files.iter() {
+                let Some(remote_size) = remote_files.get(path) else {
+                    changed.push((path.clone(), *size));
+                    continue;
+                };
+                let same = remote_size == size && {
+                    let hash = sha256_file(&local.join(path))?;
+                    api_client
+                        .checksum(current_system, remote.join(path))
+                        .await?
+                        .is_some_and(|remote_hash| remote_hash == hash)
+                };
+                if same {
+                    unchanged += 1;
+                } else {
+                    replaced.insert(path.clone());
+                    changed.push((path.clone(), *size));
+                }
+            }
+            if !changed.is_empty() {
+                upload_tree_files(
+                    api_client.as_ref(),
+                    current_system,
+                    account,
+                    config.values.cscs.transfer_parallelism(),
+                    &local,
+                    &remote,
+                    &changed,
+                    &replaced,
+                )
+                .await?;
+            }
+
+            let mut deleted: Vec<PathBuf> = vec![];
+            if delete {
+                let local_directories: HashSet<&PathBuf> = local_directories.iter().collect();
+                let local_files: HashSet<&PathBuf> = local_files.iter().map(|(path, _)| path).collect();
+                let stale = remote_directories
+                    .iter()
+                    .filter(|d| !local_directories.contains(d))
+                    .map(|d| (d, true))
+                    .chain(
+                        remote_files
+                            .keys()
+                            .filter(|f| !local_files.contains(f))
+                            .map(|f| (f, false)),
+                    )
+                    .filter(|(path, is_dir)| !ignore.matched_path_or_any_parents(path, *is_dir).is_ignore())
+                    .map(|(path, _)| path)
+                    .sorted();
+                for path in stale {
+                    // directories are removed recursively, so skip anything inside an already removed directory
+                    if deleted.iter().any(|d| path.starts_with(d)) {
+                        continue;
+                    }
+                    api_client.rm_path(current_system, remote.join(path)).await?;
+                    deleted.push(path.clone());
+                }
+            }
+
+            Ok(SyncSummary {
+                uploaded: changed.into_iter().map(|(path, _)| path).collect(),
+                unchanged,
+                deleted,
+            })
+        }
         Err(e) => Err(e),
     }
 }
 
 pub async fn cscs_stat_path(
+    config: &Config,
     path: PathBuf,
     system: Option<String>,
     platform: Option<ComputePlatform>,
 ) -> Result<Option<FileStat>> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             api_client
-                .stat_path(&system.unwrap_or(config.values.cscs.current_system), path)
+                .stat_path(&system.unwrap_or(config.values.cscs.current_system.clone()), path)
                 .await
         }
         Err(e) => Err(e),
     }
 }
 
-pub async fn cscs_user_info(system: Option<String>, platform: Option<ComputePlatform>) -> Result<UserInfo> {
-    match get_access_token().await {
-        Ok(access_token) => {
-            let api_client = CscsApi::new(access_token.0, platform).unwrap();
-            let config = Config::new().unwrap();
+pub async fn cscs_user_info(
+    config: &Config,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+) -> Result<UserInfo> {
+    match get_api_client(config, platform).await {
+        Ok(api_client) => {
             api_client
-                .get_userinfo(&system.unwrap_or(config.values.cscs.current_system))
+                .get_userinfo(&system.unwrap_or(config.values.cscs.current_system.clone()))
                 .await
         }
         Err(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use current_dir::Cwd;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::cscs::api_client::fake::FakeBackend;
+
+    #[test]
+    fn test_check_script_dependency() {
+        let dependency = Some("afterok:1".to_owned());
+        assert!(check_script_dependency("#!/bin/bash\nsrun hostname\n", &None).is_ok());
+        assert!(
+            check_script_dependency(
+                "#!/bin/bash\n#SBATCH --dependency=afterok:1\nsrun hostname\n",
+                &dependency
+            )
+            .is_ok()
+        );
+        // e.g. a custom template without the dependency variable
+        assert!(check_script_dependency("#!/bin/bash\nsrun hostname\n", &dependency).is_err());
+        assert!(check_script_dependency("#!/bin/bash\n# --dependency=afterok:1\n", &dependency).is_err());
+    }
+
+    #[test]
+    fn test_walk_local_tree_respects_comanignore() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src").join("nested")).expect("couldn't create dir");
+        std::fs::create_dir_all(root.join("target").join("debug")).expect("couldn't create dir");
+        std::fs::write(root.join(COMAN_IGNORE_FILE), "target/\n*.log\n!keep.log\n").expect("couldn't write file");
+        std::fs::write(root.join("src").join("main.rs"), "fn main() {}").expect("couldn't write file");
+        std::fs::write(root.join("src").join("nested").join("run.log"), "").expect("couldn't write file");
+        std::fs::write(root.join("keep.log"), "").expect("couldn't write file");
+        std::fs::write(root.join("target").join("debug").join("bin"), "").expect("couldn't write file");
+
+        let ignore = load_coman_ignore(root).expect("couldn't load ignore file");
+        let (directories, files) = walk_local_tree(root, &ignore).expect("couldn't walk tree");
+
+        let directories: HashSet<PathBuf> = directories.into_iter().collect();
+        assert_eq!(
+            directories,
+            HashSet::from([PathBuf::from("src"), PathBuf::from("src/nested")])
+        );
+        let files: HashMap<PathBuf, usize> = files.into_iter().collect();
+        assert_eq!(
+            files,
+            HashMap::from([
+                (PathBuf::from(COMAN_IGNORE_FILE), 24),
+                (PathBuf::from("src/main.rs"), 12),
+                (PathBuf::from("keep.log"), 0),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_remote_path() {
+        let backend = FakeBackend::new(&["daint"]);
+        backend.add_file("/scratch/user/run/out.txt", "done");
+        backend.add_file("/scratch/user/run/logs/0.log", "");
+        let remove =
+            |path: &str, recursive: bool| remove_remote_path(&backend, "daint", None, PathBuf::from(path), recursive);
+
+        remove("/scratch/user/run/out.txt", false).await.unwrap();
+        assert!(!backend.exists("/scratch/user/run/out.txt"));
+        assert!(remove("/scratch/user/run/out.txt", false).await.is_err());
+        // directories are only removed with --recursive
+        assert!(remove("/scratch/user/run", false).await.is_err());
+        assert!(backend.exists("/scratch/user/run/logs/0.log"));
+        remove("/scratch/user/run", true).await.unwrap();
+        assert!(!backend.exists("/scratch/user/run"));
+        assert!(backend.exists("/scratch/user"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_for_transfer_job() {
+        let backend = FakeBackend::new(&["daint"]);
+        let finished = backend.add_job("1", "transfer", JobStatus::Finished);
+        let failed = backend.add_job("2", "transfer", JobStatus::Failed);
+        let running = backend.add_job("3", "transfer", JobStatus::Running);
+
+        assert!(wait_for_transfer_job(&backend, "daint", &finished).await.is_ok());
+        assert!(wait_for_transfer_job(&backend, "daint", &failed).await.is_err());
+        // purged or unknown jobs don't block forever
+        let missing = JobId::new("4".to_owned());
+        let start = tokio::time::Instant::now();
+        assert!(wait_for_transfer_job(&backend, "daint", &missing).await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(60));
+        // neither do jobs that never finish
+        let start = tokio::time::Instant::now();
+        assert!(wait_for_transfer_job(&backend, "daint", &running).await.is_err());
+        assert!(start.elapsed() >= TRANSFER_TIMEOUT);
+    }
+
+    #[test]
+    fn test_array_log_path() {
+        assert_eq!(array_log_path("out.log"), "out_%A_%a.log");
+        assert_eq!(array_log_path("/scratch/user/run/out"), "/scratch/user/run/out_%A_%a");
+        assert_eq!(
+            array_log_path("/scratch/user.name/.out"),
+            "/scratch/user.name/.out_%A_%a"
+        );
+        assert_eq!(array_log_path("out_%j.log"), "out_%j.log");
+        assert_eq!(
+            array_log_directive("#SBATCH --output=out.log"),
+            "#SBATCH --output=out_%A_%a.log"
+        );
+        assert_eq!(array_log_directive("#SBATCH -e err"), "#SBATCH -e err_%A_%a");
+        assert_eq!(array_log_directive("#SBATCH --nodes=1"), "#SBATCH --nodes=1");
+    }
+
+    #[tokio::test]
+    async fn test_sweep_script() {
+        // the active site is read from the config in the current directory, which other tests change
+        {
+            let _locked_cwd = Cwd::mutex().lock().expect("couldn't get cwd lock");
+            active_site();
+        }
+        let config = Config::defaults().unwrap();
+        let backend = FakeBackend::new(&["daint"]);
+        let options = JobStartOptions {
+            no_ssh: true,
+            no_coman: true,
+            stdout: Some(PathBuf::from("out.log")),
+            sweep: vec![("LR".to_owned(), vec!["0.1".to_owned(), "0.01".to_owned()])],
+            ..Default::default()
+        };
+        let prepared = prepare_job(
+            &backend,
+            &config,
+            "daint",
+            Some("my run".to_owned()),
+            options,
+            None,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(prepared.submission.stdout, Some(PathBuf::from("out_%A_%a.log")));
+        let script = prepared.submission.script.unwrap();
+        assert!(script.contains("#SBATCH --array=0-1\n"));
+        assert!(script.contains("exec bash '/scratch/user/my run/sweep'/\"${SLURM_ARRAY_TASK_ID}.sh\"\n"));
+    }
+}
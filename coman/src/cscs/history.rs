@@ -0,0 +1,368 @@
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{DEFAULT_SITE, get_data_dir},
+    cscs::{
+        api_client::client::{EdfSpec, JobStartOptions, ScriptSpec, SlurmOptions},
+        sweep::SweepGrid,
+    },
+    util::types::DockerImageUrl,
+};
+
+/// Everything needed to find out how a job was submitted, stored locally for every submitted job
+#[derive(Debug, Clone, Serialize, Deserialize, tabled::Tabled)]
+pub struct JobSubmission {
+    pub job_id: String,
+    pub name: String,
+    pub system: String,
+    /// the FirecREST site the job ran at, jobs from before sites existed ran at CSCS
+    #[tabled(skip)]
+    #[serde(default = "default_site")]
+    pub site: String,
+    #[tabled(display("display_option"))]
+    pub account: Option<String>,
+    #[tabled(display("display_datetime"))]
+    pub submitted: DateTime<Local>,
+    #[tabled(display("display_option"))]
+    pub image: Option<String>,
+    #[tabled(display("display_command"))]
+    pub command: Vec<String>,
+    #[tabled(skip)]
+    pub workdir: String,
+    #[tabled(skip)]
+    pub env: BTreeMap<String, String>,
+    #[tabled(skip)]
+    pub mounts: BTreeMap<String, String>,
+    #[tabled(skip)]
+    pub port_forward: Vec<u16>,
+    #[tabled(skip)]
+    pub slurm: SlurmOptions,
+    #[tabled(skip)]
+    pub dependency: Option<String>,
+    #[tabled(skip)]
+    pub sweep: SweepGrid,
+    #[tabled(skip)]
+    #[serde(default)]
+    pub stdout: Option<PathBuf>,
+    #[tabled(skip)]
+    #[serde(default)]
+    pub stderr: Option<PathBuf>,
+    /// where the sbatch script came from
+    #[tabled(skip)]
+    #[serde(default)]
+    pub script_spec: ScriptSpec,
+    /// where the environment definition came from
+    #[tabled(skip)]
+    #[serde(default)]
+    pub edf_spec: EdfSpec,
+    /// the local directory the job was submitted from
+    #[tabled(skip)]
+    pub local_dir: Option<PathBuf>,
+    #[tabled(display("display_option"))]
+    pub git_commit: Option<String>,
+    /// whether the git repository had uncommitted changes
+    #[tabled(skip)]
+    pub git_dirty: bool,
+    /// remote directory containing the script and environment file
+    #[tabled(skip)]
+    pub remote_dir: PathBuf,
+    #[tabled(skip)]
+    pub script_path: PathBuf,
+    #[tabled(skip)]
+    pub environment_path: PathBuf,
+    /// rendered sbatch script, not set if a remote script was used
+    #[tabled(skip)]
+    pub script: Option<String>,
+    /// rendered environment definition, not set if a remote file was used
+    #[tabled(skip)]
+    pub environment: Option<String>,
+}
+
+fn default_site() -> String {
+    DEFAULT_SITE.to_owned()
+}
+
+/// Settings to change when submitting a job again, everything not set is taken from the original submission
+#[derive(Debug, Clone, Default)]
+pub struct JobOverrides {
+    pub image: Option<DockerImageUrl>,
+    pub command: Option<Vec<String>>,
+    pub workdir: Option<String>,
+    /// added to (or replacing) the environment variables of the original job
+    pub env: Vec<(String, String)>,
+    /// added to (or replacing) the mounts of the original job
+    pub mount: Vec<(String, String)>,
+    pub port_forward: Vec<u16>,
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+    pub edf_spec: Option<EdfSpec>,
+    pub script_spec: Option<ScriptSpec>,
+    pub slurm: SlurmOptions,
+    pub no_ssh: bool,
+    pub ssh_key: Option<PathBuf>,
+    pub no_coman: bool,
+}
+
+impl JobOverrides {
+    /// Whether anything is overridden that ends up in the generated script or environment file
+    pub fn changes_job_files(&self) -> bool {
+        self.image.is_some()
+            || self.command.is_some()
+            || self.workdir.is_some()
+            || !self.env.is_empty()
+            || !self.mount.is_empty()
+            || !self.port_forward.is_empty()
+    }
+}
+
+impl JobSubmission {
+    /// The options to start this job again with, with `overrides` applied
+    pub fn resubmit_options(&self, overrides: JobOverrides) -> Result<JobStartOptions> {
+        let image = match overrides.image {
+            Some(image) => Some(image),
+            None => self.image.clone().map(DockerImageUrl::try_from).transpose()?,
+        };
+        let command = overrides
+            .command
+            .or_else(|| (!self.command.is_empty()).then(|| self.command.clone()));
+        let mut env = self.env.clone();
+        env.extend(overrides.env);
+        let mut mount = self.mounts.clone();
+        mount.extend(overrides.mount);
+        // local files are looked up relative to where the job was submitted from
+        let local_path = |path: &Path| -> Result<PathBuf> {
+            let path = match &self.local_dir {
+                Some(dir) if path.is_relative() => dir.join(path),
+                _ => path.to_path_buf(),
+            };
+            if !path.exists() {
+                return Err(eyre!(
+                    "local file {} of the original job doesn't exist anymore",
+                    path.display()
+                ))
+                .suggestion("`coman history show` contains the rendered files of the original job");
+            }
+            Ok(path)
+        };
+        let script_spec = match overrides.script_spec.unwrap_or(self.script_spec.clone()) {
+            ScriptSpec::Local(path) => ScriptSpec::Local(local_path(&path)?),
+            spec => spec,
+        };
+        let edf_spec = match overrides.edf_spec.unwrap_or(self.edf_spec.clone()) {
+            EdfSpec::Local(path) => EdfSpec::Local(local_path(&path)?),
+            spec => spec,
+        };
+        Ok(JobStartOptions {
+            image,
+            command,
+            stdout: overrides.stdout.or(self.stdout.clone()),
+            stderr: overrides.stderr.or(self.stderr.clone()),
+            container_workdir: overrides.workdir.or(Some(self.workdir.clone())),
+            env: env.into_iter().collect(),
+            port_forward: if overrides.port_forward.is_empty() {
+                self.port_forward.clone()
+            } else {
+                overrides.port_forward
+            },
+            mount: mount.into_iter().collect(),
+            edf_spec,
+            script_spec,
+            no_ssh: overrides.no_ssh,
+            ssh_key: overrides.ssh_key,
+            no_coman: overrides.no_coman,
+            slurm: overrides.slurm.or(self.slurm.clone()),
+            sweep: self.sweep.clone(),
+            dependencies: vec![],
+        })
+    }
+}
+
+fn display_option(o: &Option<String>) -> String {
+    o.clone().unwrap_or_default()
+}
+
+fn display_datetime(d: &DateTime<Local>) -> String {
+    d.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn display_command(c: &[String]) -> String {
+    c.join(" ")
+}
+
+fn history_file() -> PathBuf {
+    get_data_dir().join("history.jsonl")
+}
+
+/// The commit and whether there are uncommitted changes of the git repository containing `dir`, if any
+pub fn git_state(dir: &Path) -> Option<(String, bool)> {
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .is_some_and(|o| !o.stdout.is_empty());
+    Some((String::from_utf8_lossy(&commit.stdout).trim().to_owned(), dirty))
+}
+
+/// Appends a submission to the history.
+///
+/// The history holds the environment of jobs, which often contains credentials, so only the user can read it.
+pub fn record_submission(submission: &JobSubmission) -> Result<()> {
+    append_submission(&history_file(), submission)
+}
+
+fn append_submission(path: &Path, submission: &JobSubmission) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(target_family = "unix")]
+    options.mode(0o600);
+    let mut file = options.open(path).wrap_err("couldn't open job history")?;
+    // histories written before were created with the default permissions
+    #[cfg(target_family = "unix")]
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))
+        .wrap_err("couldn't restrict permissions of job history")?;
+    writeln!(file, "{}", serde_json::to_string(submission)?)?;
+    Ok(())
+}
+
+/// All recorded submissions, oldest first
+pub fn load_history() -> Result<Vec<JobSubmission>> {
+    read_submissions(&history_file())
+}
+
+fn read_submissions(path: &Path) -> Result<Vec<JobSubmission>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = std::fs::File::open(path).wrap_err("couldn't open job history")?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().is_ok_and(|l| !l.trim().is_empty()))
+        .map(|(i, line)| serde_json::from_str(&line?).wrap_err(format!("couldn't parse line {} of job history", i + 1)))
+        .collect()
+}
+
+/// The submission of a job, by job id or the newest job with that name, if it is in the history
+pub fn lookup_submission(job: &str) -> Result<Option<JobSubmission>> {
+    Ok(load_history()?
+        .into_iter()
+        .rev()
+        .find(|s| s.job_id == job || s.name == job))
+}
+
+/// The submission of a job, by job id or the newest job with that name
+pub fn find_submission(job: &str) -> Result<JobSubmission> {
+    lookup_submission(job)?
+        .ok_or(eyre!("no submission found for job {}", job))
+        .suggestion("the history only contains jobs submitted from this machine")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_history_roundtrip() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let path = temp_dir.path().join("data").join("history.jsonl");
+        let mut submission = JobSubmission {
+            job_id: "1".to_owned(),
+            name: "train".to_owned(),
+            system: "daint".to_owned(),
+            site: "other".to_owned(),
+            account: None,
+            submitted: Local::now(),
+            image: Some("ubuntu:24.04".to_owned()),
+            command: vec!["python".to_owned(), "train.py".to_owned()],
+            workdir: "/scratch".to_owned(),
+            env: BTreeMap::from([("LR".to_owned(), "0.1".to_owned())]),
+            mounts: BTreeMap::new(),
+            port_forward: vec![],
+            slurm: SlurmOptions::default(),
+            dependency: None,
+            sweep: vec![],
+            stdout: None,
+            stderr: None,
+            script_spec: ScriptSpec::Generate,
+            edf_spec: EdfSpec::Remote(PathBuf::from("/scratch/user/train/environment.toml")),
+            local_dir: None,
+            git_commit: None,
+            git_dirty: false,
+            remote_dir: PathBuf::from("/scratch/user/train"),
+            script_path: PathBuf::from("/scratch/user/train/script.sh"),
+            environment_path: PathBuf::from("/scratch/user/train/environment.toml"),
+            script: Some("#!/bin/bash\nsrun python train.py\n".to_owned()),
+            environment: None,
+        };
+        append_submission(&path, &submission).unwrap();
+        submission.job_id = "2".to_owned();
+        append_submission(&path, &submission).unwrap();
+
+        #[cfg(target_family = "unix")]
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let history = read_submissions(&path).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].job_id, "2");
+        assert_eq!(history[0].script, submission.script);
+        assert_eq!(history[0].site, "other");
+
+        let mut record = serde_json::to_value(&submission).unwrap();
+        record.as_object_mut().unwrap().remove("site");
+        let old: JobSubmission = serde_json::from_value(record).unwrap();
+        assert_eq!(old.site, DEFAULT_SITE);
+        assert!(
+            read_submissions(&temp_dir.path().join("missing.jsonl"))
+                .unwrap()
+                .is_empty()
+        );
+
+        let options = history[1]
+            .resubmit_options(JobOverrides {
+                env: vec![("LR".to_owned(), "0.01".to_owned())],
+                slurm: SlurmOptions {
+                    nodes: Some(2),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(options.image.unwrap().to_string(), "ubuntu:24.04");
+        assert_eq!(options.command, Some(submission.command.clone()));
+        assert_eq!(options.env, vec![("LR".to_owned(), "0.01".to_owned())]);
+        assert_eq!(options.slurm.nodes, Some(2));
+        assert_eq!(options.edf_spec, submission.edf_spec);
+        assert!(
+            history[1]
+                .resubmit_options(JobOverrides {
+                    script_spec: Some(ScriptSpec::Local(PathBuf::from("missing.sh"))),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+    }
+}
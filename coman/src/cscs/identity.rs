@@ -0,0 +1,322 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, DEFAULT_SITE, active_site, get_data_dir},
+    cscs::oauth2::{
+        ACCESS_TOKEN_EXPIRY_SECRET_NAME, ACCESS_TOKEN_SECRET_NAME, CLIENT_ID_SECRET_NAME, CLIENT_SECRET_SECRET_NAME,
+        REFRESH_TOKEN_SECRET_NAME,
+    },
+    util::secrets::{delete_secret, get_secret},
+};
+
+/// The identity used when none is configured, its secrets use the plain secret names
+pub const DEFAULT_IDENTITY: &str = "default";
+
+static ACTIVE_IDENTITY: OnceLock<String> = OnceLock::new();
+
+/// Secrets stored for each identity
+const IDENTITY_SECRETS: [&str; 5] = [
+    CLIENT_ID_SECRET_NAME,
+    CLIENT_SECRET_SECRET_NAME,
+    ACCESS_TOKEN_SECRET_NAME,
+    ACCESS_TOKEN_EXPIRY_SECRET_NAME,
+    REFRESH_TOKEN_SECRET_NAME,
+];
+
+/// How an identity logged in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LoginMethod {
+    /// client id and secret of a service account
+    ClientCredentials,
+    /// the user's own CSCS account, authorized in the browser
+    Device,
+}
+
+/// What we remember about an identity, its credentials themselves live in the keyring
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IdentityRecord {
+    login: LoginMethod,
+    logged_in: DateTime<Local>,
+}
+
+/// A named set of CSCS credentials
+#[derive(Debug, Clone, Serialize, tabled::Tabled)]
+pub struct Identity {
+    pub name: String,
+    #[tabled(display("display_login"))]
+    pub login: Option<LoginMethod>,
+    #[tabled(display("display_logged_in"))]
+    pub logged_in: Option<DateTime<Local>>,
+    pub active: bool,
+}
+
+fn display_login(l: &Option<LoginMethod>) -> String {
+    l.map(|l| l.to_string()).unwrap_or_default()
+}
+
+fn display_logged_in(d: &Option<DateTime<Local>>) -> String {
+    d.map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default()
+}
+
+/// Selects the identity to use for the rest of this process, instead of the configured one
+pub fn set_active_identity(name: String) -> Result<()> {
+    check_identity_name(&name)?;
+    ACTIVE_IDENTITY
+        .set(name)
+        .map_err(|_| eyre!("an identity was already selected"))
+}
+
+/// The identity whose credentials are used, from `cscs.identity` in the (project local) config
+pub fn active_identity() -> String {
+    ACTIVE_IDENTITY
+        .get_or_init(|| {
+            Config::new()
+                .ok()
+                .and_then(|c| c.values.cscs.identity)
+                .unwrap_or(DEFAULT_IDENTITY.to_owned())
+        })
+        .clone()
+}
+
+/// Identity names end up in keyring entry names, so we keep them simple
+fn check_identity_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(eyre!("invalid identity name `{}`", name))
+            .suggestion("identity names can only contain letters, digits, `-` and `_`");
+    }
+    Ok(())
+}
+
+/// Name of a keyring secret of the identity at the current site, see [`site_secret_name`]
+pub fn secret_name(name: &str, identity: &str) -> String {
+    site_secret_name(name, &active_site(), identity)
+}
+
+/// Name of a keyring secret of the identity at a site, e.g. `cscs_client_id__work` for the `work` identity or
+/// `other_client_id` for the default identity of the `other` site.
+///
+/// Secret names never contain `__`, so a name like `cscs_access_token__expiry` of the `expiry` identity can't clash
+/// with the `cscs_access_token_expiry` of the default identity.
+pub fn site_secret_name(name: &str, site: &str, identity: &str) -> String {
+    let name = match name.strip_prefix("cscs_") {
+        Some(rest) if site != DEFAULT_SITE => format!("{site}_{rest}"),
+        _ => name.to_owned(),
+    };
+    if identity == DEFAULT_IDENTITY {
+        name
+    } else {
+        format!("{name}__{identity}")
+    }
+}
+
+/// Identities are per site, the same name can log in as different users at different sites
+fn identities_file(site: &str) -> PathBuf {
+    if site == DEFAULT_SITE {
+        get_data_dir().join("identities.json")
+    } else {
+        get_data_dir().join(format!("identities_{site}.json"))
+    }
+}
+
+fn read_records(path: &Path) -> Result<BTreeMap<String, IdentityRecord>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = std::fs::read_to_string(path).wrap_err("couldn't read identities")?;
+    serde_json::from_str(&content).wrap_err("couldn't parse identities")
+}
+
+fn write_records(path: &Path, records: &BTreeMap<String, IdentityRecord>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(records)?).wrap_err("couldn't write identities")
+}
+
+/// Remembers that the identity logged in, so it shows up in `coman cscs identity list`
+pub fn record_login(identity: &str, login: LoginMethod) -> Result<()> {
+    let path = identities_file(&active_site());
+    let mut records = read_records(&path)?;
+    records.insert(
+        identity.to_owned(),
+        IdentityRecord {
+            login,
+            logged_in: Local::now(),
+        },
+    );
+    write_records(&path, &records)
+}
+
+/// How the identity last logged in, if it did
+pub fn login_method(identity: &str) -> Result<Option<LoginMethod>> {
+    Ok(read_records(&identities_file(&active_site()))?
+        .get(identity)
+        .map(|r| r.login))
+}
+
+/// All identities that logged in on this machine
+pub async fn list_identities() -> Result<Vec<Identity>> {
+    let records = read_records(&identities_file(&active_site()))?;
+    let active = active_identity();
+    let mut identities: Vec<Identity> = records
+        .into_iter()
+        .map(|(name, record)| Identity {
+            active: name == active,
+            name,
+            login: Some(record.login),
+            logged_in: Some(record.logged_in),
+        })
+        .collect();
+    // logins from before identities existed aren't recorded, but their credentials are still there
+    if !identities.iter().any(|i| i.name == DEFAULT_IDENTITY) && has_credentials(DEFAULT_IDENTITY).await? {
+        identities.insert(
+            0,
+            Identity {
+                name: DEFAULT_IDENTITY.to_owned(),
+                login: None,
+                logged_in: None,
+                active: active == DEFAULT_IDENTITY,
+            },
+        );
+    }
+    Ok(identities)
+}
+
+async fn has_credentials(identity: &str) -> Result<bool> {
+    Ok(get_secret(&secret_name(CLIENT_ID_SECRET_NAME, identity))
+        .await?
+        .is_some()
+        || get_secret(&secret_name(REFRESH_TOKEN_SECRET_NAME, identity))
+            .await?
+            .is_some())
+}
+
+/// Checks that an identity exists, so we don't select one that can't log in
+pub async fn check_identity(identity: &str) -> Result<()> {
+    let identities = list_identities().await?;
+    if identities.iter().any(|i| i.name == identity) {
+        return Ok(());
+    }
+    Err(eyre!("unknown identity {}", identity)).with_suggestion(|| {
+        format!(
+            "log in as it with `coman cscs login --profile {}` first, known identities: {}",
+            identity,
+            identities.iter().map(|i| i.name.clone()).collect::<Vec<_>>().join(", ")
+        )
+    })
+}
+
+/// Names of the keyring secrets of all identities known on this machine, at all configured sites
+pub fn known_secret_names() -> Result<Vec<String>> {
+    let mut sites: Vec<String> = Config::new()?.values.sites.into_keys().collect();
+    if !sites.iter().any(|s| s == DEFAULT_SITE) {
+        sites.insert(0, DEFAULT_SITE.to_owned());
+    }
+    let mut names = Vec::new();
+    for site in sites {
+        let mut identities: Vec<String> = read_records(&identities_file(&site))?.into_keys().collect();
+        if !identities.iter().any(|i| i == DEFAULT_IDENTITY) {
+            identities.insert(0, DEFAULT_IDENTITY.to_owned());
+        }
+        names.extend(identities.iter().flat_map(|identity| {
+            IDENTITY_SECRETS
+                .iter()
+                .map(|name| site_secret_name(name, &site, identity))
+        }));
+    }
+    Ok(names)
+}
+
+/// Deletes all credentials and tokens of an identity
+pub async fn remove_identity(identity: &str) -> Result<()> {
+    check_identity(identity).await?;
+    for name in IDENTITY_SECRETS {
+        delete_secret(&secret_name(name, identity))
+            .await
+            .wrap_err(format!("couldn't delete {} of identity {}", name, identity))?;
+    }
+    let path = identities_file(&active_site());
+    let mut records = read_records(&path)?;
+    records.remove(identity);
+    write_records(&path, &records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_records() {
+        assert_eq!(
+            site_secret_name(CLIENT_ID_SECRET_NAME, DEFAULT_SITE, DEFAULT_IDENTITY),
+            "cscs_client_id"
+        );
+        assert_eq!(
+            site_secret_name(CLIENT_ID_SECRET_NAME, DEFAULT_SITE, "work"),
+            "cscs_client_id__work"
+        );
+        assert_eq!(
+            site_secret_name(CLIENT_SECRET_SECRET_NAME, "other", DEFAULT_IDENTITY),
+            "other_secret_id"
+        );
+        assert_eq!(
+            site_secret_name(REFRESH_TOKEN_SECRET_NAME, "other", "work"),
+            "other_refresh_token__work"
+        );
+
+        // identities named like the suffix of another secret get their own names
+        let identities = [
+            DEFAULT_IDENTITY,
+            "work",
+            "expiry",
+            "expiry_work",
+            "_expiry",
+            "token_expiry",
+        ];
+        let names: Vec<String> = identities
+            .iter()
+            .flat_map(|identity| {
+                IDENTITY_SECRETS
+                    .iter()
+                    .map(|name| site_secret_name(name, DEFAULT_SITE, identity))
+            })
+            .collect();
+        let unique: std::collections::HashSet<&String> = names.iter().collect();
+        assert_eq!(unique.len(), names.len());
+        assert_ne!(
+            site_secret_name(ACCESS_TOKEN_SECRET_NAME, DEFAULT_SITE, "expiry"),
+            ACCESS_TOKEN_EXPIRY_SECRET_NAME
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("identities.json");
+        assert!(read_records(&path).unwrap().is_empty());
+
+        let mut records = BTreeMap::new();
+        records.insert(
+            "work".to_owned(),
+            IdentityRecord {
+                login: LoginMethod::Device,
+                logged_in: Local::now(),
+            },
+        );
+        write_records(&path, &records).unwrap();
+        assert_eq!(read_records(&path).unwrap(), records);
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("\"login\": \"device\"")
+        );
+    }
+}
@@ -1,5 +1,13 @@
 pub mod api_client;
+pub mod auth;
 pub mod cli;
+pub mod edf;
 pub mod handlers;
+pub mod history;
+pub mod identity;
+pub mod notifications;
 pub mod oauth2;
+pub mod pipeline;
 pub mod ports;
+pub mod s3;
+pub mod sweep;
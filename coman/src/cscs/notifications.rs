@@ -0,0 +1,336 @@
+use std::{collections::HashMap, io::Write};
+
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    config::{ComputePlatform, Config, NotificationConfig, NotificationSink},
+    cscs::{
+        api_client::types::{Job, JobId, JobStatus, LogStream},
+        handlers::{cscs_job_list, cscs_job_log_from},
+    },
+    trace_dbg,
+};
+
+/// Something that happened to a job that the user should know about
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub job_id: JobId,
+    pub title: String,
+    pub message: String,
+}
+
+/// How far a job log has been searched for patterns
+#[derive(Default)]
+struct LogCursor {
+    offset: usize,
+    /// start of a line that hasn't been completely written yet
+    pending: String,
+}
+
+/// Watches all jobs of the user for status changes and log lines matching the configured patterns
+pub struct JobWatcher {
+    config: NotificationConfig,
+    patterns: Vec<(Regex, Option<LogStream>)>,
+    system: Option<String>,
+    platform: Option<ComputePlatform>,
+    statuses: HashMap<JobId, JobStatus>,
+    logs: HashMap<(JobId, LogStream), LogCursor>,
+    /// whether the jobs existing before watching started are known
+    initialized: bool,
+}
+
+impl JobWatcher {
+    pub fn new(config: NotificationConfig, system: Option<String>, platform: Option<ComputePlatform>) -> Result<Self> {
+        let patterns = config
+            .log_patterns
+            .iter()
+            .map(|p| {
+                Regex::new(&p.pattern)
+                    .wrap_err(format!("invalid log pattern {}", p.pattern))
+                    .map(|r| (r, p.stream))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            config,
+            patterns,
+            system,
+            platform,
+            statuses: HashMap::new(),
+            logs: HashMap::new(),
+            initialized: false,
+        })
+    }
+
+    pub fn config(&self) -> &NotificationConfig {
+        &self.config
+    }
+
+    /// Checks all jobs for changes since the last check.
+    ///
+    /// The first check only records the current state, so existing jobs and logs don't cause notifications.
+    pub async fn check(&mut self, config: &Config) -> Result<Vec<Notification>> {
+        let jobs = cscs_job_list(config, None, self.system.clone(), self.platform.clone()).await?;
+        let (mut notifications, logs) = self.check_statuses(jobs);
+        for (job, from_start) in logs {
+            for stream in [LogStream::Stdout, LogStream::Stderr] {
+                match self.check_log(config, &job, stream, from_start).await {
+                    Ok(matches) => notifications.extend(matches),
+                    Err(e) => {
+                        let _ = trace_dbg!(e);
+                    }
+                }
+            }
+        }
+        Ok(notifications)
+    }
+
+    /// Records the statuses of the jobs, returning notifications for changes and the jobs whose logs to search,
+    /// with whether to search them from the start
+    fn check_statuses(&mut self, jobs: Vec<Job>) -> (Vec<Notification>, Vec<(Job, bool)>) {
+        let mut notifications = vec![];
+        let mut logs = vec![];
+        for job in jobs {
+            let previous = self.statuses.insert(job.id.clone(), job.status.clone());
+            // a job that started after we started watching, it may already be done by the time we see it
+            let new = previous.is_none() && self.initialized;
+            let notify = self.config.statuses.is_empty() || self.config.statuses.contains(&job.status);
+            match &previous {
+                Some(previous) if *previous != job.status && notify => notifications.push(Notification {
+                    job_id: job.id.clone(),
+                    title: format!("Job {} {}", job.name, job.status),
+                    message: format!(
+                        "Job {} ({}) changed from {} to {}",
+                        job.name, job.id, previous, job.status
+                    ),
+                }),
+                None if new && job.status.is_terminal() && notify => notifications.push(Notification {
+                    job_id: job.id.clone(),
+                    title: format!("Job {} {}", job.name, job.status),
+                    message: format!("Job {} ({}) {}", job.name, job.id, job.status),
+                }),
+                _ => {}
+            }
+            // also check jobs that just stopped running, to catch their last output
+            if job.status == JobStatus::Running
+                || previous == Some(JobStatus::Running)
+                || (new && job.status.is_terminal())
+            {
+                // a job that started after we started watching is searched from the beginning of its logs
+                let from_start = self.initialized || previous.is_some();
+                logs.push((job, from_start));
+            }
+        }
+        self.initialized = true;
+        (notifications, logs)
+    }
+
+    fn log_patterns(&self, stream: LogStream) -> Vec<&Regex> {
+        self.patterns
+            .iter()
+            .filter(|(_, s)| s.is_none_or(|s| s == stream))
+            .map(|(r, _)| r)
+            .collect()
+    }
+
+    async fn check_log(
+        &mut self,
+        config: &Config,
+        job: &Job,
+        stream: LogStream,
+        from_start: bool,
+    ) -> Result<Vec<Notification>> {
+        if self.log_patterns(stream).is_empty() {
+            return Ok(vec![]);
+        }
+        let offset = match self.logs.get(&(job.id.clone(), stream)) {
+            Some(cursor) => Some(cursor.offset),
+            None if from_start => Some(0),
+            None => None,
+        };
+        let update = cscs_job_log_from(
+            config,
+            job.id.clone(),
+            stream,
+            offset,
+            None,
+            self.system.clone(),
+            self.platform.clone(),
+        )
+        .await?;
+        Ok(self.match_log(job, stream, update.offset, &update.content))
+    }
+
+    /// Searches newly written log output for the patterns, `offset` is where the next output starts
+    fn match_log(&mut self, job: &Job, stream: LogStream, offset: usize, content: &str) -> Vec<Notification> {
+        let cursor = self.logs.entry((job.id.clone(), stream)).or_default();
+        cursor.offset = offset;
+        cursor.pending.push_str(content);
+        let Some(last_newline) = cursor.pending.rfind('\n') else {
+            return vec![];
+        };
+        let complete: String = cursor.pending.drain(..=last_newline).collect();
+        // only notify once per pattern and check, so a log full of matches doesn't cause a flood of notifications
+        self.log_patterns(stream)
+            .into_iter()
+            .filter_map(|pattern| {
+                complete
+                    .lines()
+                    .find(|line| pattern.is_match(line))
+                    .map(|line| Notification {
+                        job_id: job.id.clone(),
+                        title: format!("Job {} {} matched '{}'", job.name, stream, pattern.as_str()),
+                        message: line.trim().to_owned(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Sends a notification to all configured sinks
+pub async fn send_notification(config: &NotificationConfig, notification: &Notification) -> Result<()> {
+    for sink in config.sinks.iter() {
+        match sink {
+            NotificationSink::Bell => {
+                print!("\x07");
+                std::io::stdout().flush()?;
+            }
+            NotificationSink::Desktop => desktop_notification(&notification.title, &notification.message).await?,
+            NotificationSink::Webhook => {
+                let url = config
+                    .webhook_url
+                    .as_ref()
+                    .ok_or(eyre!("no webhook url configured"))
+                    .suggestion("set `notifications.webhook_url` in your coman.toml")?;
+                reqwest::Client::new()
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_string(notification)?)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .wrap_err("couldn't call notification webhook")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+async fn desktop_notification(title: &str, message: &str) -> Result<()> {
+    // pass the texts as arguments so they don't need to be escaped for applescript
+    tokio::process::Command::new("osascript")
+        .args([
+            "-e",
+            "on run argv",
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+            "-e",
+            "end run",
+            title,
+            message,
+        ])
+        .status()
+        .await
+        .wrap_err("couldn't show desktop notification")?;
+    Ok(())
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
+async fn desktop_notification(title: &str, message: &str) -> Result<()> {
+    tokio::process::Command::new("notify-send")
+        .args(["--app-name=coman", title, message])
+        .status()
+        .await
+        .wrap_err("couldn't show desktop notification")
+        .suggestion("make sure `notify-send` (libnotify) is installed")?;
+    Ok(())
+}
+
+#[cfg(target_family = "windows")]
+async fn desktop_notification(_title: &str, _message: &str) -> Result<()> {
+    Err(eyre!("desktop notifications are not supported on windows"))
+        .suggestion("use the bell or webhook notification sinks instead")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LogPattern;
+
+    fn job(id: &str, status: JobStatus) -> Job {
+        Job {
+            id: JobId::new(id.to_owned()),
+            name: format!("job{id}"),
+            status,
+            user: "user".to_owned(),
+            start_date: None,
+            end_date: None,
+        }
+    }
+
+    fn watcher(statuses: Vec<JobStatus>) -> JobWatcher {
+        let config = NotificationConfig {
+            statuses,
+            log_patterns: vec![LogPattern {
+                pattern: "loss=nan".to_owned(),
+                stream: Some(LogStream::Stdout),
+            }],
+            ..Default::default()
+        };
+        JobWatcher::new(config, None, None).unwrap()
+    }
+
+    #[test]
+    fn test_check_statuses() {
+        let mut watcher = watcher(vec![JobStatus::Failed, JobStatus::Finished]);
+        // jobs that exist when watching starts don't notify
+        let (notifications, logs) = watcher.check_statuses(vec![job("1", JobStatus::Pending)]);
+        assert!(notifications.is_empty());
+        assert!(logs.is_empty());
+
+        let (notifications, logs) = watcher.check_statuses(vec![job("1", JobStatus::Running)]);
+        // running isn't one of the configured statuses, but its logs are searched
+        assert!(notifications.is_empty());
+        assert_eq!(logs.len(), 1);
+
+        // a job that finished between two checks notifies too
+        let (notifications, logs) =
+            watcher.check_statuses(vec![job("1", JobStatus::Failed), job("2", JobStatus::Finished)]);
+        let messages: Vec<_> = notifications.iter().map(|n| n.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["Job job1 (1) changed from Running to Failed", "Job job2 (2) Finished"]
+        );
+        assert_eq!(
+            logs.iter()
+                .map(|(job, from_start)| (job.id.as_ref(), *from_start))
+                .collect::<Vec<_>>(),
+            [("1", true), ("2", true)]
+        );
+
+        let (notifications, logs) =
+            watcher.check_statuses(vec![job("1", JobStatus::Failed), job("2", JobStatus::Finished)]);
+        assert!(notifications.is_empty());
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn test_match_log() {
+        let mut watcher = watcher(vec![]);
+        let job = job("1", JobStatus::Running);
+        assert!(
+            watcher
+                .match_log(&job, LogStream::Stdout, 10, "step 1 loss=")
+                .is_empty()
+        );
+        let notifications = watcher.match_log(&job, LogStream::Stdout, 30, "nan\nstep 2 loss=nan\n");
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].message, "step 1 loss=nan");
+        assert_eq!(watcher.logs[&(job.id.clone(), LogStream::Stdout)].offset, 30);
+        // the pattern only applies to stdout
+        assert!(watcher.match_log(&job, LogStream::Stderr, 10, "loss=nan\n").is_empty());
+    }
+}
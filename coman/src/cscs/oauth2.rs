@@ -1,10 +1,11 @@
 #![allow(dead_code)]
 use std::time::Duration;
 
-use color_eyre::Result;
+use color_eyre::{Result, Section};
+use eyre::eyre;
 use openidconnect::{
     AdditionalProviderMetadata, ClientId, ClientSecret, CsrfToken, DeviceAuthorizationUrl, IssuerUrl, Nonce,
-    OAuth2TokenResponse, PkceCodeChallenge, PkceCodeVerifier, ProviderMetadata, RedirectUrl, Scope,
+    OAuth2TokenResponse, PkceCodeChallenge, PkceCodeVerifier, ProviderMetadata, RedirectUrl, RefreshToken, Scope,
     core::{
         CoreAuthDisplay, CoreAuthenticationFlow, CoreClaimName, CoreClaimType, CoreClient, CoreClientAuthMethod,
         CoreDeviceAuthorizationResponse, CoreGrantType, CoreJsonWebKey, CoreJweContentEncryptionAlgorithm,
@@ -16,18 +17,62 @@ use openidconnect::{
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
-use crate::util::keyring::Secret;
+use crate::{
+    config::{SiteConfig, active_site},
+    util::secrets::Secret,
+};
 
 pub const CLIENT_ID_SECRET_NAME: &str = "cscs_client_id";
 pub const CLIENT_SECRET_SECRET_NAME: &str = "cscs_secret_id";
 
 pub const ACCESS_TOKEN_SECRET_NAME: &str = "cscs_access_token";
+pub const ACCESS_TOKEN_EXPIRY_SECRET_NAME: &str = "cscs_access_token_expiry";
 pub const REFRESH_TOKEN_SECRET_NAME: &str = "cscs_refresh_token";
 
-const CSCS_URL: &str = "https://auth.cscs.ch/auth/realms/firecrest-clients";
-const CSCS_CLIENT_ID: &str = "67905e6e-8edf-4190-ae47-110f61c833ed";
+/// Auth server of the builtin `cscs` site, other sites configure their own `auth_url`
+pub(crate) const CSCS_URL: &str = "https://auth.cscs.ch/auth/realms/firecrest-clients";
+/// Public client of coman at CSCS, used for device logins
+pub(crate) const CSCS_CLIENT_ID: &str = "67905e6e-8edf-4190-ae47-110f61c833ed";
+/// Scopes the CSCS auth server needs to hand out tokens for FirecREST
+pub(crate) const CSCS_SCOPES: [&str; 3] = ["profile", "firecrest", "firecrest-v2"];
 const TIMEOUT: u64 = 60;
 
+/// Tokens returned by the CSCS auth server after a successful login
+#[derive(Clone, Debug)]
+pub(crate) struct LoginTokens {
+    pub access_token: Secret,
+    pub refresh_token: Option<Secret>,
+    /// how long the access token stays valid, if the server told us
+    pub expires_in: Option<Duration>,
+}
+
+impl<T: OAuth2TokenResponse> From<&T> for LoginTokens {
+    fn from(token: &T) -> Self {
+        Self {
+            access_token: Secret::new(token.access_token().secret().to_owned()),
+            refresh_token: token.refresh_token().map(|t| Secret::new(t.secret().to_owned())),
+            expires_in: token.expires_in(),
+        }
+    }
+}
+
+/// The scopes to request from the auth server of the site
+fn scopes(site: &SiteConfig) -> Vec<Scope> {
+    site.scopes.iter().map(|s| Scope::new(s.clone())).collect()
+}
+
+/// The public client to log in with at the site, only sites supporting device logins have one
+fn device_client_id(site: &SiteConfig) -> Result<ClientId> {
+    site.device_client_id
+        .clone()
+        .map(ClientId::new)
+        .ok_or(eyre!(
+            "site {} doesn't support logging in with your own account",
+            active_site()
+        ))
+        .suggestion("set `device_client_id` for the site in your coman.toml, or log in with a client id and secret")
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct DeviceEndpointProviderMetadata {
     device_authorization_endpoint: DeviceAuthorizationUrl,
@@ -47,29 +92,23 @@ type DeviceProviderMetadata = ProviderMetadata<
     CoreResponseType,
     CoreSubjectIdentifierType,
 >;
-pub(crate) async fn start_cscs_device_login() -> Result<(CoreDeviceAuthorizationResponse, String)> {
+pub(crate) async fn start_cscs_device_login(site: &SiteConfig) -> Result<(CoreDeviceAuthorizationResponse, String)> {
     let http_client = reqwest::ClientBuilder::new()
         .redirect(reqwest::redirect::Policy::none())
         .build()
         .expect("Client should build");
     let provider_metadata =
-        DeviceProviderMetadata::discover_async(IssuerUrl::new(CSCS_URL.to_string())?, &http_client).await?;
+        DeviceProviderMetadata::discover_async(IssuerUrl::new(site.auth_url.clone())?, &http_client).await?;
     let device_url = provider_metadata
         .additional_metadata()
         .device_authorization_endpoint
         .clone();
-    let client = CoreClient::from_provider_metadata(
-        provider_metadata.clone(),
-        ClientId::new(CSCS_CLIENT_ID.to_string()),
-        None,
-    )
-    .set_device_authorization_url(device_url.clone())
-    .set_auth_type(openidconnect::AuthType::RequestBody);
+    let client = CoreClient::from_provider_metadata(provider_metadata.clone(), device_client_id(site)?, None)
+        .set_device_authorization_url(device_url.clone())
+        .set_auth_type(openidconnect::AuthType::RequestBody);
     let details: CoreDeviceAuthorizationResponse = client
         .exchange_device_code()
-        .add_scope(Scope::new("profile".to_string()))
-        .add_scope(Scope::new("firecrest".to_string()))
-        .add_scope(Scope::new("firecrest-v2".to_string()))
+        .add_scopes(scopes(site))
         .request_async(&http_client)
         .await?;
     let verify_url = details
@@ -80,47 +119,38 @@ pub(crate) async fn start_cscs_device_login() -> Result<(CoreDeviceAuthorization
 }
 
 pub(crate) async fn finish_cscs_device_login(
+    site: &SiteConfig,
     device_details: CoreDeviceAuthorizationResponse,
-) -> Result<(Secret, Option<Secret>)> {
+) -> Result<LoginTokens> {
     let http_client = reqwest::ClientBuilder::new()
         .redirect(reqwest::redirect::Policy::none())
         .build()
         .expect("Client should build");
     let provider_metadata =
-        DeviceProviderMetadata::discover_async(IssuerUrl::new(CSCS_URL.to_string())?, &http_client).await?;
+        DeviceProviderMetadata::discover_async(IssuerUrl::new(site.auth_url.clone())?, &http_client).await?;
     let device_url = provider_metadata
         .additional_metadata()
         .device_authorization_endpoint
         .clone();
-    let client = CoreClient::from_provider_metadata(
-        provider_metadata.clone(),
-        ClientId::new(CSCS_CLIENT_ID.to_string()),
-        None,
-    )
-    .set_device_authorization_url(device_url.clone())
-    .set_auth_type(openidconnect::AuthType::RequestBody);
+    let client = CoreClient::from_provider_metadata(provider_metadata.clone(), device_client_id(site)?, None)
+        .set_device_authorization_url(device_url.clone())
+        .set_auth_type(openidconnect::AuthType::RequestBody);
     let token = client
         .exchange_device_access_token(&device_details)?
         .request_async(&http_client, tokio::time::sleep, Some(Duration::from_secs(TIMEOUT)))
         .await?;
-    let access_token = token.access_token().secret().to_owned();
-    let refresh_token = token.refresh_token().map(|t| t.secret().to_owned());
-    Ok((Secret::new(access_token), refresh_token.map(Secret::new)))
+    Ok(LoginTokens::from(&token))
 }
-pub(crate) async fn start_cscs_pkce_login() -> Result<(PkceCodeVerifier, Nonce, Url)> {
+pub(crate) async fn start_cscs_pkce_login(site: &SiteConfig) -> Result<(PkceCodeVerifier, Nonce, Url)> {
     let http_client = reqwest::ClientBuilder::new()
         .redirect(reqwest::redirect::Policy::none())
         .build()
         .expect("Client should build");
     let provider_metadata =
-        CoreProviderMetadata::discover_async(IssuerUrl::new(CSCS_URL.to_string())?, &http_client).await?;
-    let client = CoreClient::from_provider_metadata(
-        provider_metadata.clone(),
-        ClientId::new(CSCS_CLIENT_ID.to_string()),
-        None,
-    )
-    .set_redirect_uri(RedirectUrl::new("http://localhost:54321".to_string())?)
-    .set_auth_type(openidconnect::AuthType::RequestBody);
+        CoreProviderMetadata::discover_async(IssuerUrl::new(site.auth_url.clone())?, &http_client).await?;
+    let client = CoreClient::from_provider_metadata(provider_metadata.clone(), device_client_id(site)?, None)
+        .set_redirect_uri(RedirectUrl::new("http://localhost:54321".to_string())?)
+        .set_auth_type(openidconnect::AuthType::RequestBody);
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
     let (auth_url, _, nonce) = client
         .authorize_url(
@@ -128,24 +158,23 @@ pub(crate) async fn start_cscs_pkce_login() -> Result<(PkceCodeVerifier, Nonce,
             CsrfToken::new_random,
             Nonce::new_random,
         )
-        .add_scope(Scope::new("profile".to_string()))
-        .add_scope(Scope::new("firecrest".to_string()))
-        .add_scope(Scope::new("firecrest-v2".to_string()))
+        .add_scopes(scopes(site))
         .set_pkce_challenge(pkce_challenge)
         .url();
     Ok((pkce_verifier, nonce, auth_url))
 }
 
 pub(crate) async fn client_credentials_login(
+    site: &SiteConfig,
     client_id: Secret,
     client_secret: Secret,
-) -> Result<(Secret, Option<Secret>)> {
+) -> Result<LoginTokens> {
     let http_client = reqwest::ClientBuilder::new()
         .redirect(reqwest::redirect::Policy::none())
         .build()
         .expect("Client should build");
     let provider_metadata =
-        CoreProviderMetadata::discover_async(IssuerUrl::new(CSCS_URL.to_string())?, &http_client).await?;
+        CoreProviderMetadata::discover_async(IssuerUrl::new(site.auth_url.clone())?, &http_client).await?;
     let client = CoreClient::from_provider_metadata(
         provider_metadata,
         ClientId::new(client_id.0.to_owned()),
@@ -155,12 +184,28 @@ pub(crate) async fn client_credentials_login(
 
     let token = client
         .exchange_client_credentials()?
-        .add_scope(Scope::new("profile".to_string()))
-        .add_scope(Scope::new("firecrest".to_string()))
-        .add_scope(Scope::new("firecrest-v2".to_string()))
+        .add_scopes(scopes(site))
+        .request_async(&http_client)
+        .await?;
+    Ok(LoginTokens::from(&token))
+}
+
+/// Exchanges the refresh token of a device login for a new access token.
+///
+/// The auth server may rotate the refresh token, in which case the new one is part of the returned tokens.
+pub(crate) async fn refresh_token_login(site: &SiteConfig, refresh_token: Secret) -> Result<LoginTokens> {
+    let http_client = reqwest::ClientBuilder::new()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Client should build");
+    let provider_metadata =
+        CoreProviderMetadata::discover_async(IssuerUrl::new(site.auth_url.clone())?, &http_client).await?;
+    let client = CoreClient::from_provider_metadata(provider_metadata, device_client_id(site)?, None)
+        .set_auth_type(openidconnect::AuthType::RequestBody);
+
+    let token = client
+        .exchange_refresh_token(&RefreshToken::new(refresh_token.0))?
         .request_async(&http_client)
         .await?;
-    let access_token = token.access_token().secret().to_owned();
-    let refresh_token = token.refresh_token().map(|t| t.secret().to_owned());
-    Ok((Secret::new(access_token), refresh_token.map(Secret::new)))
+    Ok(LoginTokens::from(&token))
 }
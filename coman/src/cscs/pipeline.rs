@@ -0,0 +1,276 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, get_data_dir},
+    cscs::{
+        api_client::{
+            client::{JobStartOptions, SlurmOptions},
+            types::{JobDependency, JobId},
+        },
+        handlers::{cscs_job_details, cscs_job_start},
+    },
+};
+
+/// A step of a pipeline, submitted as its own job
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PipelineStep {
+    pub image: Option<String>,
+    pub command: Option<Vec<String>>,
+    pub workdir: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub mount: BTreeMap<String, String>,
+    #[serde(default)]
+    pub port_forward: Vec<u16>,
+    #[serde(default)]
+    pub no_ssh: bool,
+    #[serde(flatten)]
+    pub slurm: SlurmOptions,
+    /// steps that have to be started before this step starts
+    #[serde(default)]
+    pub after: Vec<String>,
+    /// steps that have to finish successfully before this step starts
+    #[serde(default)]
+    pub after_ok: Vec<String>,
+}
+
+impl PipelineStep {
+    fn dependencies(&self) -> impl Iterator<Item = &String> {
+        self.after.iter().chain(self.after_ok.iter())
+    }
+}
+
+/// A DAG of jobs, read from a pipeline toml file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pipeline {
+    pub name: String,
+    pub system: Option<String>,
+    pub account: Option<String>,
+    pub steps: BTreeMap<String, PipelineStep>,
+}
+
+impl Pipeline {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).wrap_err(format!("couldn't read pipeline file {}", path.display()))?;
+        toml::from_str(&content).wrap_err("couldn't parse pipeline file")
+    }
+
+    /// Names of the steps in an order where every step comes after the steps it depends on
+    pub fn submission_order(&self) -> Result<Vec<&str>> {
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        for (name, step) in self.steps.iter() {
+            if let Some(unknown) = step.dependencies().find(|d| !self.steps.contains_key(*d)) {
+                return Err(eyre!("step {} depends on unknown step {}", name, unknown));
+            }
+            remaining.insert(name, step.dependencies().count());
+        }
+        let mut order = vec![];
+        while !remaining.is_empty() {
+            let mut ready: Vec<&str> = remaining
+                .iter()
+                .filter(|(_, count)| **count == 0)
+                .map(|(name, _)| *name)
+                .collect();
+            if ready.is_empty() {
+                return Err(eyre!("pipeline steps have circular dependencies"))
+                    .suggestion(format!("check the dependencies of {}", remaining.keys().join(", ")));
+            }
+            ready.sort();
+            for name in ready {
+                remaining.remove(name);
+                for (other, count) in remaining.iter_mut() {
+                    *count -= self.steps[*other].dependencies().filter(|d| *d == name).count();
+                }
+                order.push(name);
+            }
+        }
+        Ok(order)
+    }
+}
+
+/// The jobs submitted for a pipeline, stored in the data dir so the pipeline can be followed later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRun {
+    pub name: String,
+    pub system: String,
+    pub submitted: DateTime<Local>,
+    /// step name and job id, in submission order
+    pub steps: Vec<(String, String)>,
+}
+
+impl PipelineRun {
+    fn runs_dir() -> PathBuf {
+        get_data_dir().join("pipelines")
+    }
+
+    fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(Self::runs_dir())?;
+        std::fs::write(
+            Self::runs_dir().join(format!("{}.json", self.name)),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Loads the last run of the pipeline with the given name, or the last run of any pipeline
+    pub fn load(name: Option<String>) -> Result<Self> {
+        let path = match name {
+            Some(name) => Self::runs_dir().join(format!("{name}.json")),
+            None => std::fs::read_dir(Self::runs_dir())
+                .ok()
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+                .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())
+                .map(|e| e.path())
+                .ok_or(eyre!("no pipeline has been run yet"))?,
+        };
+        let content = std::fs::read_to_string(&path)
+            .wrap_err(format!("couldn't read pipeline run {}", path.display()))
+            .suggestion("start the pipeline with `coman pipeline run`")?;
+        serde_json::from_str(&content).wrap_err("couldn't parse pipeline run")
+    }
+}
+
+/// Status of a single step of a pipeline run
+#[derive(Debug, Clone, Serialize, tabled::Tabled)]
+pub struct PipelineStepStatus {
+    pub step: String,
+    pub job_id: String,
+    pub status: String,
+    pub start_date: String,
+    pub end_date: String,
+}
+
+/// Submits all steps of a pipeline, wiring up slurm dependencies between them
+pub async fn pipeline_run(path: &Path) -> Result<PipelineRun> {
+    let pipeline = Pipeline::from_file(path)?;
+    let order = pipeline.submission_order()?;
+    let config = Config::new()?;
+    let mut run = PipelineRun {
+        name: pipeline.name.clone(),
+        system: pipeline
+            .system
+            .clone()
+            .unwrap_or(config.values.cscs.current_system.clone()),
+        submitted: Local::now(),
+        steps: vec![],
+    };
+    let mut job_ids: HashMap<&str, JobId> = HashMap::new();
+    for name in order {
+        let step = pipeline.steps[name].clone();
+        let mut dependencies: Vec<_> = step
+            .after
+            .iter()
+            .map(|d| JobDependency::After(job_ids[d.as_str()].clone()))
+            .collect();
+        dependencies.extend(
+            step.after_ok
+                .iter()
+                .map(|d| JobDependency::AfterOk(job_ids[d.as_str()].clone())),
+        );
+        let options = JobStartOptions {
+            image: step.image.map(|i| i.try_into()).transpose()?,
+            command: step.command,
+            container_workdir: step.workdir,
+            env: step.env.into_iter().collect(),
+            mount: step.mount.into_iter().collect(),
+            port_forward: step.port_forward,
+            no_ssh: step.no_ssh,
+            slurm: step.slurm,
+            dependencies,
+            ..Default::default()
+        };
+        println!("Submitting step {name}");
+        let job_id = cscs_job_start(
+            &config,
+            Some(format!("{}-{}", pipeline.name, name)),
+            options,
+            Some(run.system.clone()),
+            None,
+            pipeline.account.clone(),
+        )
+        .await
+        .wrap_err(format!("couldn't submit step {name}"))?;
+        run.steps.push((name.to_owned(), job_id.to_string()));
+        // save after every step, so already submitted steps can be followed even if a later one fails
+        run.save()?;
+        job_ids.insert(name, job_id);
+    }
+    Ok(run)
+}
+
+/// Gets the current status of all steps of the last run of a pipeline
+pub async fn pipeline_status(name: Option<String>) -> Result<(PipelineRun, Vec<PipelineStepStatus>)> {
+    let run = PipelineRun::load(name)?;
+    let config = Config::new()?;
+    let mut statuses = vec![];
+    for (step, job_id) in run.steps.iter() {
+        let job = cscs_job_details(&config, JobId::from(job_id.as_str()), Some(run.system.clone()), None).await?;
+        let format_date = |d: Option<DateTime<Local>>| d.map(|d| d.to_string()).unwrap_or_default();
+        statuses.push(PipelineStepStatus {
+            step: step.clone(),
+            job_id: job_id.clone(),
+            status: job
+                .as_ref()
+                .map(|j| j.status.to_string())
+                .unwrap_or("Unknown".to_owned()),
+            start_date: format_date(job.as_ref().and_then(|j| j.start_date)),
+            end_date: format_date(job.as_ref().and_then(|j| j.end_date)),
+        });
+    }
+    Ok((run, statuses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline_submission_order() {
+        let pipeline: Pipeline = toml::from_str(
+            r#"
+name = "train"
+
+[steps.evaluate]
+command = ["python", "eval.py"]
+after_ok = ["train"]
+
+[steps.train]
+command = ["python", "train.py"]
+nodes = 2
+after_ok = ["preprocess"]
+
+[steps.preprocess]
+command = ["python", "prep.py"]
+"#,
+        )
+        .expect("couldn't parse pipeline");
+        assert_eq!(pipeline.steps["train"].slurm.nodes, Some(2));
+        assert_eq!(
+            pipeline.submission_order().unwrap(),
+            vec!["preprocess", "train", "evaluate"]
+        );
+
+        let mut cyclic = pipeline.clone();
+        cyclic
+            .steps
+            .get_mut("preprocess")
+            .unwrap()
+            .after
+            .push("evaluate".to_owned());
+        assert!(cyclic.submission_order().is_err());
+    }
+}
@@ -4,10 +4,9 @@ use color_eyre::{
     Section,
     eyre::{Context, Report, Result, eyre},
 };
-use futures::StreamExt;
 use openidconnect::core::CoreDeviceAuthorizationResponse;
 use strum::VariantArray;
-use tokio::{fs::File, io::AsyncWriteExt, sync::mpsc, time::Instant};
+use tokio::{sync::mpsc, time::Instant};
 use tuirealm::{
     event::Event,
     listener::{PollAsync, PortResult},
@@ -15,27 +14,38 @@ use tuirealm::{
 
 use crate::{
     app::user_events::{CscsEvent, FileEvent, StatusEvent, UserEvent},
+    config::Config,
     cscs::{
-        api_client::types::{JobId, JobStatus},
+        api_client::types::{JobId, JobStatus, LogStream},
+        auth::set_access_token,
         handlers::{
-            cscs_file_delete, cscs_file_download, cscs_file_list, cscs_job_cancel, cscs_job_details, cscs_job_list,
-            cscs_job_log, cscs_resource_usage, cscs_system_list, file_system_roots,
+            cscs_file_delete, cscs_file_download, cscs_file_list, cscs_file_verify, cscs_job_cancel, cscs_job_details,
+            cscs_job_list, cscs_job_log_from, cscs_resource_usage, cscs_set_default_platform, cscs_system_list,
+            file_system_roots,
         },
-        oauth2::{ACCESS_TOKEN_SECRET_NAME, REFRESH_TOKEN_SECRET_NAME, finish_cscs_device_login},
+        identity::{LoginMethod, active_identity, record_login},
+        notifications::{JobWatcher, send_notification},
+        oauth2::finish_cscs_device_login,
+        s3::ranged_download,
     },
     trace_dbg,
-    util::keyring::store_secret,
 };
 
+/// Error event for a config that couldn't be loaded, ports load the config once per poll
+fn config_error(e: Report) -> Event<UserEvent> {
+    Event::User(UserEvent::Error(format!(
+        "{:?}",
+        Err::<(), Report>(e).wrap_err("couldn't load config")
+    )))
+}
+
 /// This port does the polling of the token for finishing the device code oauth2 flow
-#[allow(dead_code)]
 pub(crate) struct AsyncDeviceFlowPort {
     receiver: mpsc::Receiver<(CoreDeviceAuthorizationResponse, String)>,
     current_response: Option<CoreDeviceAuthorizationResponse>,
 }
 
 impl AsyncDeviceFlowPort {
-    #[allow(dead_code)]
     pub fn new(receiver: mpsc::Receiver<(CoreDeviceAuthorizationResponse, String)>) -> Self {
         Self {
             receiver,
@@ -54,23 +64,21 @@ impl PollAsync<UserEvent> for AsyncDeviceFlowPort {
     async fn poll(&mut self) -> PortResult<Option<Event<UserEvent>>> {
         if let Some(details) = self.current_response.clone() {
             trace_dbg!("finishing login");
-            match finish_cscs_device_login(details).await {
-                Ok(result) => {
-                    if let Err(e) = store_secret(ACCESS_TOKEN_SECRET_NAME, result.0).await {
-                        return Ok(Some(Event::User(UserEvent::Error(format!(
-                            "{:?}",
-                            Err::<(), Report>(e).wrap_err("couldn't save access token")
-                        )))));
+            let result = match Config::new().and_then(|config| config.values.current_site()) {
+                Ok(site) => finish_cscs_device_login(&site, details).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(tokens) => {
+                    self.current_response = None;
+                    set_access_token(tokens).await;
+                    if let Err(e) = record_login(&active_identity(), LoginMethod::Device) {
+                        return Ok(Some(Event::User(UserEvent::Error(format!("{:?}", e)))));
                     }
-                    if let Some(refresh_token) = result.1
-                        && let Err(e) = store_secret(REFRESH_TOKEN_SECRET_NAME, refresh_token).await
-                    {
-                        return Ok(Some(Event::User(UserEvent::Error(format!(
-                            "{:?}",
-                            Err::<(), Report>(e).wrap_err("couldn't save refresh token")
-                        )))));
+                    // don't override platform if it's already set
+                    if let Err(e) = cscs_set_default_platform().await {
+                        return Ok(Some(Event::User(UserEvent::Error(format!("{:?}", e)))));
                     }
-                    self.current_response = None;
                     Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::LoggedIn))))
                 }
                 Err(e) => {
@@ -128,7 +136,11 @@ impl PollAsync<UserEvent> for AsyncFetchWorkloadsPort {
         {
             self.filter = val;
         }
-        match cscs_job_list(Some(self.filter.clone()), None, None).await {
+        let config = match Config::new() {
+            Ok(config) => config,
+            Err(e) => return Ok(Some(config_error(e))),
+        };
+        match cscs_job_list(&config, Some(self.filter.clone()), None, None).await {
             Ok(jobs) => Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::GotWorkloadData(jobs))))),
             Err(e) => {
                 let _ = trace_dbg!(e);
@@ -138,6 +150,61 @@ impl PollAsync<UserEvent> for AsyncFetchWorkloadsPort {
     }
 }
 
+/// This port watches all jobs and sends notifications as configured in `[notifications]`
+pub(crate) struct AsyncNotificationPort {
+    watcher: Option<JobWatcher>,
+}
+
+impl AsyncNotificationPort {
+    pub fn new() -> Self {
+        Self { watcher: None }
+    }
+}
+
+#[tuirealm::async_trait]
+impl PollAsync<UserEvent> for AsyncNotificationPort {
+    async fn poll(&mut self) -> PortResult<Option<Event<UserEvent>>> {
+        if self.watcher.is_none() {
+            let watcher = Config::new().and_then(|config| JobWatcher::new(config.values.notifications, None, None));
+            match watcher {
+                Ok(watcher) => self.watcher = Some(watcher),
+                Err(e) => {
+                    return Ok(Some(Event::User(UserEvent::Error(format!(
+                        "{:?}",
+                        Err::<(), Report>(e).wrap_err("couldn't set up notifications")
+                    )))));
+                }
+            }
+        }
+        let watcher = self.watcher.as_mut().unwrap();
+        if watcher.config().sinks.is_empty() {
+            return Ok(Some(Event::None));
+        }
+        let config = match Config::new() {
+            Ok(config) => config,
+            Err(e) => return Ok(Some(config_error(e))),
+        };
+        let notifications = match watcher.check(&config).await {
+            Ok(notifications) => notifications,
+            Err(e) => {
+                let _ = trace_dbg!(e);
+                return Ok(Some(Event::None));
+            }
+        };
+        for notification in notifications.iter() {
+            if let Err(e) = send_notification(watcher.config(), notification).await {
+                let _ = trace_dbg!(e);
+            }
+        }
+        match notifications.last() {
+            Some(notification) => Ok(Some(Event::User(UserEvent::Status(StatusEvent::Info(
+                notification.title.clone(),
+            ))))),
+            None => Ok(Some(Event::None)),
+        }
+    }
+}
+
 /// This port handles getting available compute systems from CSCS
 pub(crate) struct AsyncSelectSystemPort {
     receiver: mpsc::Receiver<()>,
@@ -153,7 +220,11 @@ impl AsyncSelectSystemPort {
 impl PollAsync<UserEvent> for AsyncSelectSystemPort {
     async fn poll(&mut self) -> PortResult<Option<Event<UserEvent>>> {
         if self.receiver.recv().await.is_some() {
-            match cscs_system_list(None).await {
+            let config = match Config::new() {
+                Ok(config) => config,
+                Err(e) => return Ok(Some(config_error(e))),
+            };
+            match cscs_system_list(&config, None).await {
                 Ok(systems) => Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::SelectSystemList(systems))))),
                 Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
                     "{:?}",
@@ -174,11 +245,25 @@ pub enum JobLogAction {
     Stop,
 }
 
+/// How much of the end of a log is loaded when opening it, older output can be downloaded
+const LOG_PAGER_TAIL_BYTES: usize = 1024 * 1024;
+/// How much of a log is read in one poll, so a job writing a lot of output doesn't block the port
+const LOG_PAGER_POLL_BYTES: usize = 256 * 1024;
+
 /// This port handles polling the logs of a CSCS job
+///
+/// The log is read incrementally, so only output written since the last poll is sent. Opening a log starts near
+/// its end instead of reading all of it.
 pub(crate) struct AsyncJobLogPort {
     receiver: mpsc::Receiver<JobLogAction>,
     current_job: Option<JobId>,
     stderr: bool,
+    /// where to continue reading, `None` until the log was opened
+    offset: Option<usize>,
+    /// whether reading started in the middle of a line, which is dropped
+    skip_partial_line: bool,
+    /// set when reading the log failed, to not spam errors until the log is switched or another job is opened
+    paused: bool,
 }
 
 impl AsyncJobLogPort {
@@ -187,6 +272,54 @@ impl AsyncJobLogPort {
             receiver,
             current_job: None,
             stderr: false,
+            offset: None,
+            skip_partial_line: false,
+            paused: false,
+        }
+    }
+
+    async fn read_log(
+        &mut self,
+        config: &Config,
+        job_id: JobId,
+        stream: LogStream,
+    ) -> Result<Option<(PathBuf, String)>> {
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => {
+                let end = cscs_job_log_from(config, job_id.clone(), stream, None, None, None, None)
+                    .await?
+                    .offset;
+                let start = end.saturating_sub(LOG_PAGER_TAIL_BYTES);
+                self.skip_partial_line = start > 0;
+                start
+            }
+        };
+        let update = cscs_job_log_from(
+            config,
+            job_id,
+            stream,
+            Some(offset),
+            Some(LOG_PAGER_POLL_BYTES),
+            None,
+            None,
+        )
+        .await?;
+        self.offset = Some(update.offset);
+        let mut content = update.content.as_str();
+        if self.skip_partial_line && !content.is_empty() {
+            match content.find('\n') {
+                Some(newline) => {
+                    content = &content[newline + 1..];
+                    self.skip_partial_line = false;
+                }
+                None => content = "",
+            }
+        }
+        if content.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some((update.path, content.to_owned())))
         }
     }
 }
@@ -199,30 +332,43 @@ impl PollAsync<UserEvent> for AsyncJobLogPort {
         if !self.receiver.is_empty()
             && let Some(val) = self.receiver.recv().await
         {
+            self.paused = false;
             match val {
                 JobLogAction::Job(jobid) => {
                     self.current_job = Some(jobid);
                     self.stderr = false;
+                    self.offset = None;
                 }
                 JobLogAction::SwitchLog => {
                     self.stderr = !self.stderr;
+                    self.offset = None;
                 }
                 JobLogAction::Stop => {
                     self.current_job = None;
                 }
             }
         }
-        if let Some(job_id) = &self.current_job {
-            match cscs_job_log(job_id.clone(), self.stderr, None, None).await {
-                Ok(log) => Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::GotJobLog(log))))),
+        if let Some(job_id) = self.current_job.clone()
+            && !self.paused
+        {
+            let config = match Config::new() {
+                Ok(config) => config,
+                Err(e) => return Ok(Some(config_error(e))),
+            };
+            let stream = if self.stderr {
+                LogStream::Stderr
+            } else {
+                LogStream::Stdout
+            };
+            match self.read_log(&config, job_id, stream).await {
+                Ok(None) => Ok(Some(Event::None)),
+                // tagged with the stream, so output of the previous log still in flight after a switch is ignored
+                Ok(Some((path, content))) => Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::GotJobLog(
+                    path, stream, content,
+                ))))),
                 Err(e) => {
-                    // if there was an error getting the log, if it's stderr, switch to stdout which should
-                    // always exist. If we're on stdout and it doesn't exist, unset log watching to not spam errors
-                    if self.stderr {
-                        self.stderr = false;
-                    } else {
-                        self.current_job = None;
-                    }
+                    // e.g. a job without a separate stderr, the other log can still be opened with tab
+                    self.paused = true;
                     Ok(Some(Event::User(UserEvent::Error(format!(
                         "{:?}",
                         Err::<(), Report>(e).wrap_err("couldn't get log")
@@ -273,7 +419,11 @@ impl PollAsync<UserEvent> for AsyncJobResourceUsagePort {
             }
         }
         if let Some(job_id) = &self.current_job {
-            match cscs_resource_usage(job_id.clone(), None).await {
+            let config = match Config::new() {
+                Ok(config) => config,
+                Err(e) => return Ok(Some(config_error(e))),
+            };
+            match cscs_resource_usage(&config, job_id.clone(), None, None).await {
                 Ok(ru) => Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::GotJobResourceUsage(ru))))),
                 Err(e) => Ok(Some(Event::User(UserEvent::Status(StatusEvent::Warning(format!(
                     "couldn't get resource usage: {e:?}"
@@ -304,7 +454,7 @@ impl AsyncBackgroundTaskPort {
         Self { receiver, event_tx }
     }
 }
-async fn list_files(id: PathBuf) -> Result<Option<Event<UserEvent>>> {
+async fn list_files(config: &Config, id: PathBuf) -> Result<Option<Event<UserEvent>>> {
     let id_str = id
         .clone()
         .into_os_string()
@@ -312,20 +462,21 @@ async fn list_files(id: PathBuf) -> Result<Option<Event<UserEvent>>> {
         .map_err(|_| eyre!("couldn't convert id to string".to_owned()))?;
     if id_str == "/" {
         // load file system roots
-        let subpaths = file_system_roots(None).await?;
+        let subpaths = file_system_roots(config, None).await?;
         Ok(Some(Event::User(UserEvent::File(FileEvent::List(id_str, subpaths)))))
     } else {
-        let subpaths = cscs_file_list(id, None, None).await?;
+        let subpaths = cscs_file_list(config, id, None, None).await?;
         Ok(Some(Event::User(UserEvent::File(FileEvent::List(id_str, subpaths)))))
     }
 }
 
 async fn download_file(
+    config: &Config,
     remote: PathBuf,
     local: PathBuf,
     event_tx: mpsc::Sender<UserEvent>,
 ) -> Result<Option<Event<UserEvent>>> {
-    match cscs_file_download(remote, local.clone(), None, None, None).await {
+    match cscs_file_download(config, remote.clone(), local.clone(), None, None, None).await {
         Ok(None) => Ok(Some(Event::User(UserEvent::File(FileEvent::DownloadSuccessful)))),
         Ok(Some(job_data)) => {
             // file is large, so we created a transfer job to s3 that we need to wait on
@@ -333,7 +484,7 @@ async fn download_file(
             // TODO: add status updates once we have some sort of status line update functionality
             let mut transfer_done = false;
             while !transfer_done {
-                if let Some(job) = cscs_job_details(job_data.0.clone(), None, None).await? {
+                if let Some(job) = cscs_job_details(config, job_data.0.clone(), None, None).await? {
                     match job.status {
                         JobStatus::Pending | JobStatus::Running | JobStatus::Requeued => {
                             event_tx
@@ -359,27 +510,37 @@ async fn download_file(
             }
 
             // download from s3
-
-            let mut output = File::create(local).await?;
-            let mut stream = reqwest::get(job_data.1).await?.bytes_stream();
+            let parallelism = config.values.cscs.transfer_parallelism();
             let mut start_time = Instant::now();
             let mut progress = 0;
-            while let Some(chunk_result) = stream.next().await {
-                let chunk = chunk_result?;
-                output.write_all(&chunk).await?;
-                progress += chunk.len();
-
+            if let Err(e) = ranged_download(&job_data.1, &local, job_data.2 as u64, parallelism, |bytes| {
+                progress += bytes as usize;
                 if start_time.elapsed() >= Duration::from_millis(500) {
-                    event_tx
-                        .send(UserEvent::Status(StatusEvent::Progress(
-                            "Downloading".to_owned(),
-                            100 * progress / job_data.2,
-                        )))
-                        .await?;
+                    // the status line is best effort, so don't fail the download if the channel is full
+                    let _ = event_tx.try_send(UserEvent::Status(StatusEvent::Progress(
+                        "Downloading".to_owned(),
+                        100 * progress / job_data.2,
+                    )));
                     start_time = Instant::now();
                 }
+                Ok(())
+            })
+            .await
+            {
+                return Ok(Some(Event::User(UserEvent::Error(format!(
+                    "{:?}",
+                    Err::<(), Report>(e).wrap_err("couldn't download file from s3")
+                )))));
+            }
+            event_tx
+                .send(UserEvent::Status(StatusEvent::Info("verifying checksum".to_owned())))
+                .await?;
+            if let Err(e) = cscs_file_verify(config, remote, local, None, None).await {
+                return Ok(Some(Event::User(UserEvent::Error(format!(
+                    "{:?}",
+                    Err::<(), Report>(e)
+                )))));
             }
-            output.flush().await?;
             Ok(Some(Event::User(UserEvent::File(FileEvent::DownloadSuccessful))))
         }
         Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
@@ -388,9 +549,9 @@ async fn download_file(
         ))))),
     }
 }
-async fn delete_file(id: String) -> Result<Option<Event<UserEvent>>> {
+async fn delete_file(config: &Config, id: String) -> Result<Option<Event<UserEvent>>> {
     let remote = PathBuf::from(id.clone());
-    match cscs_file_delete(remote, None, None).await {
+    match cscs_file_delete(config, remote, false, None, None, None).await {
         Ok(()) => Ok(Some(Event::User(UserEvent::File(FileEvent::DeleteSuccessful(id))))),
         Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
             "{:?}",
@@ -406,29 +567,35 @@ impl PollAsync<UserEvent> for AsyncBackgroundTaskPort {
         }
         if let Some(action) = self.receiver.recv().await {
             let event_tx = self.event_tx.clone();
+            let config = match Config::new() {
+                Ok(config) => config,
+                Err(e) => return Ok(Some(config_error(e))),
+            };
             match action {
-                BackgroundTask::ListPaths(id) => match list_files(id).await {
+                BackgroundTask::ListPaths(id) => match list_files(&config, id).await {
                     Ok(event) => Ok(event),
                     Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
                         "{:?}",
                         Err::<(), Report>(e).wrap_err("couldn't list subpaths")
                     ))))),
                 },
-                BackgroundTask::DownloadFile(remote, local) => match download_file(remote, local, event_tx).await {
-                    Ok(event) => Ok(event),
-                    Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
-                        "{:?}",
-                        Err::<(), Report>(e).wrap_err("couldn't download file")
-                    ))))),
-                },
-                BackgroundTask::DeleteFile(remote) => match delete_file(remote).await {
+                BackgroundTask::DownloadFile(remote, local) => {
+                    match download_file(&config, remote, local, event_tx).await {
+                        Ok(event) => Ok(event),
+                        Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
+                            "{:?}",
+                            Err::<(), Report>(e).wrap_err("couldn't download file")
+                        ))))),
+                    }
+                }
+                BackgroundTask::DeleteFile(remote) => match delete_file(&config, remote).await {
                     Ok(event) => Ok(event),
                     Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
                         "{:?}",
                         Err::<(), Report>(e).wrap_err("couldn't delete file")
                     ))))),
                 },
-                BackgroundTask::GetJobDetails(job_id) => match cscs_job_details(job_id, None, None).await {
+                BackgroundTask::GetJobDetails(job_id) => match cscs_job_details(&config, job_id, None, None).await {
                     Ok(Some(details)) => Ok(Some(Event::User(UserEvent::Cscs(CscsEvent::GotJobDetails(details))))),
                     Ok(None) => Ok(Some(Event::None)),
                     Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
@@ -436,7 +603,7 @@ impl PollAsync<UserEvent> for AsyncBackgroundTaskPort {
                         Err::<(), Report>(e).wrap_err("couldn't get job details")
                     ))))),
                 },
-                BackgroundTask::CancelJob(job_id) => match cscs_job_cancel(job_id, None, None).await {
+                BackgroundTask::CancelJob(job_id) => match cscs_job_cancel(&config, job_id, None, None).await {
                     Ok(()) => Ok(Some(Event::None)),
                     Err(e) => Ok(Some(Event::User(UserEvent::Error(format!(
                         "{:?}",
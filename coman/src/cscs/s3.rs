@@ -0,0 +1,535 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::UNIX_EPOCH,
+};
+
+use color_eyre::{Result, eyre::Context};
+use eyre::eyre;
+use futures::{StreamExt, stream};
+use itertools::Itertools;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    config::get_data_dir,
+    cscs::api_client::types::{JobId, S3Upload},
+};
+
+/// Size of the ranges large files are downloaded in
+const DOWNLOAD_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Uploads a single part of a multipart upload, returning its etag
+pub(crate) async fn upload_chunk(path: PathBuf, offset: u64, size: u64, url: Url) -> Result<String> {
+    let client = reqwest::Client::new();
+
+    let mut source_file = File::open(path).await?;
+    let file_size = source_file.metadata().await?.len();
+    let size = size.min(file_size.saturating_sub(offset));
+    source_file.seek(SeekFrom::Start(offset)).await?;
+    // stream the part instead of loading it into memory, parts can be several Gb
+    let body = reqwest::Body::wrap_stream(ReaderStream::new(source_file.take(size)));
+    let req = client
+        .put(url)
+        .header(reqwest::header::CONTENT_LENGTH, size)
+        .body(body)
+        .build()?;
+    let resp = client
+        .execute(req)
+        .await?
+        .error_for_status()
+        .wrap_err("couldn't upload part")?;
+    let etag = resp
+        .headers()
+        .get("etag")
+        .ok_or(eyre!("upload response didn't contain an etag"))?;
+    Ok(etag.to_str()?.to_owned())
+}
+
+/// Uploads all parts of `transfer_data` that aren't in `etags` yet, `parallelism` parts at a time,
+/// then completes the multipart upload.
+///
+/// `on_part` is called with the part index, its etag and its size after every finished part.
+pub(crate) async fn multipart_upload<F>(
+    local: &Path,
+    size: u64,
+    transfer_data: &S3Upload,
+    parallelism: usize,
+    mut etags: BTreeMap<usize, String>,
+    mut on_part: F,
+) -> Result<()>
+where
+    F: FnMut(usize, &str, u64) -> Result<()>,
+{
+    let part_size = transfer_data.part_size;
+    let done: Vec<usize> = etags.keys().copied().collect();
+    let pending = transfer_data
+        .parts_upload_urls
+        .iter()
+        .enumerate()
+        .filter(|(part, _)| !done.contains(part))
+        .map(|(part, url)| {
+            let offset = (part as u64) * part_size;
+            let length = part_size.min(size.saturating_sub(offset));
+            async move {
+                let etag = upload_chunk(local.to_path_buf(), offset, part_size, url.clone())
+                    .await
+                    .wrap_err(format!("couldn't upload part {}", part + 1))?;
+                Ok::<_, eyre::Report>((part, etag, length))
+            }
+        });
+    let mut uploads = stream::iter(pending).buffer_unordered(parallelism.max(1));
+    while let Some(result) = uploads.next().await {
+        let (part, etag, length) = result?;
+        on_part(part, &etag, length)?;
+        etags.insert(part, etag);
+    }
+    drop(uploads);
+
+    if etags.len() != transfer_data.parts_upload_urls.len() {
+        return Err(eyre!("not all parts were uploaded"));
+    }
+    let body = etags
+        .into_iter()
+        .map(|(i, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", i + 1, etag))
+        .join("");
+    let body = format!(
+        "<CompleteMultipartUpload xmlns=\"http://s3.amazonaws.com/doc/2006-03-01\">{}</CompleteMultipartUpload>",
+        body
+    );
+    let client = reqwest::Client::new();
+    let req = client
+        .post(transfer_data.complete_upload_url.clone())
+        .body(body)
+        .build()?;
+    client
+        .execute(req)
+        .await?
+        .error_for_status()
+        .wrap_err("failed to complete upload")?;
+    Ok(())
+}
+
+/// Progress of a multipart upload, persisted in the data dir so an interrupted upload can be resumed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadState {
+    pub system: String,
+    pub local: PathBuf,
+    /// The remote path as passed by the user, used to find the state again
+    pub requested_remote: PathBuf,
+    /// The remote path the file is actually uploaded to
+    pub remote: PathBuf,
+    pub size: u64,
+    pub modified: u64,
+    pub job_id: String,
+    pub parts_upload_urls: Vec<String>,
+    pub complete_upload_url: String,
+    pub part_size: u64,
+    pub etags: BTreeMap<usize, String>,
+}
+
+impl UploadState {
+    pub fn new(
+        system: &str,
+        local: &Path,
+        requested_remote: &Path,
+        remote: PathBuf,
+        job_id: JobId,
+        transfer_data: &S3Upload,
+    ) -> Result<Self> {
+        let (size, modified) = local_file_version(local)?;
+        Ok(Self {
+            system: system.to_owned(),
+            local: std::path::absolute(local)?,
+            requested_remote: requested_remote.to_path_buf(),
+            remote,
+            size,
+            modified,
+            job_id: job_id.into_string(),
+            parts_upload_urls: transfer_data.parts_upload_urls.iter().map(|u| u.to_string()).collect(),
+            complete_upload_url: transfer_data.complete_upload_url.to_string(),
+            part_size: transfer_data.part_size,
+            etags: BTreeMap::new(),
+        })
+    }
+
+    fn state_file(system: &str, local: &Path, requested_remote: &Path) -> Result<PathBuf> {
+        let mut hasher = Sha256::new();
+        hasher.update(system.as_bytes());
+        hasher.update(std::path::absolute(local)?.as_os_str().as_encoded_bytes());
+        hasher.update(requested_remote.as_os_str().as_encoded_bytes());
+        let hash = hasher.finalize();
+        Ok(get_data_dir().join("uploads").join(format!("{hash:02x}.json")))
+    }
+
+    /// Loads the state of an earlier upload of `local` to `requested_remote`, if there is one
+    pub fn load(system: &str, local: &Path, requested_remote: &Path) -> Result<Option<Self>> {
+        let path = Self::state_file(system, local, requested_remote)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        let state = serde_json::from_str(&content).wrap_err("couldn't read upload state")?;
+        Ok(Some(state))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::state_file(&self.system, &self.local, &self.requested_remote)?;
+        std::fs::create_dir_all(path.parent().expect("state file has a parent"))?;
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        let path = Self::state_file(&self.system, &self.local, &self.requested_remote)?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the local file is still the same as when the upload was started
+    pub fn local_unchanged(&self) -> Result<bool> {
+        Ok(local_file_version(&self.local)? == (self.size, self.modified))
+    }
+
+    pub fn transfer_data(&self) -> Result<S3Upload> {
+        Ok(S3Upload {
+            parts_upload_urls: self
+                .parts_upload_urls
+                .iter()
+                .map(|u| Url::parse(u).wrap_err("couldn't parse url"))
+                .collect::<Result<Vec<Url>>>()?,
+            complete_upload_url: Url::parse(&self.complete_upload_url)?,
+            part_size: self.part_size,
+            num_parts: self.parts_upload_urls.len() as u64,
+        })
+    }
+
+    /// Uploads the remaining parts, persisting progress after each one, and removes the state once done
+    pub async fn upload<F>(&mut self, parallelism: usize, mut on_part: F) -> Result<()>
+    where
+        F: FnMut(usize, u64) -> Result<()>,
+    {
+        let transfer_data = self.transfer_data()?;
+        let local = self.local.clone();
+        let etags = self.etags.clone();
+        multipart_upload(
+            &local,
+            self.size,
+            &transfer_data,
+            parallelism,
+            etags,
+            |part, etag, length| {
+                self.etags.insert(part, etag.to_owned());
+                self.save()?;
+                on_part(self.etags.len(), length)
+            },
+        )
+        .await?;
+        self.remove()
+    }
+}
+
+/// Chunks of a ranged download that are already done, persisted next to the target file
+#[derive(Debug, Serialize, Deserialize)]
+struct DownloadState {
+    /// The remote object the chunks were downloaded from, the url without its signature
+    #[serde(default)]
+    remote: String,
+    /// ETag of the remote object, if the server sent one
+    #[serde(default)]
+    etag: Option<String>,
+    size: u64,
+    chunk_size: u64,
+    done: BTreeSet<u64>,
+}
+
+fn download_state_file(local: &Path) -> PathBuf {
+    let name = local
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    local.with_file_name(format!(".{name}.coman-download"))
+}
+
+/// Identifies the remote object behind a (presigned) download url, which gets new query parameters on every request
+fn download_identity(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    url.to_string()
+}
+
+/// Downloads `url` to `local` using HTTP range requests, `parallelism` ranges at a time.
+///
+/// If an earlier download of the same remote file was interrupted, only the missing ranges are fetched.
+/// Should the remote file have changed in the meantime (different ETag), the partial state is discarded.
+/// `on_progress` is called with the number of newly written bytes.
+pub(crate) async fn ranged_download<F>(
+    url: &Url,
+    local: &Path,
+    size: u64,
+    parallelism: usize,
+    on_progress: F,
+) -> Result<()>
+where
+    F: FnMut(u64) -> Result<()> + Send,
+{
+    let state_file = download_state_file(local);
+    let previous = std::fs::read_to_string(&state_file)
+        .ok()
+        .and_then(|content| serde_json::from_str::<DownloadState>(&content).ok());
+    let remote = download_identity(url);
+    let mut state = match previous {
+        Some(state)
+            if state.remote == remote
+                && state.size == size
+                && state.chunk_size == DOWNLOAD_CHUNK_SIZE
+                && local.exists() =>
+        {
+            state
+        }
+        _ => DownloadState {
+            remote,
+            etag: None,
+            size,
+            chunk_size: DOWNLOAD_CHUNK_SIZE,
+            done: BTreeSet::new(),
+        },
+    };
+    std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(local)?
+        .set_len(size)?;
+
+    let chunk_range = |chunk: u64| {
+        let start = chunk * DOWNLOAD_CHUNK_SIZE;
+        (start, (start + DOWNLOAD_CHUNK_SIZE).min(size))
+    };
+    let on_progress = Mutex::new(on_progress);
+    let etag = Mutex::new(state.etag.clone());
+    let remote_changed = AtomicBool::new(false);
+    let already_done = state
+        .done
+        .iter()
+        .map(|chunk| {
+            let (start, end) = chunk_range(*chunk);
+            end - start
+        })
+        .sum();
+    (on_progress.lock().expect("progress lock poisoned"))(already_done)?;
+
+    let client = reqwest::Client::new();
+    let pending: Vec<u64> = (0..size.div_ceil(DOWNLOAD_CHUNK_SIZE))
+        .filter(|chunk| !state.done.contains(chunk))
+        .collect();
+    let downloads = pending.into_iter().map(|chunk| {
+        let client = client.clone();
+        let on_progress = &on_progress;
+        let etag = &etag;
+        let remote_changed = &remote_changed;
+        async move {
+            let (start, end) = chunk_range(chunk);
+            let resp = client
+                .get(url.clone())
+                .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end - 1))
+                .send()
+                .await?
+                .error_for_status()
+                .wrap_err("couldn't download file")?;
+            if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT && (start, end) != (0, size) {
+                return Err(eyre!("server doesn't support ranged downloads"));
+            }
+            if let Some(remote_etag) = resp.headers().get(reqwest::header::ETAG) {
+                let remote_etag = remote_etag.to_str()?.to_owned();
+                let mut etag = etag.lock().expect("etag lock poisoned");
+                match etag.as_ref() {
+                    Some(known) if *known != remote_etag => {
+                        remote_changed.store(true, Ordering::SeqCst);
+                        return Err(eyre!(
+                            "remote file changed while downloading, run the download again to start over"
+                        ));
+                    }
+                    Some(_) => {}
+                    None => *etag = Some(remote_etag),
+                }
+            }
+            let mut output = tokio::fs::OpenOptions::new().write(true).open(local).await?;
+            output.seek(SeekFrom::Start(start)).await?;
+            let mut stream = resp.bytes_stream();
+            while let Some(bytes) = stream.next().await {
+                let bytes = bytes?;
+                output.write_all(&bytes).await?;
+                (on_progress.lock().expect("progress lock poisoned"))(bytes.len() as u64)?;
+            }
+            output.flush().await?;
+            Ok::<_, eyre::Report>(chunk)
+        }
+    });
+    let mut downloads = stream::iter(downloads).buffer_unordered(parallelism.max(1));
+    while let Some(result) = downloads.next().await {
+        let chunk = match result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                if remote_changed.load(Ordering::SeqCst) && state_file.exists() {
+                    // the finished chunks belong to another version of the file, start over next time
+                    std::fs::remove_file(&state_file)?;
+                }
+                return Err(e);
+            }
+        };
+        state.done.insert(chunk);
+        state.etag = etag.lock().expect("etag lock poisoned").clone();
+        std::fs::write(&state_file, serde_json::to_string(&state)?)?;
+    }
+    drop(downloads);
+
+    if state_file.exists() {
+        std::fs::remove_file(state_file)?;
+    }
+    Ok(())
+}
+
+/// Size and modification time of a local file, to detect changes between upload attempts
+fn local_file_version(path: &Path) -> Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    Ok((metadata.len(), modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::cscs::handlers::check_checksum;
+
+    #[test]
+    fn test_upload_state_roundtrip() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let local = temp_dir.path().join("data.bin");
+        std::fs::write(&local, vec![0u8; 100]).expect("couldn't write file");
+        let transfer_data = S3Upload {
+            parts_upload_urls: vec![
+                Url::parse("http://test/1").unwrap(),
+                Url::parse("http://test/2").unwrap(),
+            ],
+            complete_upload_url: Url::parse("http://test/complete").unwrap(),
+            part_size: 50,
+            num_parts: 2,
+        };
+        let mut state = UploadState::new(
+            "test-system",
+            &local,
+            Path::new("/remote"),
+            PathBuf::from("/remote/data.bin"),
+            JobId::from("1"),
+            &transfer_data,
+        )
+        .expect("couldn't create state");
+        state.etags.insert(0, "etag-1".to_owned());
+
+        let restored: UploadState =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).expect("couldn't deserialize state");
+        assert_eq!(restored.etags, state.etags);
+        assert_eq!(restored.remote, PathBuf::from("/remote/data.bin"));
+        assert!(restored.local_unchanged().unwrap());
+        let restored_transfer = restored.transfer_data().unwrap();
+        assert_eq!(restored_transfer.parts_upload_urls, transfer_data.parts_upload_urls);
+        assert_eq!(restored_transfer.num_parts, 2);
+
+        std::fs::write(&local, vec![0u8; 10]).expect("couldn't write file");
+        assert!(!restored.local_unchanged().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_resumed_download_checksum_mismatch() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let local = temp_dir.path().join("data.bin");
+        let remote_content = b"remote content".to_vec();
+        let remote_hash = format!("{:02x}", Sha256::digest(&remote_content));
+        // an interrupted download whose finished chunk doesn't hold what the remote file contains
+        std::fs::write(&local, b"stale content!").expect("couldn't write file");
+        let url = Url::parse("http://localhost:1/unreachable").unwrap();
+        let state = DownloadState {
+            remote: download_identity(&url),
+            etag: None,
+            size: remote_content.len() as u64,
+            chunk_size: DOWNLOAD_CHUNK_SIZE,
+            done: BTreeSet::from([0]),
+        };
+        std::fs::write(download_state_file(&local), serde_json::to_string(&state).unwrap())
+            .expect("couldn't write state");
+
+        let mut resumed = 0;
+        ranged_download(
+            &url.join("unreachable?X-Amz-Signature=new").unwrap(),
+            &local,
+            state.size,
+            2,
+            |bytes| {
+                resumed += bytes;
+                Ok(())
+            },
+        )
+        .await
+        .expect("resumed download shouldn't fetch anything");
+        assert_eq!(resumed, state.size);
+        assert!(!download_state_file(&local).exists());
+        assert!(check_checksum(&local, &remote_hash).is_err());
+
+        std::fs::write(&local, &remote_content).expect("couldn't write file");
+        check_checksum(&local, &remote_hash).expect("checksum should match");
+    }
+
+    #[tokio::test]
+    async fn test_download_state_of_other_remote_discarded() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let local = temp_dir.path().join("data.bin");
+        std::fs::write(&local, b"other content!").expect("couldn't write file");
+        // an interrupted download of another remote file with the same size
+        let state = DownloadState {
+            remote: download_identity(&Url::parse("http://localhost:1/other.bin").unwrap()),
+            etag: Some("\"other\"".to_owned()),
+            size: 14,
+            chunk_size: DOWNLOAD_CHUNK_SIZE,
+            done: BTreeSet::from([0]),
+        };
+        std::fs::write(download_state_file(&local), serde_json::to_string(&state).unwrap())
+            .expect("couldn't write state");
+
+        let result = ranged_download(
+            &Url::parse("http://localhost:1/data.bin").unwrap(),
+            &local,
+            state.size,
+            2,
+            |_| Ok(()),
+        )
+        .await;
+        assert!(result.is_err(), "the other file's chunks shouldn't be reused");
+        assert_eq!(
+            download_identity(&Url::parse("http://test/bucket/data.bin?X-Amz-Signature=abc").unwrap()),
+            "http://test/bucket/data.bin"
+        );
+    }
+
+    #[test]
+    fn test_download_state_file_is_hidden_sibling() {
+        let state_file = download_state_file(Path::new("/tmp/target/data.bin"));
+        assert_eq!(state_file, PathBuf::from("/tmp/target/.data.bin.coman-download"));
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use itertools::Itertools;
+
+use crate::util::shell::check_variable_name;
+
+/// Parameter grid of a sweep, every key with the values to try
+pub type SweepGrid = Vec<(String, Vec<String>)>;
+
+/// Reads a sweep grid from a toml file mapping parameter names to lists of values, e.g. `lr = [0.1, 0.01]`
+pub fn load_sweep_file(path: &Path) -> Result<SweepGrid> {
+    let content = std::fs::read_to_string(path).wrap_err(format!("couldn't read sweep file {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&content).wrap_err("couldn't parse sweep file")?;
+    table
+        .into_iter()
+        .map(|(key, value)| match value {
+            toml::Value::Array(values) => Ok((key, values.into_iter().map(sweep_value).collect())),
+            other => Ok((key, vec![sweep_value(other)])),
+        })
+        .collect()
+}
+
+fn sweep_value(value: toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// All combinations of the grid, the last key changing fastest
+pub fn sweep_combinations(grid: &SweepGrid) -> Result<Vec<Vec<(String, String)>>> {
+    if let Some((key, _)) = grid.iter().find(|(_, values)| values.is_empty()) {
+        return Err(eyre!("sweep parameter {} has no values", key));
+    }
+    for (key, _) in grid {
+        check_variable_name(key).wrap_err("invalid sweep parameter")?;
+    }
+    if let Some(key) = grid.iter().map(|(key, _)| key).duplicates().next() {
+        return Err(eyre!("sweep parameter {} is specified more than once", key))
+            .suggestion("list all values in a single `--sweep KEY=v1,v2` flag");
+    }
+    Ok(grid
+        .iter()
+        .map(|(key, values)| values.iter().map(move |value| (key.clone(), value.clone())))
+        .multi_cartesian_product()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_sweep_combinations() {
+        let grid = vec![
+            ("lr".to_owned(), vec!["0.1".to_owned(), "0.01".to_owned()]),
+            ("batch".to_owned(), vec!["32".to_owned(), "64".to_owned()]),
+        ];
+        let combinations = sweep_combinations(&grid).unwrap();
+        assert_eq!(combinations.len(), 4);
+        assert_eq!(
+            combinations[1],
+            vec![
+                ("lr".to_owned(), "0.1".to_owned()),
+                ("batch".to_owned(), "64".to_owned())
+            ]
+        );
+        assert!(sweep_combinations(&vec![("lr".to_owned(), vec![])]).is_err());
+        assert!(sweep_combinations(&vec![("lr;id".to_owned(), vec!["1".to_owned()])]).is_err());
+    }
+
+    #[test]
+    fn test_load_sweep_file() {
+        let temp_dir = tempdir().expect("couldn't create temp dir");
+        let path = temp_dir.path().join("sweep.toml");
+        std::fs::write(&path, "lr = [0.1, 0.01]\noptimizer = [\"adam\", \"sgd\"]\nseed = 1\n").unwrap();
+        let grid = load_sweep_file(&path).unwrap();
+        assert_eq!(
+            grid,
+            vec![
+                ("lr".to_owned(), vec!["0.1".to_owned(), "0.01".to_owned()]),
+                ("optimizer".to_owned(), vec!["adam".to_owned(), "sgd".to_owned()]),
+                ("seed".to_owned(), vec!["1".to_owned()]),
+            ]
+        );
+    }
+}
@@ -22,8 +22,9 @@ use crate::{
     },
     cli::{
         app::{
-            Cli, CliCommands, ConfigCommands, CscsCommands, CscsFileCommands, CscsJobCommands, CscsSystemCommands,
-            check_update, get_config, print_completions, set_config, update, version,
+            Cli, CliCommands, ConfigCommands, CscsCommands, CscsEdfCommands, CscsFileCommands, CscsIdentityCommands,
+            CscsJobCommands, CscsSystemCommands, HistoryCommands, PipelineCommands, SecretsCommands, check_update,
+            get_config, print_completions, set_config, update, version,
         },
         exec::cli_exec_command,
         proxy::cli_proxy_command,
@@ -32,20 +33,30 @@ use crate::{
         file_tree::FileTree, global_listener::GlobalListener, status_bar::StatusBar, toolbar::Toolbar,
         workload_list::WorkloadList,
     },
-    config::Config,
+    config::{Config, set_active_site},
     cscs::{
         api_client::client::JobStartOptions,
         cli::{
-            cli_cscs_file_delete, cli_cscs_file_download, cli_cscs_file_list, cli_cscs_file_upload,
-            cli_cscs_job_cancel, cli_cscs_job_detail, cli_cscs_job_list, cli_cscs_job_log, cli_cscs_job_resource_usage,
-            cli_cscs_job_start, cli_cscs_login, cli_cscs_port_forward, cli_cscs_set_system, cli_cscs_system_list,
+            JobDependencies, JobLogOptions, UploadOptions, cli_config_profiles, cli_cscs_edf_validate,
+            cli_cscs_file_cat, cli_cscs_file_chown, cli_cscs_file_copy, cli_cscs_file_delete, cli_cscs_file_download,
+            cli_cscs_file_download_archive, cli_cscs_file_head, cli_cscs_file_link, cli_cscs_file_list,
+            cli_cscs_file_move, cli_cscs_file_sync, cli_cscs_file_tar, cli_cscs_file_type, cli_cscs_file_untar,
+            cli_cscs_file_upload, cli_cscs_identity_list, cli_cscs_identity_remove, cli_cscs_identity_use,
+            cli_cscs_job_cancel, cli_cscs_job_detail, cli_cscs_job_list, cli_cscs_job_log, cli_cscs_job_render,
+            cli_cscs_job_resource_usage, cli_cscs_job_resubmit, cli_cscs_job_start, cli_cscs_job_wait, cli_cscs_login,
+            cli_cscs_port_forward, cli_cscs_set_system, cli_cscs_system_list, cli_cscs_whoami, cli_history_list,
+            cli_history_show, cli_pipeline_run, cli_pipeline_status, cli_secrets_migrate, cli_secrets_status,
+            cli_watch,
         },
+        history::JobOverrides,
         ports::{
-            AsyncBackgroundTaskPort, AsyncFetchWorkloadsPort, AsyncJobLogPort, AsyncJobResourceUsagePort,
-            AsyncSelectSystemPort, AsyncUserEventPort,
+            AsyncBackgroundTaskPort, AsyncDeviceFlowPort, AsyncFetchWorkloadsPort, AsyncJobLogPort,
+            AsyncJobResourceUsagePort, AsyncNotificationPort, AsyncSelectSystemPort, AsyncUserEventPort,
         },
+        sweep::load_sweep_file,
     },
     errors::AsyncErrorPort,
+    util::secrets::unlock_secrets,
 };
 
 mod app;
@@ -97,37 +108,98 @@ async fn main() -> Result<()> {
                     let content = toml::to_string_pretty(&config.values)?;
                     println!("{}", content)
                 }
+                ConfigCommands::Profiles => cli_config_profiles(args.output)?,
+            },
+            CliCommands::Secrets { command } => match command {
+                SecretsCommands::Status => cli_secrets_status(),
+                SecretsCommands::Migrate { to, from, keep } => cli_secrets_migrate(to, from, keep).await?,
             },
             CliCommands::Cscs {
                 command: cscs_command,
                 system,
                 platform,
+                site,
                 account,
-            } => match cscs_command {
-                CscsCommands::Login => cli_cscs_login().await?,
-                CscsCommands::Job { command } => match command {
-                    CscsJobCommands::List { status } => cli_cscs_job_list(status, system, platform).await?,
-                    CscsJobCommands::Get { job } => cli_cscs_job_detail(job, system, platform).await?,
-                    CscsJobCommands::Log { job, stderr } => cli_cscs_job_log(job, stderr, system, platform).await?,
-                    CscsJobCommands::Submit {
-                        name,
-                        image,
-                        command,
-                        workdir,
-                        env,
-                        port_forward,
-                        mount,
-                        stdout,
-                        stderr,
-                        edf_spec,
-                        script_spec,
-                        no_ssh,
-                        ssh_key,
-                        no_coman,
-                    } => {
-                        cli_cscs_job_start(
+            } => {
+                if let Some(site) = site {
+                    set_active_site(site)?;
+                }
+                match cscs_command {
+                    CscsCommands::Login { device, profile } => cli_cscs_login(device, profile).await?,
+                    CscsCommands::Whoami => cli_cscs_whoami(args.output, system, platform).await?,
+                    CscsCommands::Identity { command } => match command {
+                        CscsIdentityCommands::List => cli_cscs_identity_list(args.output).await?,
+                        CscsIdentityCommands::Use { global, name } => cli_cscs_identity_use(name, global).await?,
+                        CscsIdentityCommands::Remove { name } => cli_cscs_identity_remove(name).await?,
+                    },
+                    CscsCommands::Job { command } => match command {
+                        CscsJobCommands::List { status } => {
+                            cli_cscs_job_list(status, args.output, system, platform).await?
+                        }
+                        CscsJobCommands::Get { job } => cli_cscs_job_detail(job, args.output, system, platform).await?,
+                        CscsJobCommands::Wait { job } => {
+                            let exit_code = cli_cscs_job_wait(job, false, system, platform).await?;
+                            if exit_code != 0 {
+                                std::process::exit(exit_code);
+                            }
+                        }
+                        CscsJobCommands::Watch { job } => {
+                            let exit_code = cli_cscs_job_wait(job, true, system, platform).await?;
+                            if exit_code != 0 {
+                                std::process::exit(exit_code);
+                            }
+                        }
+                        CscsJobCommands::Log {
+                            job,
+                            stderr,
+                            all,
+                            follow,
+                            lines,
+                            since,
+                        } => {
+                            cli_cscs_job_log(
+                                job,
+                                JobLogOptions {
+                                    stderr,
+                                    all,
+                                    follow,
+                                    lines,
+                                    since,
+                                },
+                                system,
+                                platform,
+                            )
+                            .await?
+                        }
+                        CscsJobCommands::Submit {
                             name,
-                            JobStartOptions {
+                            image,
+                            command,
+                            workdir,
+                            env,
+                            port_forward,
+                            mount,
+                            stdout,
+                            stderr,
+                            edf_spec,
+                            script_spec,
+                            slurm,
+                            sweep,
+                            sweep_file,
+                            after,
+                            afterok,
+                            no_ssh,
+                            ssh_key,
+                            no_coman,
+                            profile,
+                            dry_run,
+                            render_dir,
+                        } => {
+                            let mut sweep = sweep;
+                            if let Some(sweep_file) = sweep_file {
+                                sweep.extend(load_sweep_file(&sweep_file)?);
+                            }
+                            let options = JobStartOptions {
                                 image,
                                 command,
                                 container_workdir: workdir,
@@ -141,43 +213,182 @@ async fn main() -> Result<()> {
                                 no_ssh,
                                 ssh_key,
                                 no_coman,
-                            },
-                            system,
-                            platform,
-                            account,
-                        )
-                        .await?
-                    }
-                    CscsJobCommands::Cancel { job } => cli_cscs_job_cancel(job, system, platform).await?,
-                    CscsJobCommands::ResourceUsage { job } => {
-                        cli_cscs_job_resource_usage(job, system, platform).await?
-                    }
-                },
-                CscsCommands::File { command } => match command {
-                    CscsFileCommands::List { path } => cli_cscs_file_list(path, system, platform).await?,
-                    CscsFileCommands::Remove { path } => cli_cscs_file_delete(path, system, platform).await?,
-                    CscsFileCommands::Download { remote, local } => {
-                        cli_cscs_file_download(remote, local, account, system, platform).await?
-                    }
-                    CscsFileCommands::Upload { local, remote } => {
-                        cli_cscs_file_upload(local, remote, account, system, platform).await?
-                    }
-                },
-                CscsCommands::System { command } => match command {
-                    CscsSystemCommands::List => cli_cscs_system_list(platform).await?,
-                    CscsSystemCommands::Set { system_name, global } => cli_cscs_set_system(system_name, global).await?,
-                },
-                CscsCommands::PortForward {
-                    source_port,
-                    destination_port,
-                    job,
-                } => cli_cscs_port_forward(source_port, destination_port, job, system, platform).await?,
+                                slurm: slurm.into(),
+                                sweep,
+                                dependencies: vec![],
+                            };
+                            let dependencies = JobDependencies { after, afterok };
+                            let config = match profile {
+                                Some(profile) => Config::new()?.with_profile(&profile)?,
+                                None => Config::new()?,
+                            };
+                            if dry_run {
+                                cli_cscs_job_render(
+                                    &config,
+                                    name,
+                                    options,
+                                    dependencies,
+                                    render_dir,
+                                    args.output,
+                                    system,
+                                    platform,
+                                    account,
+                                )
+                                .await?
+                            } else {
+                                cli_cscs_job_start(&config, name, options, dependencies, system, platform, account)
+                                    .await?
+                            }
+                        }
+                        CscsJobCommands::Resubmit {
+                            job,
+                            name,
+                            workdir,
+                            env,
+                            port_forward,
+                            mount,
+                            image,
+                            stdout,
+                            stderr,
+                            edf_spec,
+                            script_spec,
+                            slurm,
+                            no_ssh,
+                            ssh_key,
+                            no_coman,
+                            command,
+                        } => {
+                            cli_cscs_job_resubmit(
+                                job,
+                                name,
+                                JobOverrides {
+                                    image,
+                                    command,
+                                    workdir,
+                                    env,
+                                    mount,
+                                    port_forward,
+                                    stdout,
+                                    stderr,
+                                    edf_spec: edf_spec.map(Into::into),
+                                    script_spec: script_spec.map(Into::into),
+                                    slurm: slurm.into(),
+                                    no_ssh,
+                                    ssh_key,
+                                    no_coman,
+                                },
+                                system,
+                                platform,
+                                account,
+                            )
+                            .await?
+                        }
+                        CscsJobCommands::Cancel { job } => cli_cscs_job_cancel(job, system, platform).await?,
+                        CscsJobCommands::ResourceUsage { job, node } => {
+                            cli_cscs_job_resource_usage(job, node, args.output, system, platform).await?
+                        }
+                    },
+                    CscsCommands::File { command } => match command {
+                        CscsFileCommands::List { path } => {
+                            cli_cscs_file_list(path, args.output, system, platform).await?
+                        }
+                        CscsFileCommands::Remove { recursive, path } => {
+                            cli_cscs_file_delete(path, recursive, account, system, platform).await?
+                        }
+                        CscsFileCommands::Head { lines, bytes, path } => {
+                            cli_cscs_file_head(path, lines, bytes, system, platform).await?
+                        }
+                        CscsFileCommands::Cat { path } => cli_cscs_file_cat(path, system, platform).await?,
+                        CscsFileCommands::Type { path } => cli_cscs_file_type(path, system, platform).await?,
+                        CscsFileCommands::Cp { source, target } => {
+                            cli_cscs_file_copy(source, target, account, system, platform).await?
+                        }
+                        CscsFileCommands::Mv { source, target } => {
+                            cli_cscs_file_move(source, target, account, system, platform).await?
+                        }
+                        CscsFileCommands::Ln { target, link } => {
+                            cli_cscs_file_link(target, link, system, platform).await?
+                        }
+                        CscsFileCommands::Chown { owner, path } => {
+                            cli_cscs_file_chown(path, owner, system, platform).await?
+                        }
+                        CscsFileCommands::Tar {
+                            direct,
+                            source,
+                            archive,
+                        } => cli_cscs_file_tar(source, archive, direct, account, system, platform).await?,
+                        CscsFileCommands::Untar {
+                            direct,
+                            archive,
+                            target,
+                        } => cli_cscs_file_untar(archive, target, direct, account, system, platform).await?,
+                        CscsFileCommands::Download {
+                            remote,
+                            local,
+                            recursive,
+                            archive,
+                            extract,
+                        } => {
+                            if archive {
+                                cli_cscs_file_download_archive(remote, local, extract, account, system, platform)
+                                    .await?
+                            } else {
+                                cli_cscs_file_download(remote, local, recursive, account, system, platform).await?
+                            }
+                        }
+                        CscsFileCommands::Upload {
+                            local,
+                            remote,
+                            recursive,
+                            resume,
+                            parallel,
+                        } => {
+                            let options = UploadOptions {
+                                recursive,
+                                resume,
+                                parallel,
+                            };
+                            cli_cscs_file_upload(local, remote, options, account, system, platform).await?
+                        }
+                        CscsFileCommands::Sync { delete, local, remote } => {
+                            cli_cscs_file_sync(local, remote, delete, account, system, platform).await?
+                        }
+                    },
+                    CscsCommands::System { command } => match command {
+                        CscsSystemCommands::List => cli_cscs_system_list(args.output, platform).await?,
+                        CscsSystemCommands::Set { system_name, global } => {
+                            cli_cscs_set_system(system_name, global).await?
+                        }
+                    },
+                    CscsCommands::Edf { command } => match command {
+                        CscsEdfCommands::Validate { file } => cli_cscs_edf_validate(file)?,
+                    },
+                    CscsCommands::PortForward {
+                        source_port,
+                        destination_port,
+                        job,
+                        node,
+                    } => cli_cscs_port_forward(source_port, destination_port, job, node, system, platform).await?,
+                }
+            }
+            CliCommands::Pipeline { command } => match command {
+                PipelineCommands::Run { file } => cli_pipeline_run(file).await?,
+                PipelineCommands::Status { name } => cli_pipeline_status(name, args.output).await?,
+            },
+            CliCommands::History { command } => match command {
+                HistoryCommands::List { limit } => cli_history_list(limit, args.output)?,
+                HistoryCommands::Show { job } => cli_history_show(job, args.output)?,
             },
+            CliCommands::Watch { interval } => cli_watch(Duration::from_secs(interval)).await?,
             CliCommands::Init { destination, name } => Config::create_project_config(destination, name)?,
             CliCommands::Exec { command } => cli_exec_command(command).await?,
-            CliCommands::Proxy { system, job_id } => cli_proxy_command(system, job_id.into()).await?,
+            CliCommands::Proxy { system, job_id, node } => cli_proxy_command(system, job_id.into(), node).await?,
         },
-        None => run_tui(args.tick_rate)?,
+        None => {
+            // the TUI can't prompt for a passphrase, so we ask before starting it
+            unlock_secrets()?;
+            run_tui(args.tick_rate)?
+        }
     }
 
     Ok(())
@@ -192,6 +403,7 @@ fn run_tui(tick_rate: f64) -> Result<()> {
     let handle = Handle::current();
 
     let (select_system_tx, select_system_rx) = mpsc::channel(100);
+    let (device_login_tx, device_login_rx) = mpsc::channel(100);
     let (job_log_tx, job_log_rx) = mpsc::channel(100);
     let (job_filter_tx, job_filter_rx) = mpsc::channel(100);
     let (job_resource_usage_tx, job_resource_usage_rx) = mpsc::channel(100);
@@ -218,7 +430,13 @@ fn run_tui(tick_rate: f64) -> Result<()> {
             Duration::default(),
             1,
         )
+        .add_async_port(
+            Box::new(AsyncDeviceFlowPort::new(device_login_rx)),
+            Duration::default(),
+            1,
+        )
         .add_async_port(Box::new(AsyncJobLogPort::new(job_log_rx)), Duration::from_secs(3), 1)
+        .add_async_port(Box::new(AsyncNotificationPort::new()), Duration::from_secs(30), 1)
         .add_async_port(
             Box::new(AsyncJobResourceUsagePort::new(job_resource_usage_rx)),
             Duration::from_secs(1),
@@ -288,7 +506,10 @@ fn run_tui(tick_rate: f64) -> Result<()> {
                     code: Key::Char('q'),
                     modifiers: KeyModifiers::NONE,
                 }),
-                SubClause::Not(Box::new(SubClause::IsMounted(Id::LoginPopup))),
+                SubClause::Not(Box::new(SubClause::OrMany(vec![
+                    SubClause::IsMounted(Id::LoginPopup),
+                    SubClause::IsMounted(Id::LogSearchPopup),
+                ]))),
             ),
             Sub::new(
                 EventClause::Keyboard(KeyEvent {
@@ -344,6 +565,7 @@ fn run_tui(tick_rate: f64) -> Result<()> {
         adapter,
         error_tx,
         select_system_tx,
+        device_login_tx,
         job_log_tx,
         job_filter_tx,
         job_resource_usage_tx,
@@ -390,5 +612,6 @@ fn popup_exclusion_clause() -> SubClause<Id> {
         SubClause::IsMounted(Id::DownloadPopup),
         SubClause::IsMounted(Id::SystemSelectPopup),
         SubClause::IsMounted(Id::JobFilterPopup),
+        SubClause::IsMounted(Id::LogSearchPopup),
     ])))
 }
@@ -1,28 +1,7 @@
-use std::fmt::{Debug, Display};
-
 use color_eyre::Result;
 use keyring::KeyringEntry;
 
-#[derive(Clone)]
-pub struct Secret(pub String);
-
-impl Display for Secret {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<redacted>")
-    }
-}
-
-impl Debug for Secret {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Secret(<redacted>)")
-    }
-}
-
-impl Secret {
-    pub fn new(value: String) -> Self {
-        Self(value)
-    }
-}
+use crate::util::secrets::Secret;
 
 pub async fn store_secret(name: &str, secret: Secret) -> Result<()> {
     let entry = KeyringEntry::try_new(name)?;
@@ -38,3 +17,11 @@ pub async fn get_secret(name: &str) -> Result<Option<Secret>> {
         Ok(None)
     }
 }
+
+pub async fn delete_secret(name: &str) -> Result<()> {
+    let entry = KeyringEntry::try_new(name)?;
+    if entry.find_secret().await?.is_some() {
+        entry.delete_secret().await?;
+    }
+    Ok(())
+}
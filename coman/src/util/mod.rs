@@ -1,3 +1,6 @@
 pub mod keyring;
+pub mod secret_file;
+pub mod secrets;
+pub mod shell;
 pub mod types;
 pub mod ui;
@@ -0,0 +1,261 @@
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use base64::prelude::*;
+use color_eyre::{Result, Section};
+use eyre::{Context, eyre};
+use inquire::Password;
+use ring::{
+    aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::get_config_dir, util::secrets::Secret};
+
+/// Environment variable to read the passphrase of the secrets file from, instead of asking for it
+pub const PASSPHRASE_ENV_VAR: &str = "COMAN_SECRETS_PASSPHRASE";
+const SECRETS_FILE_NAME: &str = "secrets.enc";
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+
+/// Key derived from the passphrase, kept so we only ask for the passphrase once per run
+static KEY: Mutex<Option<DerivedKey>> = Mutex::new(None);
+
+/// On disk format of the secrets file, all secrets are encrypted together as one json object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SecretFile {
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Clone)]
+struct DerivedKey {
+    salt: Vec<u8>,
+    iterations: u32,
+    key: [u8; 32],
+}
+
+impl DerivedKey {
+    fn new(passphrase: &str, salt: Vec<u8>, iterations: u32) -> Result<Self> {
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(iterations).ok_or(eyre!("invalid iteration count"))?,
+            &salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        Ok(Self { salt, iterations, key })
+    }
+
+    fn aead_key(&self) -> Result<LessSafeKey> {
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &self.key).map_err(|_| eyre!("invalid encryption key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+fn secrets_file() -> PathBuf {
+    get_config_dir().join(SECRETS_FILE_NAME)
+}
+
+fn encrypt(secrets: &BTreeMap<String, String>, key: &DerivedKey) -> Result<SecretFile> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| eyre!("couldn't generate nonce"))?;
+    let mut data = serde_json::to_vec(secrets)?;
+    key.aead_key()?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| eyre!("couldn't encrypt secrets"))?;
+    Ok(SecretFile {
+        iterations: key.iterations,
+        salt: BASE64_STANDARD.encode(&key.salt),
+        nonce: BASE64_STANDARD.encode(nonce),
+        ciphertext: BASE64_STANDARD.encode(data),
+    })
+}
+
+fn decrypt(file: &SecretFile, key: &DerivedKey) -> Result<BTreeMap<String, String>> {
+    let nonce: [u8; NONCE_LEN] = BASE64_STANDARD
+        .decode(&file.nonce)?
+        .try_into()
+        .map_err(|_| eyre!("invalid nonce in secrets file"))?;
+    let mut data = BASE64_STANDARD.decode(&file.ciphertext)?;
+    let plaintext = key
+        .aead_key()?
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| eyre!("couldn't decrypt secrets file"))
+        .suggestion("check that the passphrase is correct")?;
+    Ok(serde_json::from_slice(plaintext)?)
+}
+
+fn passphrase(new: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV_VAR) {
+        return Ok(passphrase);
+    }
+    let prompt = Password::new("Passphrase for the coman secrets file:");
+    let prompt = match new {
+        true => prompt.with_custom_confirmation_message("Repeat passphrase:"),
+        false => prompt.without_confirmation(),
+    };
+    prompt
+        .prompt()
+        .wrap_err("couldn't read passphrase")
+        .with_suggestion(|| format!("set {PASSPHRASE_ENV_VAR} when running non-interactively"))
+}
+
+/// Returns the key for the salt, asking for the passphrase if we don't know it yet
+fn key_for(salt: Vec<u8>, iterations: u32, new: bool) -> Result<DerivedKey> {
+    let mut cached = KEY.lock().map_err(|_| eyre!("secrets key cache is poisoned"))?;
+    if let Some(key) = cached.as_ref()
+        && key.salt == salt
+        && key.iterations == iterations
+    {
+        return Ok(key.clone());
+    }
+    let key = DerivedKey::new(&passphrase(new)?, salt, iterations)?;
+    *cached = Some(key.clone());
+    Ok(key)
+}
+
+fn forget_key() {
+    if let Ok(mut cached) = KEY.lock() {
+        *cached = None;
+    }
+}
+
+fn load(path: &Path) -> Result<Option<(BTreeMap<String, String>, DerivedKey)>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path).wrap_err(format!("couldn't read {}", path.display()))?;
+    let file: SecretFile =
+        serde_json::from_str(&content).wrap_err(format!("couldn't parse secrets file {}", path.display()))?;
+    let key = key_for(BASE64_STANDARD.decode(&file.salt)?, file.iterations, false)?;
+    match decrypt(&file, &key) {
+        Ok(secrets) => Ok(Some((secrets, key))),
+        Err(e) => {
+            // so the next attempt asks for the passphrase again
+            forget_key();
+            Err(e)
+        }
+    }
+}
+
+fn save(path: &Path, secrets: &BTreeMap<String, String>, key: &DerivedKey) -> Result<()> {
+    let content = serde_json::to_string_pretty(&encrypt(secrets, key)?)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // write to a temporary file first and swap it in, so a failed write doesn't lose the stored secrets
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(target_family = "unix")]
+    options.mode(0o600);
+    let mut file = options
+        .open(&tmp_path)
+        .wrap_err(format!("couldn't write {}", tmp_path.display()))?;
+    // the mode only applies to new files, a leftover temporary file keeps its permissions
+    #[cfg(target_family = "unix")]
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .wrap_err(format!("couldn't write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).wrap_err(format!("couldn't write {}", path.display()))?;
+    Ok(())
+}
+
+fn load_or_create(path: &Path) -> Result<(BTreeMap<String, String>, DerivedKey)> {
+    match load(path)? {
+        Some(loaded) => Ok(loaded),
+        None => {
+            let mut salt = vec![0u8; SALT_LEN];
+            SystemRandom::new()
+                .fill(&mut salt)
+                .map_err(|_| eyre!("couldn't generate salt"))?;
+            Ok((BTreeMap::new(), key_for(salt, PBKDF2_ITERATIONS, true)?))
+        }
+    }
+}
+
+/// Asks for the passphrase up front, e.g. before starting the TUI where we can't prompt
+pub fn unlock() -> Result<()> {
+    load(&secrets_file()).map(|_| ())
+}
+
+pub fn store_secret(name: &str, secret: Secret) -> Result<()> {
+    let path = secrets_file();
+    let (mut secrets, key) = load_or_create(&path)?;
+    secrets.insert(name.to_owned(), secret.0);
+    save(&path, &secrets, &key)
+}
+
+pub fn get_secret(name: &str) -> Result<Option<Secret>> {
+    Ok(load(&secrets_file())?.and_then(|(mut secrets, _)| secrets.remove(name).map(Secret)))
+}
+
+pub fn delete_secret(name: &str) -> Result<()> {
+    let path = secrets_file();
+    if let Some((mut secrets, key)) = load(&path)?
+        && secrets.remove(name).is_some()
+    {
+        save(&path, &secrets, &key)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_file_roundtrip() {
+        let key = DerivedKey::new("correct horse", vec![1; SALT_LEN], 1000).unwrap();
+        let mut secrets = BTreeMap::new();
+        secrets.insert("cscs_client_id".to_owned(), "id".to_owned());
+        secrets.insert("cscs_secret_id".to_owned(), "secret".to_owned());
+
+        let file = encrypt(&secrets, &key).unwrap();
+        let ciphertext = BASE64_STANDARD.decode(&file.ciphertext).unwrap();
+        assert!(!ciphertext.windows(6).any(|w| w == b"secret"));
+        assert_eq!(decrypt(&file, &key).unwrap(), secrets);
+        // a fresh nonce for every write
+        assert_ne!(encrypt(&secrets, &key).unwrap().nonce, file.nonce);
+
+        let wrong_key = DerivedKey::new("battery staple", vec![1; SALT_LEN], 1000).unwrap();
+        assert!(decrypt(&file, &wrong_key).is_err());
+    }
+
+    #[test]
+    fn test_save_secret_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SECRETS_FILE_NAME);
+        let key = DerivedKey::new("correct horse", vec![1; SALT_LEN], 1000).unwrap();
+        let mut secrets = BTreeMap::new();
+        secrets.insert("cscs_client_id".to_owned(), "id".to_owned());
+        std::fs::write(&path, "old").unwrap();
+        #[cfg(target_family = "unix")]
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        save(&path, &secrets, &key).unwrap();
+        let file: SecretFile = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(decrypt(&file, &key).unwrap(), secrets);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        #[cfg(target_family = "unix")]
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
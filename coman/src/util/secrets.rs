@@ -0,0 +1,156 @@
+use std::{
+    fmt::{Debug, Display},
+    sync::OnceLock,
+};
+
+use color_eyre::{Result, Section};
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    util::{keyring, secret_file},
+};
+
+/// Backend selected for this run, read from the config on first use
+static BACKEND: OnceLock<SecretBackend> = OnceLock::new();
+
+#[derive(Clone)]
+pub struct Secret(pub String);
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+}
+
+/// Where credentials and tokens are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum SecretBackend {
+    /// The secure storage of the operating system
+    #[default]
+    Keyring,
+    /// A passphrase encrypted file in the config directory, for machines without a keyring
+    File,
+    /// Read only, from environment variables like `COMAN_CSCS_CLIENT_ID` and `COMAN_CSCS_CLIENT_SECRET`
+    Env,
+}
+
+impl SecretBackend {
+    pub async fn get(&self, name: &str) -> Result<Option<Secret>> {
+        match self {
+            SecretBackend::Keyring => keyring::get_secret(name).await,
+            SecretBackend::File => secret_file::get_secret(name),
+            SecretBackend::Env => Ok(std::env::var(env_var_name(name))
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(Secret)),
+        }
+    }
+
+    pub async fn store(&self, name: &str, secret: Secret) -> Result<()> {
+        match self {
+            SecretBackend::Keyring => keyring::store_secret(name, secret).await,
+            SecretBackend::File => secret_file::store_secret(name, secret),
+            SecretBackend::Env => Err(eyre!("the env secret backend is read only"))
+                .with_suggestion(|| format!("set the {} environment variable instead", env_var_name(name))),
+        }
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        match self {
+            SecretBackend::Keyring => keyring::delete_secret(name).await,
+            SecretBackend::File => secret_file::delete_secret(name),
+            SecretBackend::Env => Err(eyre!("the env secret backend is read only"))
+                .with_suggestion(|| format!("unset the {} environment variable instead", env_var_name(name))),
+        }
+    }
+}
+
+/// The secret backend from `secrets.backend` in the config
+pub fn configured_backend() -> SecretBackend {
+    *BACKEND.get_or_init(|| Config::new().map(|c| c.values.secrets.backend).unwrap_or_default())
+}
+
+/// Environment variable a secret is read from by the env backend, e.g. `COMAN_CSCS_CLIENT_ID` for `cscs_client_id`
+pub fn env_var_name(name: &str) -> String {
+    // the client secret is stored as `<site>_secret_id` for historical reasons
+    let name = name.replacen("_secret_id", "_client_secret", 1);
+    format!("COMAN_{}", name.to_uppercase().replace('-', "_"))
+}
+
+pub async fn store_secret(name: &str, secret: Secret) -> Result<()> {
+    configured_backend().store(name, secret).await
+}
+
+pub async fn get_secret(name: &str) -> Result<Option<Secret>> {
+    configured_backend().get(name).await
+}
+
+pub async fn delete_secret(name: &str) -> Result<()> {
+    configured_backend().delete(name).await
+}
+
+/// Makes sure the secrets can be read without prompting later on, e.g. before starting the TUI
+pub fn unlock_secrets() -> Result<()> {
+    match configured_backend() {
+        SecretBackend::File => secret_file::unlock(),
+        SecretBackend::Keyring | SecretBackend::Env => Ok(()),
+    }
+}
+
+/// Copies the secrets from one backend to another, returning the names of the secrets that were copied
+pub async fn migrate_secrets(
+    from: SecretBackend,
+    to: SecretBackend,
+    names: &[String],
+    keep: bool,
+) -> Result<Vec<String>> {
+    if from == to {
+        return Err(eyre!("secrets are already stored in the {} backend", to));
+    }
+    let mut migrated = vec![];
+    for name in names {
+        if let Some(secret) = from.get(name).await? {
+            to.store(name, secret).await?;
+            migrated.push(name.clone());
+        }
+    }
+    if !keep && from != SecretBackend::Env {
+        for name in &migrated {
+            from.delete(name).await?;
+        }
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(env_var_name("cscs_client_id"), "COMAN_CSCS_CLIENT_ID");
+        assert_eq!(env_var_name("cscs_secret_id"), "COMAN_CSCS_CLIENT_SECRET");
+        assert_eq!(
+            env_var_name("cscs_secret_id__project-a"),
+            "COMAN_CSCS_CLIENT_SECRET__PROJECT_A"
+        );
+        assert_eq!(env_var_name("cscs_refresh_token"), "COMAN_CSCS_REFRESH_TOKEN");
+        assert_eq!(env_var_name("other_secret_id"), "COMAN_OTHER_CLIENT_SECRET");
+    }
+}
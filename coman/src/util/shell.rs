@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use color_eyre::{Result, Section};
+use eyre::eyre;
+
+/// Quotes a value for a posix shell, e.g. `it's` becomes `'it'\''s'`
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Tera filter quoting a value for the shell, use it as `{{ value | shell_quote }}` in script templates
+pub fn shell_quote_filter(value: &tera::Value, _: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let value = match value {
+        tera::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    Ok(tera::Value::String(shell_quote(&value)))
+}
+
+/// Checks that `name` can be used as an environment variable name in scripts and edf files
+pub fn check_variable_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(eyre!("invalid variable name `{}`", name))
+            .suggestion("variable names can only contain letters, digits and `_` and can't start with a digit");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("0.1"), "'0.1'");
+        assert_eq!(shell_quote("it's $HOME `id` \"x\""), r#"'it'\''s $HOME `id` "x"'"#);
+        assert!(check_variable_name("LR").is_ok());
+        assert!(check_variable_name("_batch_size2").is_ok());
+        assert!(check_variable_name("2fast").is_err());
+        assert!(check_variable_name("a;rm -rf").is_err());
+        assert!(check_variable_name("").is_err());
+    }
+}
@@ -54,6 +54,19 @@ impl FirecrestClient {
         params: Option<Vec<(&str, &str)>>,
         multipart: Option<(&str, (&str, Vec<u8>))>,
     ) -> Result<String> {
+        let resp = self.send(path, method, body, params, multipart).await?;
+        Ok(resp.text().await?)
+    }
+
+    /// Sends a request, turning error statuses into errors
+    async fn send(
+        &self,
+        path: &str,
+        method: reqwest::Method,
+        body: Option<String>,
+        params: Option<Vec<(&str, &str)>>,
+        multipart: Option<(&str, (&str, Vec<u8>))>,
+    ) -> Result<reqwest::Response> {
         let mut url = self.base_path.join(path)?;
         if let Some(params) = params {
             url.query_pairs_mut().extend_pairs(params);
@@ -85,8 +98,7 @@ impl FirecrestClient {
         let status = resp.status();
 
         if !status.is_client_error() && !status.is_server_error() {
-            let content = resp.text().await?;
-            Ok(content)
+            Ok(resp)
         } else {
             let content = resp.text().await?;
             Err(FirecrestError::ResponseError {
@@ -99,6 +111,11 @@ impl FirecrestClient {
     pub async fn get(&self, path: &str, params: Option<Vec<(&str, &str)>>) -> Result<String> {
         self.request(path, reqwest::Method::GET, None, params, None).await
     }
+    /// Like `get`, but returns the raw response body, for responses that aren't necessarily valid utf-8
+    pub async fn get_bytes(&self, path: &str, params: Option<Vec<(&str, &str)>>) -> Result<Vec<u8>> {
+        let resp = self.send(path, reqwest::Method::GET, None, params, None).await?;
+        Ok(resp.bytes().await?.to_vec())
+    }
     pub async fn delete(&self, path: &str, params: Option<Vec<(&str, &str)>>) -> Result<String> {
         self.request(path, reqwest::Method::DELETE, None, params, None).await
     }
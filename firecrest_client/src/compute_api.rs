@@ -19,6 +19,9 @@ pub struct JobOptions<'a> {
     pub envvars: HashMap<String, String>,
     pub stdout: Option<PathBuf>,
     pub stderr: Option<PathBuf>,
+    pub partition: Option<String>,
+    pub reservation: Option<String>,
+    pub constraints: Option<String>,
 }
 
 pub async fn post_compute_system_job<'a>(
@@ -60,6 +63,9 @@ pub async fn post_compute_system_job<'a>(
                 .map_err(|e| eyre!("Path:{}", e.display()))
                 .wrap_err("stderr is not a valid path")?,
             account,
+            partition: options.partition,
+            reservation: options.reservation,
+            constraints: options.constraints,
             ..Default::default()
         },
     };
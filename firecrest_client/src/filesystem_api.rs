@@ -5,10 +5,14 @@ use eyre::{Result, eyre};
 use crate::{
     client::FirecrestClient,
     types::{
-        DownloadFileResponse, DownloadFileResponseTransferDirectives, GetDirectoryLsResponse, GetFileChecksumResponse,
-        GetFileStatResponse, GetFileTailResponse, PostFileDownloadRequest, PostFileDownloadRequestTransferDirectives,
-        PostFileUploadRequest, PostFileUploadRequestTransferDirectives, PostMakeDirRequest, PostMkdirResponse,
-        PutFileChmodRequest, PutFileChmodResponse, S3TransferRequest, S3TransferResponse, UploadFileResponse,
+        CompressRequest, CompressResponse, CompressionType, CopyRequest, CopyResponse, DeleteResponse,
+        DownloadFileResponse, DownloadFileResponseTransferDirectives, ExtractRequest, ExtractResponse,
+        GetDirectoryLsResponse, GetFileChecksumResponse, GetFileHeadResponse, GetFileStatResponse, GetFileTailResponse,
+        GetFileTypeResponse, GetViewFileResponse, MoveRequest, MoveResponse, PostCompressRequest, PostExtractRequest,
+        PostFileDownloadRequest, PostFileDownloadRequestTransferDirectives, PostFileSymlinkRequest,
+        PostFileSymlinkResponse, PostFileUploadRequest, PostFileUploadRequestTransferDirectives, PostMakeDirRequest,
+        PostMkdirResponse, PutFileChmodRequest, PutFileChmodResponse, PutFileChownRequest, PutFileChownResponse,
+        S3TransferRequest, S3TransferResponse, UploadFileResponse,
     },
 };
 
@@ -177,15 +181,18 @@ pub async fn post_filesystem_transfer_upload(
     Ok(model)
 }
 
-pub async fn get_filesystem_ops_download(client: &FirecrestClient, system_name: &str, path: PathBuf) -> Result<String> {
+pub async fn get_filesystem_ops_download(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+) -> Result<Vec<u8>> {
     let file_path = path.as_os_str().to_str().ok_or(eyre!("couldn't cast path to string"))?;
-    let response = client
-        .get(
+    client
+        .get_bytes(
             format!("filesystem/{system_name}/ops/download").as_str(),
             Some(vec![("path", file_path)]),
         )
-        .await?;
-    Ok(response.as_str().to_string())
+        .await
 }
 
 pub async fn post_filesystem_transfer_download(
@@ -245,3 +252,297 @@ pub async fn get_filesystem_ops_checksum(
 
     Ok(model.output.map(|o| o.checksum))
 }
+
+pub async fn get_filesystem_ops_head(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+    lines: Option<usize>,
+    bytes: Option<usize>,
+) -> Result<GetFileHeadResponse> {
+    let path = path.as_os_str().to_str().ok_or(eyre!("couldn't cast path to string"))?;
+    let lines = lines.map(|l| l.to_string());
+    let bytes = bytes.map(|b| b.to_string());
+    let mut params = vec![("path", path)];
+    if let Some(lines) = lines.as_ref() {
+        params.push(("lines", lines));
+    }
+    if let Some(bytes) = bytes.as_ref() {
+        params.push(("bytes", bytes));
+    }
+    let response = client
+        .get(format!("filesystem/{system_name}/ops/head").as_str(), Some(params))
+        .await?;
+    let model: GetFileHeadResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn get_filesystem_ops_view(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+    size: usize,
+    offset: usize,
+) -> Result<GetViewFileResponse> {
+    let path = path.as_os_str().to_str().ok_or(eyre!("couldn't cast path to string"))?;
+    let response = client
+        .get(
+            format!("filesystem/{system_name}/ops/view").as_str(),
+            Some(vec![
+                ("path", path),
+                ("size", &size.to_string()),
+                ("offset", &offset.to_string()),
+            ]),
+        )
+        .await?;
+    let model: GetViewFileResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn get_filesystem_ops_file(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+) -> Result<GetFileTypeResponse> {
+    let path = path.as_os_str().to_str().ok_or(eyre!("couldn't cast path to string"))?;
+    let response = client
+        .get(
+            format!("filesystem/{system_name}/ops/file").as_str(),
+            Some(vec![("path", path)]),
+        )
+        .await?;
+    let model: GetFileTypeResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn post_filesystem_ops_symlink(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+    link_path: PathBuf,
+) -> Result<PostFileSymlinkResponse> {
+    let body = PostFileSymlinkRequest {
+        source_path: Some(path_to_string(path)?),
+        link_path: path_to_string(link_path)?,
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let response = client
+        .post(
+            format!("filesystem/{system_name}/ops/symlink").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    let model: PostFileSymlinkResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn put_filesystem_ops_chown(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+    owner: Option<String>,
+    group: Option<String>,
+) -> Result<PutFileChownResponse> {
+    let body = PutFileChownRequest {
+        source_path: Some(path_to_string(path)?),
+        owner,
+        group,
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let response = client
+        .put(format!("filesystem/{system_name}/ops/chown").as_str(), body_json, None)
+        .await?;
+    let model: PutFileChownResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn post_filesystem_ops_compress(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+    target_path: PathBuf,
+    compression: Option<CompressionType>,
+) -> Result<()> {
+    let body = PostCompressRequest {
+        source_path: Some(path_to_string(path)?),
+        target_path: path_to_string(target_path)?,
+        compression: compression.map(compression_name),
+        ..Default::default()
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let _ = client
+        .post(
+            format!("filesystem/{system_name}/ops/compress").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn post_filesystem_ops_extract(
+    client: &FirecrestClient,
+    system_name: &str,
+    path: PathBuf,
+    target_path: PathBuf,
+    compression: Option<CompressionType>,
+) -> Result<()> {
+    let body = PostExtractRequest {
+        source_path: Some(path_to_string(path)?),
+        target_path: path_to_string(target_path)?,
+        compression: compression.map(compression_name),
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let _ = client
+        .post(
+            format!("filesystem/{system_name}/ops/extract").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn post_filesystem_transfer_cp(
+    client: &FirecrestClient,
+    system_name: &str,
+    account: Option<String>,
+    path: PathBuf,
+    target_path: PathBuf,
+) -> Result<CopyResponse> {
+    let body = CopyRequest {
+        account,
+        source_path: Some(path_to_string(path)?),
+        target_path: path_to_string(target_path)?,
+        ..Default::default()
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let response = client
+        .post(
+            format!("filesystem/{system_name}/transfer/cp").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    let model: CopyResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn post_filesystem_transfer_mv(
+    client: &FirecrestClient,
+    system_name: &str,
+    account: Option<String>,
+    path: PathBuf,
+    target_path: PathBuf,
+) -> Result<MoveResponse> {
+    let body = MoveRequest {
+        account,
+        source_path: Some(path_to_string(path)?),
+        target_path: path_to_string(target_path)?,
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let response = client
+        .post(
+            format!("filesystem/{system_name}/transfer/mv").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    let model: MoveResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn delete_filesystem_transfer_rm(
+    client: &FirecrestClient,
+    system_name: &str,
+    account: Option<String>,
+    path: PathBuf,
+) -> Result<DeleteResponse> {
+    let path = path.as_os_str().to_str().ok_or(eyre!("couldn't cast path to string"))?;
+    let mut params = vec![("path", path)];
+    if let Some(account) = account.as_ref() {
+        params.push(("account", account));
+    }
+    let response = client
+        .delete(format!("filesystem/{system_name}/transfer/rm").as_str(), Some(params))
+        .await?;
+    let model: DeleteResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn post_filesystem_transfer_compress(
+    client: &FirecrestClient,
+    system_name: &str,
+    account: Option<String>,
+    path: PathBuf,
+    target_path: PathBuf,
+    compression: Option<CompressionType>,
+) -> Result<CompressResponse> {
+    let body = CompressRequest {
+        account,
+        source_path: Some(path_to_string(path)?),
+        target_path: path_to_string(target_path)?,
+        compression: compression.map(compression_name),
+        ..Default::default()
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let response = client
+        .post(
+            format!("filesystem/{system_name}/transfer/compress").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    let model: CompressResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+pub async fn post_filesystem_transfer_extract(
+    client: &FirecrestClient,
+    system_name: &str,
+    account: Option<String>,
+    path: PathBuf,
+    target_path: PathBuf,
+    compression: Option<CompressionType>,
+) -> Result<ExtractResponse> {
+    let body = ExtractRequest {
+        account,
+        source_path: Some(path_to_string(path)?),
+        target_path: path_to_string(target_path)?,
+        compression: compression.map(compression_name),
+    };
+    let body_json = serde_json::to_string(&body)?;
+    let response = client
+        .post(
+            format!("filesystem/{system_name}/transfer/extract").as_str(),
+            body_json,
+            None,
+            None,
+        )
+        .await?;
+    let model: ExtractResponse = serde_json::from_str(response.as_str())?;
+    Ok(model)
+}
+
+fn path_to_string(path: PathBuf) -> Result<String> {
+    path.into_os_string()
+        .into_string()
+        .map_err(|_| eyre!("couldn't convert path"))
+}
+
+fn compression_name(compression: CompressionType) -> String {
+    match compression {
+        CompressionType::None => "none",
+        CompressionType::Bzip2 => "bzip2",
+        CompressionType::Gzip => "gzip",
+        CompressionType::Xz => "xz",
+    }
+    .to_owned()
+}